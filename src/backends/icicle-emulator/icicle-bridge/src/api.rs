@@ -0,0 +1,420 @@
+// Safe interface for Rust code embedding the emulator directly instead of going through
+// the C functions in lib.rs. Failures are reported as `Result`s rather than 0/1 values,
+// hooks are plain closures. Everything not covered here is reachable through `inner`.
+
+use crate::icicle::{IcicleEmulator, StopHandle, StopReason, SyscallAction, VmConfig};
+use crate::registers::X86Register;
+
+pub use crate::icicle::{
+    FOREIGN_EXEC as PERM_EXEC, FOREIGN_READ as PERM_READ, FOREIGN_WRITE as PERM_WRITE,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// The memory range is not mapped or not accessible.
+    MemoryAccess,
+    /// The mapping request was rejected (overlap, W^X policy or allocation failure).
+    Mapping,
+    /// The value does not fit the register.
+    RegisterSize,
+    /// The SLEIGH specification does not define the register, e.g. ZMM, K or XMM16-31.
+    UnavailableRegister,
+    /// The hook could not be installed.
+    Hook,
+}
+
+/// Identifier of an installed hook, used to remove it again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HookId(u32);
+
+impl HookId {
+    fn from_raw(id: u32) -> Result<Self, Error> {
+        if id == 0 {
+            return Err(Error::Hook);
+        }
+
+        return Ok(HookId(id));
+    }
+
+    /// The qualified id used by the C interface.
+    pub fn raw(&self) -> u32 {
+        return self.0;
+    }
+}
+
+fn check(success: bool, error: Error) -> Result<(), Error> {
+    if success {
+        return Ok(());
+    }
+
+    return Err(error);
+}
+
+/// An x86-64 emulator instance. Not thread safe, use it from the thread that created it.
+pub struct Emulator {
+    inner: IcicleEmulator,
+}
+
+impl Emulator {
+    pub fn new() -> Self {
+        Self {
+            inner: IcicleEmulator::new(),
+        }
+    }
+
+    /// Creates an emulator with the `CREATE_*` flags of the C interface.
+    pub fn with_flags(flags: u32) -> Self {
+        Self {
+            inner: IcicleEmulator::with_flags(flags),
+        }
+    }
+
+    /// Creates an emulator with explicit JIT, optimization and tracking options.
+    pub fn with_config(config: &VmConfig) -> Self {
+        Self {
+            inner: IcicleEmulator::with_config(config),
+        }
+    }
+
+    /// Full emulator interface, including everything exposed to C.
+    pub fn inner(&mut self) -> &mut IcicleEmulator {
+        return &mut self.inner;
+    }
+
+    /// Runs at most `count` instructions, 0 runs until another stop condition.
+    pub fn run(&mut self, count: u64) -> StopReason {
+        return self.inner.start(count);
+    }
+
+    /// Makes a run in progress on this thread stop before the next instruction.
+    pub fn stop(&mut self) {
+        self.inner.stop();
+    }
+
+    /// Handle that can be sent to other threads to stop runs of this emulator.
+    pub fn stop_handle(&self) -> StopHandle {
+        return self.inner.stop_handle();
+    }
+
+    /// Unmaps all memory and resets the registers and instruction count, optionally
+    /// removing all hooks. Faster than creating a new emulator.
+    pub fn reset(&mut self, remove_hooks: bool) {
+        self.inner.reset(remove_hooks);
+    }
+
+    /// Maps zeroed memory with a combination of `PERM_READ`, `PERM_WRITE` and `PERM_EXEC`.
+    pub fn map_memory(&mut self, address: u64, length: u64, permissions: u8) -> Result<(), Error> {
+        return check(
+            self.inner.map_memory(address, length, permissions),
+            Error::Mapping,
+        );
+    }
+
+    pub fn unmap_memory(&mut self, address: u64, length: u64) -> Result<(), Error> {
+        return check(self.inner.unmap_memory(address, length), Error::Mapping);
+    }
+
+    pub fn protect_memory(
+        &mut self,
+        address: u64,
+        length: u64,
+        permissions: u8,
+    ) -> Result<(), Error> {
+        return check(
+            self.inner.protect_memory(address, length, permissions),
+            Error::Mapping,
+        );
+    }
+
+    /// Reads guest memory, ignoring page permissions.
+    pub fn read_memory(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
+        return check(self.inner.read_memory(address, data), Error::MemoryAccess);
+    }
+
+    /// Writes guest memory, ignoring page permissions.
+    pub fn write_memory(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
+        return check(self.inner.write_memory(address, data), Error::MemoryAccess);
+    }
+
+    /// Little-endian value of the register, zero extended to its full size.
+    pub fn read_register_bytes(&mut self, reg: X86Register) -> Result<Vec<u8>, Error> {
+        check(self.inner.has_register(reg), Error::UnavailableRegister)?;
+
+        let mut data = vec![0u8; self.inner.register_size(reg)];
+        self.inner.read_register(reg, &mut data);
+        return Ok(data);
+    }
+
+    /// Low 64 bits of the register.
+    pub fn read_register(&mut self, reg: X86Register) -> Result<u64, Error> {
+        check(self.inner.has_register(reg), Error::UnavailableRegister)?;
+
+        let mut data = [0u8; 8];
+        self.inner.read_register(reg, &mut data);
+        return Ok(u64::from_le_bytes(data));
+    }
+
+    /// Writes a little-endian value that must not be wider than the register.
+    pub fn write_register_bytes(&mut self, reg: X86Register, data: &[u8]) -> Result<(), Error> {
+        check(self.inner.has_register(reg), Error::UnavailableRegister)?;
+
+        return match self.inner.write_register_checked(reg, data) {
+            Some(_) => Ok(()),
+            None => Err(Error::RegisterSize),
+        };
+    }
+
+    /// Writes `value`, which must fit the register.
+    pub fn write_register(&mut self, reg: X86Register, value: u64) -> Result<(), Error> {
+        check(self.inner.has_register(reg), Error::UnavailableRegister)?;
+
+        let size = std::cmp::min(self.inner.register_size(reg), 8);
+        if size < 8 && (value >> (size * 8)) != 0 {
+            return Err(Error::RegisterSize);
+        }
+
+        return self.write_register_bytes(reg, &value.to_le_bytes()[..size]);
+    }
+
+    pub fn pc(&mut self) -> u64 {
+        return self.inner.get_pc();
+    }
+
+    pub fn set_pc(&mut self, pc: u64) {
+        self.inner.set_pc(pc);
+    }
+
+    /// Calls `callback` with the address of every executed instruction.
+    pub fn add_instruction_hook(
+        &mut self,
+        callback: impl Fn(u64) + 'static,
+    ) -> Result<HookId, Error> {
+        return HookId::from_raw(self.inner.add_generic_execution_hook(Box::new(callback)));
+    }
+
+    /// Calls `callback` before the instruction at `address` executes.
+    pub fn add_address_hook(
+        &mut self,
+        address: u64,
+        callback: impl Fn(u64) + 'static,
+    ) -> Result<HookId, Error> {
+        return HookId::from_raw(self.inner.add_execution_hook(address, Box::new(callback)));
+    }
+
+    /// Calls `callback` with the PC, address and value of guest reads in `[start, end)`.
+    pub fn add_read_hook(
+        &mut self,
+        start: u64,
+        end: u64,
+        callback: impl Fn(u64, u64, &[u8]) + 'static,
+    ) -> Result<HookId, Error> {
+        return HookId::from_raw(self.inner.add_read_hook(start, end, Box::new(callback)));
+    }
+
+    /// Calls `callback` with the PC, address and value of guest writes in `[start, end)`.
+    pub fn add_write_hook(
+        &mut self,
+        start: u64,
+        end: u64,
+        callback: impl Fn(u64, u64, &[u8]) + 'static,
+    ) -> Result<HookId, Error> {
+        return HookId::from_raw(self.inner.add_write_hook(start, end, Box::new(callback)));
+    }
+
+    /// The callback gets the emulator to read the arguments and write the result, the
+    /// returned action decides whether the run continues past the syscall.
+    pub fn add_syscall_hook(
+        &mut self,
+        callback: impl Fn(&mut IcicleEmulator) -> SyscallAction + 'static,
+    ) -> Result<HookId, Error> {
+        return HookId::from_raw(self.inner.add_syscall_hook(Box::new(callback)));
+    }
+
+    /// Calls `callback` with the interrupt vector raised by the guest.
+    pub fn add_interrupt_hook(
+        &mut self,
+        callback: impl Fn(i32) + 'static,
+    ) -> Result<HookId, Error> {
+        return HookId::from_raw(self.inner.add_interrupt_hook(Box::new(callback)));
+    }
+
+    /// Calls `callback` with the address, the attempted `PERM_*` access and whether the
+    /// address is unmapped. Execution continues if every violation hook returns true.
+    pub fn add_violation_hook(
+        &mut self,
+        callback: impl Fn(u64, u8, bool) -> bool + 'static,
+    ) -> Result<HookId, Error> {
+        return HookId::from_raw(self.inner.add_violation_hook(Box::new(callback)));
+    }
+
+    pub fn remove_hook(&mut self, id: HookId) {
+        self.inner.remove_hook(id.0);
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    const CODE: u64 = 0x1000;
+    const DATA: u64 = 0x2000;
+
+    // mov rax, 0x1234; mov [rbx], rax; nop
+    const PROGRAM: [u8; 11] = [
+        0x48, 0xC7, 0xC0, 0x34, 0x12, 0x00, 0x00, 0x48, 0x89, 0x03, 0x90,
+    ];
+
+    fn setup() -> Emulator {
+        let mut emulator = Emulator::new();
+        emulator
+            .map_memory(CODE, 0x1000, PERM_READ | PERM_EXEC)
+            .unwrap();
+        emulator
+            .map_memory(DATA, 0x1000, PERM_READ | PERM_WRITE)
+            .unwrap();
+        emulator.write_memory(CODE, &PROGRAM).unwrap();
+        emulator.write_register(X86Register::Rbx, DATA).unwrap();
+        emulator.set_pc(CODE);
+        return emulator;
+    }
+
+    #[test]
+    fn memory_round_trip() {
+        let mut emulator = setup();
+        emulator.write_memory(DATA + 8, &[1, 2, 3, 4]).unwrap();
+
+        let mut data = [0u8; 4];
+        emulator.read_memory(DATA + 8, &mut data).unwrap();
+        assert_eq!(data, [1, 2, 3, 4]);
+
+        assert_eq!(
+            emulator.read_memory(0x10_0000, &mut data),
+            Err(Error::MemoryAccess)
+        );
+    }
+
+    #[test]
+    fn write_register_rejects_wide_values() {
+        let mut emulator = Emulator::new();
+        assert_eq!(
+            emulator.write_register(X86Register::Eax, 1 << 32),
+            Err(Error::RegisterSize)
+        );
+
+        emulator.write_register(X86Register::Rax, 1 << 32).unwrap();
+        assert_eq!(emulator.read_register(X86Register::Rax), Ok(1 << 32));
+        assert_eq!(
+            emulator.read_register_bytes(X86Register::Eax),
+            Ok(vec![0; 4])
+        );
+    }
+
+    #[test]
+    fn run_executes_instructions() {
+        let mut emulator = setup();
+        assert_eq!(emulator.run(2), StopReason::InstructionLimit);
+        assert_eq!(emulator.read_register(X86Register::Rax), Ok(0x1234));
+        assert_eq!(emulator.pc(), CODE + 10);
+
+        let mut data = [0u8; 8];
+        emulator.read_memory(DATA, &mut data).unwrap();
+        assert_eq!(u64::from_le_bytes(data), 0x1234);
+    }
+
+    #[test]
+    fn hooks_observe_execution_and_writes() {
+        let mut emulator = setup();
+
+        let executed = Rc::new(RefCell::new(Vec::new()));
+        let executed_hook = executed.clone();
+        emulator
+            .add_instruction_hook(move |address| executed_hook.borrow_mut().push(address))
+            .unwrap();
+
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let writes_hook = writes.clone();
+        emulator
+            .add_write_hook(DATA, DATA + 0x1000, move |pc, address, value| {
+                writes_hook.borrow_mut().push((pc, address, value.to_vec()));
+            })
+            .unwrap();
+
+        emulator.run(2);
+        assert_eq!(*executed.borrow(), vec![CODE, CODE + 7]);
+        assert_eq!(
+            *writes.borrow(),
+            vec![(CODE + 7, DATA, 0x1234u64.to_le_bytes().to_vec())]
+        );
+    }
+
+    #[test]
+    fn removed_hooks_are_not_called() {
+        let mut emulator = setup();
+
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let id = emulator
+            .add_address_hook(CODE + 7, move |_| counter.set(counter.get() + 1))
+            .unwrap();
+        emulator.remove_hook(id);
+
+        emulator.run(2);
+        assert_eq!(calls.get(), 0);
+    }
+
+    #[test]
+    fn stop_handle_stops_the_next_run() {
+        let mut emulator = setup();
+        let handle = emulator.stop_handle();
+        std::thread::spawn(move || handle.request_stop())
+            .join()
+            .unwrap();
+
+        assert_eq!(emulator.run(0), StopReason::StopRequested);
+        assert_eq!(emulator.pc(), CODE);
+    }
+
+    #[test]
+    fn reset_unmaps_memory() {
+        let mut emulator = setup();
+        emulator.run(1);
+        emulator.reset(true);
+
+        let mut data = [0u8; 1];
+        assert_eq!(
+            emulator.read_memory(CODE, &mut data),
+            Err(Error::MemoryAccess)
+        );
+        assert_eq!(emulator.read_register(X86Register::Rax), Ok(0));
+    }
+
+    // The vendored specification has no AVX-512 state, so a ZMM5 round trip cannot pass.
+    #[test]
+    fn zmm_registers_are_rejected() {
+        let mut emulator = Emulator::new();
+        assert_eq!(
+            emulator.write_register_bytes(X86Register::Zmm5, &[0x55; 64]),
+            Err(Error::UnavailableRegister)
+        );
+        assert_eq!(
+            emulator.read_register_bytes(X86Register::Zmm5),
+            Err(Error::UnavailableRegister)
+        );
+
+        let mut data = [0u8; 64];
+        assert_eq!(emulator.inner.register_size(X86Register::Zmm5), 0);
+        assert_eq!(
+            emulator.inner.read_register(X86Register::Zmm5, &mut data),
+            0
+        );
+        assert_eq!(emulator.inner.write_register(X86Register::Zmm5, &data), 0);
+    }
+}
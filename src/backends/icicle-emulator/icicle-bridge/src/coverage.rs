@@ -0,0 +1,81 @@
+// AFL-style coverage bitmap, updated from the block hook without calling back into the
+// host. Every executed block increments one byte of the map:
+//
+//   COVERAGE_BLOCK  map[hash(block) & mask]
+//   COVERAGE_EDGE   map[(hash(block) ^ previous) & mask], previous = hash(block) >> 1
+//
+// Counters wrap around like in AFL. The map belongs to the caller, e.g. AFL's shared memory
+// region, and is updated in place. Its size is a power of two of at most MAX_MAP_SIZE.
+
+pub const COVERAGE_DISABLED: i32 = 0;
+pub const COVERAGE_BLOCK: i32 = 1;
+pub const COVERAGE_EDGE: i32 = 2;
+
+pub const MAX_MAP_SIZE: usize = 1 << 24;
+
+pub struct CoverageMap {
+    mode: i32,
+    map: *mut u8,
+    size: usize,
+    previous: u64,
+}
+
+// Block addresses are often aligned, mix the bits so neighbouring blocks spread out.
+fn hash_address(address: u64) -> u64 {
+    let mut value = address;
+    value ^= value >> 33;
+    value = value.wrapping_mul(0xFF51AFD7ED558CCD);
+    value ^= value >> 33;
+    return value;
+}
+
+impl CoverageMap {
+    // `map` must stay valid for reads and writes of `map_size` bytes for as long as the
+    // coverage map is in use. Its current contents are kept.
+    pub unsafe fn new(mode: i32, map: *mut u8, map_size: usize) -> Option<Self> {
+        if mode != COVERAGE_BLOCK && mode != COVERAGE_EDGE {
+            return None;
+        }
+
+        if map.is_null() || !map_size.is_power_of_two() || map_size > MAX_MAP_SIZE {
+            return None;
+        }
+
+        return Some(Self {
+            mode,
+            map,
+            size: map_size,
+            previous: 0,
+        });
+    }
+
+    pub fn on_block(&mut self, address: u64) {
+        let mask = (self.size - 1) as u64;
+        let current = hash_address(address);
+
+        let index = match self.mode {
+            COVERAGE_EDGE => {
+                let index = (current ^ self.previous) & mask;
+                self.previous = current >> 1;
+                index
+            }
+            _ => current & mask,
+        };
+
+        unsafe {
+            let counter = &mut *self.map.add(index as usize);
+            *counter = counter.wrapping_add(1);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        unsafe {
+            std::ptr::write_bytes(self.map, 0, self.size);
+        }
+        self.previous = 0;
+    }
+
+    pub fn map(&self) -> &[u8] {
+        return unsafe { std::slice::from_raw_parts(self.map, self.size) };
+    }
+}
@@ -0,0 +1,190 @@
+// Single instruction decoding for hosts that need to inspect code without executing it.
+//
+// Classification is derived from the branch hints of the lifted p-code. Syscalls and
+// privileged instructions are lifted as user-defined ops by sleigh, so they are identified
+// by their mnemonic instead.
+
+pub const MAX_INSTRUCTION_SIZE: usize = 16;
+pub const MNEMONIC_SIZE: usize = 32;
+pub const DISASM_SIZE: usize = 64;
+
+pub const FLAG_BRANCH: u32 = 1 << 0;
+pub const FLAG_CALL: u32 = 1 << 1;
+pub const FLAG_RET: u32 = 1 << 2;
+pub const FLAG_SYSCALL: u32 = 1 << 3;
+pub const FLAG_CONDITIONAL: u32 = 1 << 4;
+pub const FLAG_PRIVILEGED: u32 = 1 << 5;
+
+const SYSCALL_MNEMONICS: [&str; 3] = ["SYSCALL", "SYSENTER", "INT"];
+
+const PRIVILEGED_MNEMONICS: [&str; 19] = [
+    "HLT", "CLI", "STI", "LGDT", "LIDT", "LLDT", "LTR", "LMSW", "CLTS", "INVD", "WBINVD", "INVLPG",
+    "RDMSR", "WRMSR", "SYSRET", "SYSEXIT", "IRET", "IRETD", "IRETQ",
+];
+
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum DecodeStatus {
+    Success = 0,
+    Unreadable,
+    Invalid,
+}
+
+#[repr(C)]
+pub struct DecodedInsn {
+    pub length: u32,
+    pub flags: u32,
+    pub mnemonic: [u8; MNEMONIC_SIZE],
+}
+
+// Captured when a run stops on an unhandled exception. `length` is the number of bytes
+// that could be read at `address`, 0 if the PC itself is unmapped.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FaultInsn {
+    pub address: u64,
+    pub status: i32,
+    pub length: u32,
+    pub bytes: [u8; MAX_INSTRUCTION_SIZE],
+    pub disasm: [u8; DISASM_SIZE],
+}
+
+pub struct Instruction {
+    pub length: u64,
+    pub disasm: String,
+    pub flags: u32,
+    // Size in bytes of the largest memory read, 0 if the instruction reads no memory.
+    pub load_size: u64,
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &str {
+        return self.disasm.split_whitespace().next().unwrap_or("");
+    }
+
+    pub fn to_decoded(&self) -> DecodedInsn {
+        let mut mnemonic = [0u8; MNEMONIC_SIZE];
+        copy_string(&mut mnemonic, self.mnemonic());
+
+        return DecodedInsn {
+            length: self.length as u32,
+            flags: self.flags,
+            mnemonic,
+        };
+    }
+}
+
+// Copies `value` as a NUL-terminated string, truncating it if needed.
+pub fn copy_string(buffer: &mut [u8], value: &str) {
+    let len = std::cmp::min(value.len(), buffer.len() - 1);
+    buffer[..len].copy_from_slice(&value.as_bytes()[..len]);
+    buffer[len] = 0;
+}
+
+// Matches CR0..CR15 and DR0..DR7.
+fn is_system_register(operand: &str) -> bool {
+    if !(operand.starts_with("CR") || operand.starts_with("DR")) || operand.len() < 3 {
+        return false;
+    }
+
+    return operand[2..].bytes().all(|byte| byte.is_ascii_digit());
+}
+
+fn max_load_size(block: &pcode::Block) -> u64 {
+    return block
+        .instructions
+        .iter()
+        .filter(|statement| matches!(statement.op, pcode::Op::Load(_)))
+        .map(|statement| statement.output.size as u64)
+        .max()
+        .unwrap_or(0);
+}
+
+fn classify(block: &pcode::Block) -> u32 {
+    let mut flags = 0;
+
+    for statement in block.instructions.iter() {
+        flags |= match statement.op {
+            pcode::Op::Branch(pcode::BranchHint::Jump) => FLAG_BRANCH,
+            pcode::Op::Branch(pcode::BranchHint::Call) => FLAG_BRANCH | FLAG_CALL,
+            pcode::Op::Branch(pcode::BranchHint::Return) => FLAG_BRANCH | FLAG_RET,
+            _ => continue,
+        };
+
+        // The first input of a branch is its condition, constant for unconditional ones.
+        if !matches!(statement.inputs.first(), pcode::Value::Const(..)) {
+            flags |= FLAG_CONDITIONAL;
+        }
+    }
+
+    return flags;
+}
+
+pub struct Decoder {
+    decoder: sleigh_runtime::Decoder,
+    lifter: sleigh_runtime::Lifter,
+    instruction: sleigh_runtime::Instruction,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            decoder: sleigh_runtime::Decoder::new(),
+            lifter: sleigh_runtime::Lifter::new(),
+            instruction: sleigh_runtime::Instruction::default(),
+        }
+    }
+
+    pub fn decode(
+        &mut self,
+        sleigh: &sleigh_runtime::SleighData,
+        context: u64,
+        address: u64,
+        bytes: &[u8],
+    ) -> Option<Instruction> {
+        self.decoder.global_context = context;
+        self.decoder.set_inst(address, bytes);
+        sleigh.decode_into(&mut self.decoder, &mut self.instruction)?;
+
+        let disasm = sleigh.disasm(&self.instruction)?;
+        let block = self.lifter.lift(sleigh, &self.instruction).ok()?;
+
+        let mut instruction = Instruction {
+            length: self.instruction.num_bytes(),
+            disasm,
+            flags: classify(block),
+            load_size: max_load_size(block),
+        };
+
+        if SYSCALL_MNEMONICS.contains(&instruction.mnemonic()) {
+            instruction.flags |= FLAG_SYSCALL;
+        }
+
+        // Control register and debug register moves share the MOV mnemonic.
+        let is_system_move = instruction.mnemonic() == "MOV"
+            && instruction.disasm.split([' ', ',']).any(is_system_register);
+
+        if PRIVILEGED_MNEMONICS.contains(&instruction.mnemonic()) || is_system_move {
+            instruction.flags |= FLAG_PRIVILEGED;
+        }
+
+        return Some(instruction);
+    }
+}
+
+// True for REP/REPNE prefixed string instructions (MOVS, CMPS, STOS, LODS, SCAS, INS, OUTS),
+// which execute one iteration per instruction count.
+pub fn is_rep_string(bytes: &[u8]) -> bool {
+    let mut has_rep = false;
+
+    for byte in bytes {
+        match byte {
+            0xF2 | 0xF3 => has_rep = true,
+            0xF0 | 0x2E | 0x36 | 0x3E | 0x26 | 0x64 | 0x65 | 0x66 | 0x67 | 0x40..=0x4F => {}
+            0xA4..=0xA7 | 0xAA..=0xAF | 0x6C..=0x6F => return has_rep,
+            _ => return false,
+        }
+    }
+
+    return false;
+}
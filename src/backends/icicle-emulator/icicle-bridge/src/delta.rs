@@ -0,0 +1,98 @@
+// Delta encoding of register blobs produced by save_registers.
+//
+// Format (all integers little-endian):
+//
+//   magic      4 bytes  "ICDL"
+//   version    u32      DELTA_VERSION
+//   base_len   u64      length of the blob the delta was computed against
+//   runs       repeated until the end of the buffer:
+//                offset  u32
+//                length  u32
+//                bytes   `length` bytes replacing base[offset..offset + length]
+//
+// Neighbouring changes closer than MERGE_GAP bytes are merged into one run.
+
+const DELTA_MAGIC: &[u8; 4] = b"ICDL";
+const DELTA_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 16;
+const RUN_HEADER_SIZE: usize = 8;
+const MERGE_GAP: usize = 8;
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    return u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+}
+
+fn push_run(delta: &mut Vec<u8>, current: &[u8], start: usize, end: usize) {
+    delta.extend_from_slice(&(start as u32).to_le_bytes());
+    delta.extend_from_slice(&((end - start) as u32).to_le_bytes());
+    delta.extend_from_slice(&current[start..end]);
+}
+
+pub fn encode(base: &[u8], current: &[u8]) -> Option<Vec<u8>> {
+    if base.len() != current.len() || base.len() > u32::MAX as usize {
+        return None;
+    }
+
+    let mut delta = Vec::new();
+    delta.extend_from_slice(DELTA_MAGIC);
+    delta.extend_from_slice(&DELTA_VERSION.to_le_bytes());
+    delta.extend_from_slice(&(base.len() as u64).to_le_bytes());
+
+    let mut run: Option<(usize, usize)> = None;
+
+    for (index, (old, new)) in base.iter().zip(current.iter()).enumerate() {
+        if old == new {
+            continue;
+        }
+
+        run = match run {
+            Some((start, end)) if index - end < MERGE_GAP => Some((start, index + 1)),
+            Some((start, end)) => {
+                push_run(&mut delta, current, start, end);
+                Some((index, index + 1))
+            }
+            None => Some((index, index + 1)),
+        };
+    }
+
+    if let Some((start, end)) = run {
+        push_run(&mut delta, current, start, end);
+    }
+
+    return Some(delta);
+}
+
+pub fn apply(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    if delta.len() < HEADER_SIZE || &delta[..4] != DELTA_MAGIC {
+        return None;
+    }
+
+    let version = read_u32(delta, 4);
+    let base_len = u64::from_le_bytes(delta[8..16].try_into().unwrap());
+
+    if version != DELTA_VERSION || base_len != base.len() as u64 {
+        return None;
+    }
+
+    let mut result = base.to_vec();
+    let mut position = HEADER_SIZE;
+
+    while position < delta.len() {
+        if delta.len() - position < RUN_HEADER_SIZE {
+            return None;
+        }
+
+        let offset = read_u32(delta, position) as usize;
+        let length = read_u32(delta, position + 4) as usize;
+        position += RUN_HEADER_SIZE;
+
+        if delta.len() - position < length || offset + length > result.len() {
+            return None;
+        }
+
+        result[offset..offset + length].copy_from_slice(&delta[position..position + length]);
+        position += length;
+    }
+
+    return Some(result);
+}
@@ -0,0 +1,203 @@
+// Decoded views of the x87 control/status/tag words and MXCSR.
+// Boolean fields are exposed as u8 (0 or 1) to keep the structs safe to fill from C.
+// Rounding control: 0 = nearest, 1 = down, 2 = up, 3 = toward zero.
+// Tags: 0 = valid, 1 = zero, 2 = special, 3 = empty.
+
+fn bit(value: u32, index: u32) -> u8 {
+    return ((value >> index) & 1) as u8;
+}
+
+fn set_bit(value: &mut u32, index: u32, enabled: u8) {
+    if enabled != 0 {
+        *value |= 1 << index;
+    } else {
+        *value &= !(1 << index);
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct FpuExceptions {
+    pub invalid: u8,
+    pub denormal: u8,
+    pub zero_divide: u8,
+    pub overflow: u8,
+    pub underflow: u8,
+    pub precision: u8,
+}
+
+impl FpuExceptions {
+    // Decodes the six exception bits starting at `shift`, in the IE/DE/ZE/OE/UE/PE order.
+    fn decode(value: u32, shift: u32) -> Self {
+        Self {
+            invalid: bit(value, shift),
+            denormal: bit(value, shift + 1),
+            zero_divide: bit(value, shift + 2),
+            overflow: bit(value, shift + 3),
+            underflow: bit(value, shift + 4),
+            precision: bit(value, shift + 5),
+        }
+    }
+
+    fn encode(&self, value: &mut u32, shift: u32) {
+        set_bit(value, shift, self.invalid);
+        set_bit(value, shift + 1, self.denormal);
+        set_bit(value, shift + 2, self.zero_divide);
+        set_bit(value, shift + 3, self.overflow);
+        set_bit(value, shift + 4, self.underflow);
+        set_bit(value, shift + 5, self.precision);
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct FpuControlWord {
+    pub exception_masks: FpuExceptions,
+    pub precision_control: u8,
+    pub rounding_control: u8,
+    pub infinity_control: u8,
+}
+
+impl FpuControlWord {
+    pub fn decode(raw: u16) -> Self {
+        let value: u32 = raw.into();
+
+        Self {
+            exception_masks: FpuExceptions::decode(value, 0),
+            precision_control: ((value >> 8) & 3) as u8,
+            rounding_control: ((value >> 10) & 3) as u8,
+            infinity_control: bit(value, 12),
+        }
+    }
+
+    pub fn encode(&self, raw: u16) -> u16 {
+        let mut value: u32 = raw.into();
+        self.exception_masks.encode(&mut value, 0);
+
+        value &= !(0xF << 8);
+        value |= ((self.precision_control & 3) as u32) << 8;
+        value |= ((self.rounding_control & 3) as u32) << 10;
+        set_bit(&mut value, 12, self.infinity_control);
+
+        return value as u16;
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct FpuStatusWord {
+    pub exceptions: FpuExceptions,
+    pub stack_fault: u8,
+    pub error_summary: u8,
+    pub c0: u8,
+    pub c1: u8,
+    pub c2: u8,
+    pub c3: u8,
+    pub top: u8,
+    pub busy: u8,
+}
+
+impl FpuStatusWord {
+    pub fn decode(raw: u16) -> Self {
+        let value: u32 = raw.into();
+
+        Self {
+            exceptions: FpuExceptions::decode(value, 0),
+            stack_fault: bit(value, 6),
+            error_summary: bit(value, 7),
+            c0: bit(value, 8),
+            c1: bit(value, 9),
+            c2: bit(value, 10),
+            top: ((value >> 11) & 7) as u8,
+            c3: bit(value, 14),
+            busy: bit(value, 15),
+        }
+    }
+
+    pub fn encode(&self) -> u16 {
+        let mut value: u32 = 0;
+        self.exceptions.encode(&mut value, 0);
+
+        set_bit(&mut value, 6, self.stack_fault);
+        set_bit(&mut value, 7, self.error_summary);
+        set_bit(&mut value, 8, self.c0);
+        set_bit(&mut value, 9, self.c1);
+        set_bit(&mut value, 10, self.c2);
+        value |= ((self.top & 7) as u32) << 11;
+        set_bit(&mut value, 14, self.c3);
+        set_bit(&mut value, 15, self.busy);
+
+        return value as u16;
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct FpuTagWord {
+    // Indexed by physical register number, not by ST(i).
+    pub tags: [u8; 8],
+}
+
+impl FpuTagWord {
+    pub fn decode(raw: u16) -> Self {
+        let mut tags = [0u8; 8];
+        for (index, tag) in tags.iter_mut().enumerate() {
+            *tag = ((raw >> (index * 2)) & 3) as u8;
+        }
+
+        Self { tags }
+    }
+
+    pub fn encode(&self) -> u16 {
+        let mut value: u16 = 0;
+        for (index, tag) in self.tags.iter().enumerate() {
+            value |= ((*tag & 3) as u16) << (index * 2);
+        }
+
+        return value;
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MxcsrRegister {
+    pub exceptions: FpuExceptions,
+    pub exception_masks: FpuExceptions,
+    pub denormals_are_zero: u8,
+    pub rounding_control: u8,
+    pub flush_to_zero: u8,
+}
+
+impl MxcsrRegister {
+    pub fn decode(value: u32) -> Self {
+        Self {
+            exceptions: FpuExceptions::decode(value, 0),
+            denormals_are_zero: bit(value, 6),
+            exception_masks: FpuExceptions::decode(value, 7),
+            rounding_control: ((value >> 13) & 3) as u8,
+            flush_to_zero: bit(value, 15),
+        }
+    }
+
+    pub fn encode(&self, raw: u32) -> u32 {
+        let mut value = raw;
+        self.exceptions.encode(&mut value, 0);
+        set_bit(&mut value, 6, self.denormals_are_zero);
+        self.exception_masks.encode(&mut value, 7);
+
+        value &= !(3 << 13);
+        value |= ((self.rounding_control & 3) as u32) << 13;
+        set_bit(&mut value, 15, self.flush_to_zero);
+
+        return value;
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct FpuState {
+    pub control: FpuControlWord,
+    pub status: FpuStatusWord,
+    pub tag: FpuTagWord,
+    pub mxcsr: MxcsrRegister,
+}
@@ -0,0 +1,658 @@
+// GDB remote serial protocol server built on gdbstub. A single connection is served on the
+// calling thread and the emulator only runs while gdb continues or steps. Supported are
+// register and memory access, continue, single step, software breakpoints and Ctrl-C.
+// Signals gdb asks to resume with are not delivered to the guest.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::net::{TcpListener, TcpStream};
+use std::num::NonZeroUsize;
+
+use gdbstub::arch::{Arch, RegId, Registers};
+use gdbstub::common::Signal;
+use gdbstub::conn::{Connection, ConnectionExt};
+use gdbstub::stub::{GdbStub, SingleThreadStopReason, run_blocking};
+use gdbstub::target::ext::base::BaseOps;
+use gdbstub::target::ext::base::single_register_access::{
+    SingleRegisterAccess, SingleRegisterAccessOps,
+};
+use gdbstub::target::ext::base::singlethread::{
+    SingleThreadBase, SingleThreadResume, SingleThreadResumeOps, SingleThreadSingleStep,
+    SingleThreadSingleStepOps,
+};
+use gdbstub::target::ext::breakpoints::{
+    Breakpoints, BreakpointsOps, SwBreakpoint, SwBreakpointOps,
+};
+use gdbstub::target::ext::target_description_xml_override::{
+    TargetDescriptionXmlOverride, TargetDescriptionXmlOverrideOps,
+};
+use gdbstub::target::{Target, TargetError, TargetResult};
+
+use crate::icicle::{ExitException, IcicleEmulator, StopReason};
+use crate::registers::X86Register;
+
+const EFAULT: u8 = 14;
+
+// Instructions executed between two checks for data from gdb, e.g. a Ctrl-C.
+const RUN_CHUNK: u64 = 0x10000;
+
+struct GdbRegister {
+    reg: X86Register,
+    name: &'static str,
+    size: usize,
+    kind: &'static str,
+}
+
+const fn gdb_register(
+    reg: X86Register,
+    name: &'static str,
+    size: usize,
+    kind: &'static str,
+) -> GdbRegister {
+    GdbRegister {
+        reg,
+        name,
+        size,
+        kind,
+    }
+}
+
+// Registers in gdb numbering. The first CORE_REGISTERS form org.gnu.gdb.i386.core, the
+// rest org.gnu.gdb.i386.sse.
+const CORE_REGISTERS: usize = 40;
+
+const REGISTERS: [GdbRegister; 57] = [
+    gdb_register(X86Register::Rax, "rax", 8, "int64"),
+    gdb_register(X86Register::Rbx, "rbx", 8, "int64"),
+    gdb_register(X86Register::Rcx, "rcx", 8, "int64"),
+    gdb_register(X86Register::Rdx, "rdx", 8, "int64"),
+    gdb_register(X86Register::Rsi, "rsi", 8, "int64"),
+    gdb_register(X86Register::Rdi, "rdi", 8, "int64"),
+    gdb_register(X86Register::Rbp, "rbp", 8, "data_ptr"),
+    gdb_register(X86Register::Rsp, "rsp", 8, "data_ptr"),
+    gdb_register(X86Register::R8, "r8", 8, "int64"),
+    gdb_register(X86Register::R9, "r9", 8, "int64"),
+    gdb_register(X86Register::R10, "r10", 8, "int64"),
+    gdb_register(X86Register::R11, "r11", 8, "int64"),
+    gdb_register(X86Register::R12, "r12", 8, "int64"),
+    gdb_register(X86Register::R13, "r13", 8, "int64"),
+    gdb_register(X86Register::R14, "r14", 8, "int64"),
+    gdb_register(X86Register::R15, "r15", 8, "int64"),
+    gdb_register(X86Register::Rip, "rip", 8, "code_ptr"),
+    gdb_register(X86Register::Eflags, "eflags", 4, "int32"),
+    gdb_register(X86Register::Cs, "cs", 4, "int32"),
+    gdb_register(X86Register::Ss, "ss", 4, "int32"),
+    gdb_register(X86Register::Ds, "ds", 4, "int32"),
+    gdb_register(X86Register::Es, "es", 4, "int32"),
+    gdb_register(X86Register::Fs, "fs", 4, "int32"),
+    gdb_register(X86Register::Gs, "gs", 4, "int32"),
+    gdb_register(X86Register::St0, "st0", 10, "i387_ext"),
+    gdb_register(X86Register::St1, "st1", 10, "i387_ext"),
+    gdb_register(X86Register::St2, "st2", 10, "i387_ext"),
+    gdb_register(X86Register::St3, "st3", 10, "i387_ext"),
+    gdb_register(X86Register::St4, "st4", 10, "i387_ext"),
+    gdb_register(X86Register::St5, "st5", 10, "i387_ext"),
+    gdb_register(X86Register::St6, "st6", 10, "i387_ext"),
+    gdb_register(X86Register::St7, "st7", 10, "i387_ext"),
+    gdb_register(X86Register::Fpcw, "fctrl", 4, "int"),
+    gdb_register(X86Register::Fpsw, "fstat", 4, "int"),
+    gdb_register(X86Register::Fptag, "ftag", 4, "int"),
+    gdb_register(X86Register::Fcs, "fiseg", 4, "int"),
+    gdb_register(X86Register::Fip, "fioff", 4, "int"),
+    gdb_register(X86Register::Fds, "foseg", 4, "int"),
+    gdb_register(X86Register::Fdp, "fooff", 4, "int"),
+    gdb_register(X86Register::Fop, "fop", 4, "int"),
+    gdb_register(X86Register::Xmm0, "xmm0", 16, "uint128"),
+    gdb_register(X86Register::Xmm1, "xmm1", 16, "uint128"),
+    gdb_register(X86Register::Xmm2, "xmm2", 16, "uint128"),
+    gdb_register(X86Register::Xmm3, "xmm3", 16, "uint128"),
+    gdb_register(X86Register::Xmm4, "xmm4", 16, "uint128"),
+    gdb_register(X86Register::Xmm5, "xmm5", 16, "uint128"),
+    gdb_register(X86Register::Xmm6, "xmm6", 16, "uint128"),
+    gdb_register(X86Register::Xmm7, "xmm7", 16, "uint128"),
+    gdb_register(X86Register::Xmm8, "xmm8", 16, "uint128"),
+    gdb_register(X86Register::Xmm9, "xmm9", 16, "uint128"),
+    gdb_register(X86Register::Xmm10, "xmm10", 16, "uint128"),
+    gdb_register(X86Register::Xmm11, "xmm11", 16, "uint128"),
+    gdb_register(X86Register::Xmm12, "xmm12", 16, "uint128"),
+    gdb_register(X86Register::Xmm13, "xmm13", 16, "uint128"),
+    gdb_register(X86Register::Xmm14, "xmm14", 16, "uint128"),
+    gdb_register(X86Register::Xmm15, "xmm15", 16, "uint128"),
+    gdb_register(X86Register::Mxcsr, "mxcsr", 4, "int"),
+];
+
+// Size of the register block in the g/G packets.
+const REGISTER_BYTES: usize = register_offset(REGISTERS.len());
+
+const fn register_offset(index: usize) -> usize {
+    let mut offset = 0;
+    let mut current = 0;
+    while current < index {
+        offset += REGISTERS[current].size;
+        current += 1;
+    }
+
+    return offset;
+}
+
+fn target_description() -> String {
+    use std::fmt::Write;
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target>\
+         <architecture>i386:x86-64</architecture>",
+    );
+
+    for (index, register) in REGISTERS.iter().enumerate() {
+        if index == 0 {
+            xml.push_str("<feature name=\"org.gnu.gdb.i386.core\">");
+        } else if index == CORE_REGISTERS {
+            xml.push_str("</feature><feature name=\"org.gnu.gdb.i386.sse\">");
+        }
+
+        let _ = write!(
+            xml,
+            "<reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>",
+            register.name,
+            register.size * 8,
+            register.kind,
+            index
+        );
+    }
+
+    xml.push_str("</feature></target>");
+    return xml;
+}
+
+pub enum X86_64 {}
+
+impl Arch for X86_64 {
+    type Usize = u64;
+    type Registers = GdbRegisters;
+    type BreakpointKind = usize;
+    type RegId = GdbRegId;
+}
+
+// Register values laid out as in the g/G packets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GdbRegisters {
+    data: Vec<u8>,
+}
+
+impl Default for GdbRegisters {
+    fn default() -> Self {
+        return Self {
+            data: vec![0u8; REGISTER_BYTES],
+        };
+    }
+}
+
+impl GdbRegisters {
+    fn value(&self, index: usize) -> &[u8] {
+        let offset = register_offset(index);
+        return &self.data[offset..offset + REGISTERS[index].size];
+    }
+
+    fn value_mut(&mut self, index: usize) -> &mut [u8] {
+        let offset = register_offset(index);
+        return &mut self.data[offset..offset + REGISTERS[index].size];
+    }
+}
+
+impl Registers for GdbRegisters {
+    type ProgramCounter = u64;
+
+    fn pc(&self) -> u64 {
+        let index = REGISTERS
+            .iter()
+            .position(|register| register.reg == X86Register::Rip)
+            .unwrap_or(0);
+
+        let mut pc = [0u8; 8];
+        pc.copy_from_slice(self.value(index));
+        return u64::from_le_bytes(pc);
+    }
+
+    fn gdb_serialize(&self, mut write_byte: impl FnMut(Option<u8>)) {
+        for byte in self.data.iter() {
+            write_byte(Some(*byte));
+        }
+    }
+
+    fn gdb_deserialize(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        if bytes.len() != self.data.len() {
+            return Err(());
+        }
+
+        self.data.copy_from_slice(bytes);
+        return Ok(());
+    }
+}
+
+// Index into REGISTERS, which is the gdb register number.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GdbRegId(usize);
+
+impl RegId for GdbRegId {
+    fn from_raw_id(id: usize) -> Option<(Self, Option<NonZeroUsize>)> {
+        let register = REGISTERS.get(id)?;
+        return Some((GdbRegId(id), NonZeroUsize::new(register.size)));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Resume {
+    Continue,
+    Step,
+}
+
+struct GdbTarget<'a> {
+    emulator: &'a mut IcicleEmulator,
+    breakpoints: HashMap<u64, u32>,
+    resume: Option<Resume>,
+}
+
+fn exception_signal(exception: ExitException) -> Signal {
+    return match exception {
+        ExitException::ReadUnmapped
+        | ExitException::WriteUnmapped
+        | ExitException::ReadPermission
+        | ExitException::WritePermission
+        | ExitException::ExecViolation
+        | ExitException::Unaligned => Signal::SIGSEGV,
+        ExitException::InvalidInstruction | ExitException::Unimplemented => Signal::SIGILL,
+        ExitException::DivisionError => Signal::SIGFPE,
+        _ => Signal::SIGTRAP,
+    };
+}
+
+impl GdbTarget<'_> {
+    fn read_gdb_register(&mut self, index: usize, value: &mut [u8]) {
+        let mut data = [0u8; 64];
+        self.emulator.read_register(REGISTERS[index].reg, &mut data);
+        value.copy_from_slice(&data[..value.len()]);
+    }
+
+    fn write_gdb_register(&mut self, index: usize, value: &[u8]) {
+        let register = REGISTERS[index].reg;
+        let size = std::cmp::min(value.len(), self.emulator.register_size(register));
+        if size != 0 {
+            self.emulator.write_register(register, &value[..size]);
+        }
+    }
+
+    fn stop_reason(&self, reason: StopReason) -> SingleThreadStopReason<u64> {
+        return match reason {
+            StopReason::Breakpoint => SingleThreadStopReason::SwBreak(()),
+            StopReason::Exception => SingleThreadStopReason::Signal(exception_signal(
+                self.emulator.get_exit_exception().0,
+            )),
+            _ => SingleThreadStopReason::Signal(Signal::SIGTRAP),
+        };
+    }
+
+    // Executes the current instruction with a breakpoint on it lifted, so resuming from a
+    // breakpoint does not hit it again. A completed step is StopReason::InstructionLimit.
+    fn step_over_breakpoint(&mut self) -> StopReason {
+        let pc = self.pc();
+
+        let installed = self.breakpoints.remove(&pc);
+        if let Some(id) = installed {
+            self.emulator.remove_breakpoint(id);
+        }
+
+        let reason = self.emulator.step(0);
+
+        if installed.is_some() {
+            let id = self.emulator.add_breakpoint(pc, 0);
+            self.breakpoints.insert(pc, id);
+        }
+
+        return reason;
+    }
+
+    fn pc(&mut self) -> u64 {
+        let mut pc = [0u8; 8];
+        self.emulator.read_register(X86Register::Rip, &mut pc);
+        return u64::from_le_bytes(pc);
+    }
+
+    fn has_breakpoint_at_pc(&mut self) -> bool {
+        let pc = self.pc();
+        return self.breakpoints.contains_key(&pc);
+    }
+}
+
+impl Target for GdbTarget<'_> {
+    type Arch = X86_64;
+    type Error = &'static str;
+
+    fn base_ops(&mut self) -> BaseOps<'_, X86_64, Self::Error> {
+        return BaseOps::SingleThread(self);
+    }
+
+    fn support_breakpoints(&mut self) -> Option<BreakpointsOps<'_, Self>> {
+        return Some(self);
+    }
+
+    fn support_target_description_xml_override(
+        &mut self,
+    ) -> Option<TargetDescriptionXmlOverrideOps<'_, Self>> {
+        return Some(self);
+    }
+}
+
+impl SingleThreadBase for GdbTarget<'_> {
+    fn read_registers(&mut self, registers: &mut GdbRegisters) -> TargetResult<(), Self> {
+        for index in 0..REGISTERS.len() {
+            self.read_gdb_register(index, registers.value_mut(index));
+        }
+
+        return Ok(());
+    }
+
+    fn write_registers(&mut self, registers: &GdbRegisters) -> TargetResult<(), Self> {
+        for index in 0..REGISTERS.len() {
+            self.write_gdb_register(index, registers.value(index));
+        }
+
+        return Ok(());
+    }
+
+    fn support_single_register_access(&mut self) -> Option<SingleRegisterAccessOps<'_, (), Self>> {
+        return Some(self);
+    }
+
+    // Partial reads are reported as far as the memory is readable.
+    fn read_addrs(&mut self, start_addr: u64, data: &mut [u8]) -> TargetResult<usize, Self> {
+        if self.emulator.read_memory(start_addr, data) {
+            return Ok(data.len());
+        }
+
+        let readable = (0..data.len())
+            .take_while(|offset| {
+                let address = start_addr.wrapping_add(*offset as u64);
+                return self.emulator.read_memory(address, &mut [0u8; 1]);
+            })
+            .count();
+
+        if readable == 0 || !self.emulator.read_memory(start_addr, &mut data[..readable]) {
+            return Err(TargetError::Errno(EFAULT));
+        }
+
+        return Ok(readable);
+    }
+
+    fn write_addrs(&mut self, start_addr: u64, data: &[u8]) -> TargetResult<(), Self> {
+        if !self.emulator.write_memory(start_addr, data) {
+            return Err(TargetError::Errno(EFAULT));
+        }
+
+        return Ok(());
+    }
+
+    fn support_resume(&mut self) -> Option<SingleThreadResumeOps<'_, Self>> {
+        return Some(self);
+    }
+}
+
+impl SingleRegisterAccess<()> for GdbTarget<'_> {
+    fn read_register(
+        &mut self,
+        _tid: (),
+        reg_id: GdbRegId,
+        buf: &mut [u8],
+    ) -> TargetResult<usize, Self> {
+        let size = REGISTERS[reg_id.0].size;
+        if buf.len() < size {
+            return Err(TargetError::NonFatal);
+        }
+
+        self.read_gdb_register(reg_id.0, &mut buf[..size]);
+        return Ok(size);
+    }
+
+    fn write_register(
+        &mut self,
+        _tid: (),
+        reg_id: GdbRegId,
+        value: &[u8],
+    ) -> TargetResult<(), Self> {
+        self.write_gdb_register(reg_id.0, value);
+        return Ok(());
+    }
+}
+
+impl SingleThreadResume for GdbTarget<'_> {
+    fn resume(&mut self, _signal: Option<Signal>) -> Result<(), Self::Error> {
+        self.resume = Some(Resume::Continue);
+        return Ok(());
+    }
+
+    fn support_single_step(&mut self) -> Option<SingleThreadSingleStepOps<'_, Self>> {
+        return Some(self);
+    }
+}
+
+impl SingleThreadSingleStep for GdbTarget<'_> {
+    fn step(&mut self, _signal: Option<Signal>) -> Result<(), Self::Error> {
+        self.resume = Some(Resume::Step);
+        return Ok(());
+    }
+}
+
+impl Breakpoints for GdbTarget<'_> {
+    fn support_sw_breakpoint(&mut self) -> Option<SwBreakpointOps<'_, Self>> {
+        return Some(self);
+    }
+}
+
+impl SwBreakpoint for GdbTarget<'_> {
+    fn add_sw_breakpoint(&mut self, addr: u64, _kind: usize) -> TargetResult<bool, Self> {
+        if !self.breakpoints.contains_key(&addr) {
+            let id = self.emulator.add_breakpoint(addr, 0);
+            self.breakpoints.insert(addr, id);
+        }
+
+        return Ok(true);
+    }
+
+    fn remove_sw_breakpoint(&mut self, addr: u64, _kind: usize) -> TargetResult<bool, Self> {
+        return match self.breakpoints.remove(&addr) {
+            Some(id) => Ok(self.emulator.remove_breakpoint(id)),
+            None => Ok(false),
+        };
+    }
+}
+
+impl TargetDescriptionXmlOverride for GdbTarget<'_> {
+    fn target_description_xml(
+        &self,
+        annex: &[u8],
+        offset: u64,
+        length: usize,
+        buf: &mut [u8],
+    ) -> TargetResult<usize, Self> {
+        if annex != b"target.xml" {
+            return Err(TargetError::NonFatal);
+        }
+
+        let xml = target_description();
+        let start = std::cmp::min(offset as usize, xml.len());
+        let end = std::cmp::min(start.saturating_add(length), xml.len());
+        let size = std::cmp::min(end - start, buf.len());
+
+        buf[..size].copy_from_slice(&xml.as_bytes()[start..start + size]);
+        return Ok(size);
+    }
+}
+
+struct EventLoop<'a> {
+    _target: PhantomData<GdbTarget<'a>>,
+}
+
+type WaitResult<'a> = Result<
+    run_blocking::Event<SingleThreadStopReason<u64>>,
+    run_blocking::WaitForStopReasonError<&'static str, <TcpStream as Connection>::Error>,
+>;
+
+impl<'a> run_blocking::BlockingEventLoop for EventLoop<'a> {
+    type Target = GdbTarget<'a>;
+    type Connection = TcpStream;
+    type StopReason = SingleThreadStopReason<u64>;
+
+    // Continuing runs in RUN_CHUNK pieces and returns to gdbstub as soon as gdb sent
+    // anything. The pieces bypass the limit policy, only the caller's own runs use it.
+    fn wait_for_stop_reason(target: &mut GdbTarget<'a>, conn: &mut TcpStream) -> WaitResult<'a> {
+        let resume = target.resume.take().unwrap_or(Resume::Continue);
+
+        if resume == Resume::Step || target.has_breakpoint_at_pc() {
+            let reason = target.step_over_breakpoint();
+
+            if reason != StopReason::InstructionLimit {
+                return Ok(run_blocking::Event::TargetStopped(
+                    target.stop_reason(reason),
+                ));
+            }
+
+            if resume == Resume::Step {
+                return Ok(run_blocking::Event::TargetStopped(
+                    SingleThreadStopReason::DoneStep,
+                ));
+            }
+        }
+
+        loop {
+            let pending = conn
+                .peek()
+                .map_err(run_blocking::WaitForStopReasonError::Connection)?;
+
+            if pending.is_some() {
+                let byte = conn
+                    .read()
+                    .map_err(run_blocking::WaitForStopReasonError::Connection)?;
+                return Ok(run_blocking::Event::IncomingData(byte));
+            }
+
+            let reason = target.emulator.start_ignoring_limit_policy(RUN_CHUNK);
+            if reason != StopReason::InstructionLimit {
+                return Ok(run_blocking::Event::TargetStopped(
+                    target.stop_reason(reason),
+                ));
+            }
+        }
+    }
+
+    fn on_interrupt(
+        _target: &mut GdbTarget<'a>,
+    ) -> Result<Option<SingleThreadStopReason<u64>>, &'static str> {
+        return Ok(Some(SingleThreadStopReason::Signal(Signal::SIGINT)));
+    }
+}
+
+// Waits for gdb to connect to 127.0.0.1:`port` and serves it until gdb detaches, kills the
+// target or disconnects. Returns false if listening or accepting failed.
+pub fn serve(emulator: &mut IcicleEmulator, port: u16) -> bool {
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(_) => return false,
+    };
+
+    let stream = match listener.accept() {
+        Ok((stream, _)) => stream,
+        Err(_) => return false,
+    };
+
+    let mut target = GdbTarget {
+        emulator,
+        breakpoints: HashMap::new(),
+        resume: None,
+    };
+
+    let _ = GdbStub::new(stream).run_blocking::<EventLoop>(&mut target);
+
+    for (_address, id) in std::mem::take(&mut target.breakpoints) {
+        target.emulator.remove_breakpoint(id);
+    }
+
+    return true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_description_lists_every_register() {
+        let xml = target_description();
+
+        assert!(xml.starts_with("<?xml version=\"1.0\"?>"));
+        assert!(xml.ends_with("</feature></target>"));
+        assert_eq!(xml.matches("<reg ").count(), REGISTERS.len());
+        assert_eq!(xml.matches("<feature ").count(), 2);
+
+        assert!(xml.contains("<reg name=\"rip\" bitsize=\"64\" type=\"code_ptr\" regnum=\"16\"/>"));
+        assert!(
+            xml.contains(
+                "<feature name=\"org.gnu.gdb.i386.sse\"><reg name=\"xmm0\" bitsize=\"128\""
+            )
+        );
+        assert!(xml.contains("<reg name=\"mxcsr\" bitsize=\"32\" type=\"int\" regnum=\"56\"/>"));
+    }
+
+    #[test]
+    fn register_ids_follow_the_register_table() {
+        let (id, size) = GdbRegId::from_raw_id(16).unwrap();
+        assert_eq!(id, GdbRegId(16));
+        assert_eq!(size, NonZeroUsize::new(8));
+
+        let (_, size) = GdbRegId::from_raw_id(24).unwrap();
+        assert_eq!(size, NonZeroUsize::new(10));
+
+        assert!(GdbRegId::from_raw_id(REGISTERS.len()).is_none());
+    }
+
+    #[test]
+    fn register_blocks_round_trip() {
+        assert_eq!(register_offset(16), 16 * 8);
+        assert_eq!(
+            REGISTER_BYTES,
+            17 * 8 + 7 * 4 + 8 * 10 + 8 * 4 + 16 * 16 + 4
+        );
+
+        let bytes: Vec<u8> = (0..REGISTER_BYTES).map(|index| index as u8).collect();
+
+        let mut registers = GdbRegisters::default();
+        assert!(registers.gdb_deserialize(&bytes[1..]).is_err());
+        assert!(registers.gdb_deserialize(&bytes).is_ok());
+
+        let mut pc = [0u8; 8];
+        pc.copy_from_slice(&bytes[16 * 8..17 * 8]);
+        assert_eq!(registers.pc(), u64::from_le_bytes(pc));
+
+        let mut serialized = Vec::new();
+        registers.gdb_serialize(|byte| serialized.push(byte.unwrap()));
+        assert_eq!(serialized, bytes);
+    }
+
+    #[test]
+    fn exceptions_map_to_signals() {
+        assert_eq!(
+            exception_signal(ExitException::ReadUnmapped),
+            Signal::SIGSEGV
+        );
+        assert_eq!(
+            exception_signal(ExitException::ExecViolation),
+            Signal::SIGSEGV
+        );
+        assert_eq!(
+            exception_signal(ExitException::InvalidInstruction),
+            Signal::SIGILL
+        );
+        assert_eq!(
+            exception_signal(ExitException::DivisionError),
+            Signal::SIGFPE
+        );
+        assert_eq!(exception_signal(ExitException::None), Signal::SIGTRAP);
+    }
+}
@@ -1,25 +1,100 @@
 use icicle_cpu::ExceptionCode;
 use icicle_cpu::ValueSource;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
-
+use std::{cell::Cell, cell::RefCell, collections::BTreeMap, collections::HashMap, rc::Rc};
+
+use crate::coverage;
+use crate::decode;
+use crate::delta;
+use crate::fpu;
+use crate::mmio_log;
+use crate::msr;
+use crate::paging;
+use crate::port;
+use crate::regions;
 use crate::registers;
+use crate::signal;
+use crate::stack_tracker;
+use crate::timer;
+use crate::trace;
+use crate::vector;
+use crate::watchpoint;
+use crate::xsave;
+
+pub const CREATE_SHADOW_STACK: u32 = 1 << 0;
+
+pub const STEP_OVER_REP: u32 = 1 << 0;
+
+pub const BREAKPOINT_TEMPORARY: u32 = 1 << 0;
+
+pub const MMIO_ACCESS_UNALIGNED: u32 = 1 << 0;
+pub const MMIO_ACCESS_CROSSES_END: u32 = 1 << 1;
+
+const REGISTER_BLOB_MAGIC: &[u8; 4] = b"ICRG";
+const REGISTER_BLOB_VERSION: u32 = 2;
+const REGISTER_BLOB_HEADER_SIZE: usize = 20;
+
+// Flags describing an access of `size` bytes at `address` to the MMIO window at `base`.
+pub fn mmio_access_flags(base: u64, length: u64, address: u64, size: usize) -> u32 {
+    let mut flags = 0;
+
+    if size != 0 && !address.is_multiple_of(size as u64) {
+        flags |= MMIO_ACCESS_UNALIGNED;
+    }
+
+    let offset = address.wrapping_sub(base);
+    if offset.saturating_add(size as u64) > length {
+        flags |= MMIO_ACCESS_CROSSES_END;
+    }
+
+    return flags;
+}
+
+// The subset of icicle's cpu::Config that can be chosen when creating an emulator.
+#[derive(Clone, Copy)]
+pub struct VmConfig {
+    pub enable_jit: bool,
+    pub enable_shadow_stack: bool,
+    pub enable_recompilation: bool,
+    pub track_uninitialized: bool,
+    pub optimize_instructions: bool,
+    pub optimize_block: bool,
+}
+
+impl VmConfig {
+    pub fn from_flags(flags: u32) -> Self {
+        Self {
+            enable_jit: false,
+            enable_shadow_stack: (flags & CREATE_SHADOW_STACK) != 0,
+            enable_recompilation: true,
+            track_uninitialized: false,
+            optimize_instructions: true,
+            optimize_block: false,
+        }
+    }
+}
 
-fn create_x64_vm() -> icicle_vm::Vm {
+impl Default for VmConfig {
+    fn default() -> Self {
+        return Self::from_flags(0);
+    }
+}
+
+fn create_x64_vm(config: &VmConfig) -> icicle_vm::Vm {
     let mut cpu_config = icicle_vm::cpu::Config::from_target_triple("x86_64-none");
-    cpu_config.enable_jit = false;
+    cpu_config.enable_jit = config.enable_jit;
     cpu_config.enable_jit_mem = true;
-    cpu_config.enable_shadow_stack = false;
-    cpu_config.enable_recompilation = true;
-    cpu_config.track_uninitialized = false;
-    cpu_config.optimize_instructions = true;
-    cpu_config.optimize_block = false;
+    cpu_config.enable_shadow_stack = config.enable_shadow_stack;
+    cpu_config.enable_recompilation = config.enable_recompilation;
+    cpu_config.track_uninitialized = config.track_uninitialized;
+    cpu_config.optimize_instructions = config.optimize_instructions;
+    cpu_config.optimize_block = config.optimize_block;
 
     return icicle_vm::build(&cpu_config).unwrap();
 }
 
-const FOREIGN_READ: u8 = 1 << 0;
-const FOREIGN_WRITE: u8 = 1 << 1;
-const FOREIGN_EXEC: u8 = 1 << 2;
+pub const FOREIGN_READ: u8 = 1 << 0;
+pub const FOREIGN_WRITE: u8 = 1 << 1;
+pub const FOREIGN_EXEC: u8 = 1 << 2;
 
 fn map_permissions(foreign_permissions: u8) -> u8 {
     let mut permissions: u8 = 0;
@@ -39,6 +114,216 @@ fn map_permissions(foreign_permissions: u8) -> u8 {
     return permissions;
 }
 
+fn foreign_permissions(permissions: u8) -> u8 {
+    let mut foreign_permissions: u8 = 0;
+
+    if (permissions & icicle_vm::cpu::mem::perm::READ) != 0 {
+        foreign_permissions |= FOREIGN_READ;
+    }
+
+    if (permissions & icicle_vm::cpu::mem::perm::WRITE) != 0 {
+        foreign_permissions |= FOREIGN_WRITE;
+    }
+
+    if (permissions & icicle_vm::cpu::mem::perm::EXEC) != 0 {
+        foreign_permissions |= FOREIGN_EXEC;
+    }
+
+    return foreign_permissions;
+}
+
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    InstructionLimit = 0,
+    Breakpoint,
+    Exception,
+    Other,
+    HarnessExit,
+    Syscall,
+    Doorbell,
+    LimitPaused,
+    LimitExtended,
+    Timeout,
+    DepthReached,
+    Watchpoint,
+    Halt,
+    StopRequested,
+}
+
+fn map_vm_exit(exit: icicle_vm::VmExit) -> StopReason {
+    match exit {
+        icicle_vm::VmExit::InstructionLimit => StopReason::InstructionLimit,
+        icicle_vm::VmExit::Breakpoint => StopReason::Breakpoint,
+        icicle_vm::VmExit::Halt => StopReason::Halt,
+        icicle_vm::VmExit::UnhandledException((ExceptionCode::Syscall, _)) => StopReason::Syscall,
+        icicle_vm::VmExit::UnhandledException(_) => StopReason::Exception,
+        _ => StopReason::Other,
+    }
+}
+
+// The exception a run stopped on with StopReason::Exception or StopReason::Syscall. The
+// values are stable, unlike the numbering of icicle's ExceptionCode.
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExitException {
+    None = 0,
+    Syscall,
+    ReadUnmapped,
+    WriteUnmapped,
+    ReadPermission,
+    WritePermission,
+    ExecViolation,
+    Unaligned,
+    InvalidInstruction,
+    DivisionError,
+    SoftwareBreakpoint,
+    Unimplemented,
+    Other,
+}
+
+fn map_exception_code(code: ExceptionCode) -> ExitException {
+    match code {
+        ExceptionCode::Syscall => ExitException::Syscall,
+        ExceptionCode::ReadUnmapped => ExitException::ReadUnmapped,
+        ExceptionCode::WriteUnmapped => ExitException::WriteUnmapped,
+        ExceptionCode::ReadPerm => ExitException::ReadPermission,
+        ExceptionCode::WritePerm => ExitException::WritePermission,
+        ExceptionCode::ExecViolation => ExitException::ExecViolation,
+        ExceptionCode::ReadUnaligned | ExceptionCode::WriteUnaligned => ExitException::Unaligned,
+        ExceptionCode::InvalidInstruction => ExitException::InvalidInstruction,
+        ExceptionCode::DivisionException => ExitException::DivisionError,
+        ExceptionCode::SoftwareBreakpoint => ExitException::SoftwareBreakpoint,
+        ExceptionCode::UnimplementedOp => ExitException::Unimplemented,
+        _ => ExitException::Other,
+    }
+}
+
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum WxPolicy {
+    Allow = 0,
+    Warn,
+    Deny,
+}
+
+impl WxPolicy {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(WxPolicy::Allow),
+            1 => Some(WxPolicy::Warn),
+            2 => Some(WxPolicy::Deny),
+            _ => None,
+        }
+    }
+}
+
+// What happens when a run reaches its instruction limit. Extend asks the limit
+// callback for more instructions once per run and stops if it returns 0.
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum LimitPolicy {
+    Stop = 0,
+    Pause,
+    Extend,
+}
+
+impl LimitPolicy {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(LimitPolicy::Stop),
+            1 => Some(LimitPolicy::Pause),
+            2 => Some(LimitPolicy::Extend),
+            _ => None,
+        }
+    }
+}
+
+// Intercept reports SYSCALL to the host through the syscall hooks. Emulate performs the
+// architectural transition into the kernel entry point configured in the MSRs.
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum SyscallMode {
+    Intercept = 0,
+    Emulate,
+}
+
+impl SyscallMode {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(SyscallMode::Intercept),
+            1 => Some(SyscallMode::Emulate),
+            _ => None,
+        }
+    }
+}
+
+// The descriptor table registers hold the limit in their low 4 bytes and the base in the
+// following 8. LDTR additionally carries the cached descriptor attributes, which are kept.
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum DescriptorTable {
+    Gdtr = 0,
+    Idtr,
+    Ldtr,
+}
+
+impl DescriptorTable {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(DescriptorTable::Gdtr),
+            1 => Some(DescriptorTable::Idtr),
+            2 => Some(DescriptorTable::Ldtr),
+            _ => None,
+        }
+    }
+
+    fn register(&self) -> registers::X86Register {
+        return match self {
+            DescriptorTable::Gdtr => registers::X86Register::Gdtr,
+            DescriptorTable::Idtr => registers::X86Register::Idtr,
+            DescriptorTable::Ldtr => registers::X86Register::Ldtr,
+        };
+    }
+
+    // GDTR and IDTR limits are 16 bits wide, the LDT limit comes from a segment descriptor.
+    fn max_limit(&self) -> u32 {
+        return match self {
+            DescriptorTable::Gdtr | DescriptorTable::Idtr => 0xFFFF,
+            DescriptorTable::Ldtr => u32::MAX,
+        };
+    }
+}
+
+// Returned by syscall hooks. Continue advances past the syscall unless a hook moved the
+// PC, Handled leaves the PC alone, Stop advances like Continue and ends the run with
+// StopReason::Syscall. With several hooks Stop wins over Handled, which wins over Continue.
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum SyscallAction {
+    Continue = 0,
+    Handled,
+    Stop,
+}
+
+impl SyscallAction {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(SyscallAction::Continue),
+            1 => Some(SyscallAction::Handled),
+            2 => Some(SyscallAction::Stop),
+            _ => None,
+        }
+    }
+}
+
+#[repr(i32)]
+pub enum TranslationState {
+    Untranslated = 0,
+    Interpreted,
+    Jitted,
+}
+
 #[repr(u8)]
 #[allow(dead_code)]
 #[derive(PartialEq)]
@@ -51,29 +336,94 @@ enum HookType {
     Violation,
     Interrupt,
     Block,
+    WxViolation,
+    RegisterRead,
+    Tracepoint,
+    StackUninit,
+    ReturnCheck,
+    ModeTransition,
+    Watchpoint,
+    UninitRead,
+    Breakpoint,
+    Exception,
     Unknown,
 }
 
-fn u8_to_hook_type_unsafe(value: u8) -> HookType {
-    unsafe { std::mem::transmute(value) }
+// Ids come from C callers, values that are not a hook type map to Unknown.
+fn u8_to_hook_type(value: u8) -> HookType {
+    return match value {
+        1 => HookType::Syscall,
+        2 => HookType::Read,
+        3 => HookType::Write,
+        4 => HookType::ExecuteGeneric,
+        5 => HookType::ExecuteSpecific,
+        6 => HookType::Violation,
+        7 => HookType::Interrupt,
+        8 => HookType::Block,
+        9 => HookType::WxViolation,
+        10 => HookType::RegisterRead,
+        11 => HookType::Tracepoint,
+        12 => HookType::StackUninit,
+        13 => HookType::ReturnCheck,
+        14 => HookType::ModeTransition,
+        15 => HookType::Watchpoint,
+        16 => HookType::UninitRead,
+        17 => HookType::Breakpoint,
+        18 => HookType::Exception,
+        _ => HookType::Unknown,
+    };
 }
 
+// The type is stored in the top 8 bits, leaving 24 bits for the id. 0 is never a valid id.
+const MAX_HOOK_ID: u32 = 0xFFFFFF;
+
 fn split_hook_id(id: u32) -> (u32, HookType) {
-    let hook_id = id & 0xFFFFFF;
-    let hook_type = u8_to_hook_type_unsafe((id >> 24) as u8);
+    let hook_id = id & MAX_HOOK_ID;
+    let hook_type = u8_to_hook_type((id >> 24) as u8);
 
     return (hook_id, hook_type);
 }
 
+// Returns 0 for ids that do not fit, callers treat that as a failed registration.
 fn qualify_hook_id(hook_id: u32, hook_type: HookType) -> u32 {
+    if hook_id == 0 || hook_id > MAX_HOOK_ID {
+        return 0;
+    }
+
     let hook_type: u32 = (hook_type as u8).into();
     let hook_type_mask: u32 = hook_type << 24;
-    return (hook_id | hook_type_mask).into();
+    return hook_id | hook_type_mask;
+}
+
+// Next unused id after `last`, wrapping around so that ids of removed hooks are reused.
+fn next_hook_id(last: u32, in_use: impl Fn(u32) -> bool) -> Option<u32> {
+    let mut id = last;
+
+    for _ in 0..MAX_HOOK_ID {
+        id = if id >= MAX_HOOK_ID { 1 } else { id + 1 };
+        if !in_use(id) {
+            return Some(id);
+        }
+    }
+
+    return None;
+}
+
+pub type MmioReadHandler = Box<dyn Fn(u64, &mut [u8]) -> bool>;
+pub type MmioWriteHandler = Box<dyn Fn(u64, &[u8]) -> bool>;
+pub type MemoryAccessHook = Box<dyn Fn(u64, u64, &[u8])>;
+pub type ReturnCheckHook = Box<dyn Fn(u64, u64, u64)>;
+pub type SyscallHook = dyn Fn(&mut IcicleEmulator) -> SyscallAction;
+pub type RegisterDiffCallback<'a> = dyn FnMut(registers::X86Register, &[u8], &[u8]) + 'a;
+
+struct HookEntry<Func: ?Sized> {
+    callback: Rc<Func>,
+    tag: u64,
 }
 
 pub struct HookContainer<Func: ?Sized> {
     hook_id: u32,
-    hooks: HashMap<u32, Box<Func>>,
+    hooks: HashMap<u32, HookEntry<Func>>,
 }
 
 impl<Func: ?Sized> HookContainer<Func> {
@@ -84,16 +434,61 @@ impl<Func: ?Sized> HookContainer<Func> {
         }
     }
 
+    // Returns 0 once all MAX_HOOK_ID ids are in use.
     pub fn add_hook(&mut self, callback: Box<Func>) -> u32 {
-        self.hook_id += 1;
-        let id = self.hook_id;
-        self.hooks.insert(id, callback);
+        if self.hooks.len() >= MAX_HOOK_ID as usize {
+            return 0;
+        }
+
+        let id = match next_hook_id(self.hook_id, |id| self.hooks.contains_key(&id)) {
+            Some(id) => id,
+            None => return 0,
+        };
+
+        self.hook_id = id;
+        self.hooks.insert(
+            id,
+            HookEntry {
+                callback: Rc::from(callback),
+                tag: 0,
+            },
+        );
 
         return id;
     }
 
-    pub fn get_hooks(&self) -> &HashMap<u32, Box<Func>> {
-        return &self.hooks;
+    pub fn get(&self, id: u32) -> Option<&Func> {
+        return self.hooks.get(&id).map(|entry| entry.callback.as_ref());
+    }
+
+    pub fn callbacks(&self) -> impl Iterator<Item = &Func> {
+        return self.hooks.values().map(|entry| entry.callback.as_ref());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.hooks.is_empty();
+    }
+
+    // Callbacks that may modify the container while they run are called from a snapshot.
+    pub fn snapshot(&self) -> Vec<(u32, Rc<Func>)> {
+        return self
+            .hooks
+            .iter()
+            .map(|(id, entry)| (*id, entry.callback.clone()))
+            .collect();
+    }
+
+    // False once the hook was removed, even if its id was given to a new hook since.
+    pub fn is_installed(&self, id: u32, callback: &Rc<Func>) -> bool {
+        return self
+            .hooks
+            .get(&id)
+            .is_some_and(|entry| Rc::ptr_eq(&entry.callback, callback));
+    }
+
+    // The tag lives in the entry, so a hook added later under the same id starts at 0.
+    pub fn tag_mut(&mut self, id: u32) -> Option<&mut u64> {
+        return self.hooks.get_mut(&id).map(|entry| &mut entry.tag);
     }
 
     pub fn remove_hook(&mut self, id: u32) {
@@ -104,6 +499,59 @@ impl<Func: ?Sized> HookContainer<Func> {
 struct InstructionHookInjector {
     inst_hook: pcode::HookId,
     block_hook: pcode::HookId,
+    register_read_hook: pcode::HookId,
+    call_hook: pcode::HookId,
+    return_hook: pcode::HookId,
+    hooks: Rc<RefCell<ExecutionHooks>>,
+    translated_ops: Rc<Cell<u64>>,
+    code_cache_limit: Rc<Cell<Option<u64>>>,
+    interrupt_flag: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+fn pack_var_node(var: pcode::VarNode) -> u64 {
+    let id = (var.id as u16) as u64;
+    return id | ((var.offset as u64) << 16) | ((var.size as u64) << 24);
+}
+
+fn unpack_var_node(value: u64) -> pcode::VarNode {
+    return pcode::VarNode {
+        id: (value & 0xFFFF) as u16 as pcode::VarId,
+        offset: ((value >> 16) & 0xFF) as u8,
+        size: ((value >> 24) & 0xFF) as u8,
+    };
+}
+
+fn var_nodes_overlap(a: pcode::VarNode, b: pcode::VarNode) -> bool {
+    if a.id != b.id {
+        return false;
+    }
+
+    return a.offset < b.offset + b.size && b.offset < a.offset + a.size;
+}
+
+// Fixed hash for values that are compared across runs, unlike std's DefaultHasher whose
+// algorithm may change between Rust releases. Integers are hashed little-endian.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        return Self(0xCBF29CE484222325);
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001B3);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        return self.0;
+    }
 }
 
 fn count_instructions(block: &icicle_cpu::lifter::Block) -> u64 {
@@ -123,6 +571,8 @@ impl icicle_vm::CodeInjector for InstructionHookInjector {
         group: &icicle_vm::cpu::BlockGroup,
         code: &mut icicle_vm::BlockTable,
     ) {
+        let cached_ops = self.translated_ops.get();
+
         for id in group.range() {
             let block = &mut code.blocks[id];
 
@@ -130,9 +580,44 @@ impl icicle_vm::CodeInjector for InstructionHookInjector {
             tmp_block.next_tmp = block.pcode.next_tmp;
 
             let mut is_first_inst = true;
-            let inst_count = count_instructions(&block);
+            let mut next_address: u64 = 0;
+            let inst_count = count_instructions(block);
+            let hooks = self.hooks.borrow();
 
             for stmt in block.pcode.instructions.drain(..) {
+                for input in [stmt.inputs.first(), stmt.inputs.second()] {
+                    if let pcode::Value::Var(var) = input
+                        && hooks.is_register_watched(var)
+                    {
+                        tmp_block.push((pcode::Op::Arg(0), pcode::Inputs::one(pack_var_node(var))));
+                        tmp_block.push(pcode::Op::Hook(self.register_read_hook));
+                        code.modified.insert(id);
+                    }
+                }
+
+                match stmt.op {
+                    pcode::Op::Branch(pcode::BranchHint::Call) => {
+                        tmp_block.push((pcode::Op::Arg(0), pcode::Inputs::one(next_address)));
+                        tmp_block.push(pcode::Op::Hook(self.call_hook));
+                        code.modified.insert(id);
+                    }
+                    pcode::Op::Branch(pcode::BranchHint::Return) => {
+                        tmp_block
+                            .push((pcode::Op::Arg(0), pcode::Inputs::one(stmt.inputs.second())));
+                        tmp_block.push(pcode::Op::Hook(self.return_hook));
+                        code.modified.insert(id);
+                    }
+                    _ => {}
+                }
+
+                // Markers carry the address and length of the instruction that follows.
+                if let pcode::Op::InstructionMarker = stmt.op
+                    && let (pcode::Value::Const(address, _), pcode::Value::Const(length, _)) =
+                        (stmt.inputs.first(), stmt.inputs.second())
+                {
+                    next_address = address + length;
+                }
+
                 tmp_block.push(stmt);
                 if let pcode::Op::InstructionMarker = stmt.op {
                     if is_first_inst {
@@ -147,31 +632,86 @@ impl icicle_vm::CodeInjector for InstructionHookInjector {
             }
 
             std::mem::swap(&mut tmp_block.instructions, &mut block.pcode.instructions);
+
+            let block_ops = block.pcode.instructions.len() as u64;
+            self.translated_ops
+                .set(self.translated_ops.get() + block_ops);
+        }
+
+        // The cache cannot be flushed while code is being translated, the run loop does it.
+        // A group that exceeds the limit on its own is kept, flushing it would only lead
+        // to translating it again.
+        if let Some(limit) = self.code_cache_limit.get()
+            && cached_ops != 0
+            && self.translated_ops.get() > limit
+        {
+            self.interrupt_flag
+                .store(true, std::sync::atomic::Ordering::Relaxed);
         }
     }
 }
 
 struct ExecutionHooks {
     stop: Rc<RefCell<bool>>,
+    current_pc: Rc<Cell<u64>>,
     generic_hooks: HookContainer<dyn Fn(u64)>,
     specific_hooks: HookContainer<dyn Fn(u64)>,
     block_hooks: HookContainer<dyn Fn(u64, u64)>,
     address_mapping: HashMap<u64, Vec<u32>>,
+    register_read_hooks: HookContainer<dyn Fn(u64)>,
+    register_read_nodes: HashMap<u32, pcode::VarNode>,
+    tracepoints: HookContainer<dyn Fn(u64, u64)>,
+    tracepoint_mapping: HashMap<u64, Vec<u32>>,
+    stack_pointer: Option<(pcode::VarNode, Rc<Cell<u64>>)>,
+    deadline: Option<std::time::Instant>,
+    deadline_expired: bool,
+    return_check: Option<ReturnCheckHook>,
+    return_check_tag: u64,
+    return_addresses: Vec<u64>,
+    call_depth: i64,
+    depth_target: Option<i64>,
+    depth_reached: bool,
+    mode_hooks: HookContainer<dyn Fn(u8, u8, u64)>,
+    cs_node: Option<pcode::VarNode>,
+    last_cpl: u8,
+    trace: Option<trace::TraceRecorder>,
+    trace_nodes: Option<[pcode::VarNode; trace::TRACE_REGISTERS.len()]>,
+    coverage: Option<coverage::CoverageMap>,
 }
 
 impl ExecutionHooks {
-    pub fn new(stop_value: Rc<RefCell<bool>>) -> Self {
+    pub fn new(stop_value: Rc<RefCell<bool>>, current_pc: Rc<Cell<u64>>) -> Self {
         Self {
             stop: stop_value,
+            current_pc,
             generic_hooks: HookContainer::new(),
             specific_hooks: HookContainer::new(),
             block_hooks: HookContainer::new(),
             address_mapping: HashMap::new(),
+            register_read_hooks: HookContainer::new(),
+            register_read_nodes: HashMap::new(),
+            tracepoints: HookContainer::new(),
+            tracepoint_mapping: HashMap::new(),
+            stack_pointer: None,
+            deadline: None,
+            deadline_expired: false,
+            return_check: None,
+            return_check_tag: 0,
+            return_addresses: Vec::new(),
+            call_depth: 0,
+            depth_target: None,
+            depth_reached: false,
+            mode_hooks: HookContainer::new(),
+            cs_node: None,
+            last_cpl: 0,
+            trace: None,
+            trace_nodes: None,
+            coverage: None,
         }
     }
 
     fn run_hooks(&self, address: u64) {
-        for (_key, func) in self.generic_hooks.get_hooks() {
+        for func in self.generic_hooks.callbacks() {
             func(address);
         }
 
@@ -181,20 +721,97 @@ impl ExecutionHooks {
         }
 
         for id in mapping.unwrap() {
-            let func = self.specific_hooks.get_hooks().get(&id);
-            if func.is_some() {
-                func.unwrap()(address);
+            let func = self.specific_hooks.get(*id);
+            if let Some(func) = func {
+                func(address);
             }
         }
     }
 
     pub fn on_block(&mut self, address: u64, instructions: u64) {
-        for (_key, func) in self.block_hooks.get_hooks() {
+        if let Some(deadline) = self.deadline
+            && std::time::Instant::now() >= deadline
+        {
+            self.deadline = None;
+            self.deadline_expired = true;
+            *self.stop.borrow_mut() = true;
+        }
+
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.on_block(address);
+        }
+
+        for func in self.block_hooks.callbacks() {
             func(address, instructions);
         }
     }
 
+    // CS is only sampled at block boundaries, so the transition is reported with the
+    // address of the first block executed at the new privilege level.
+    pub fn check_privilege_level(&mut self, cpu: &mut icicle_cpu::Cpu, address: u64) {
+        let node = match self.cs_node {
+            Some(node) => node,
+            None => return,
+        };
+
+        let cpl = (cpu.read_reg(node) & 3) as u8;
+        if cpl == self.last_cpl {
+            return;
+        }
+
+        let old_cpl = self.last_cpl;
+        self.last_cpl = cpl;
+
+        for func in self.mode_hooks.callbacks() {
+            func(old_cpl, cpl, address);
+        }
+    }
+
+    pub fn add_mode_hook(
+        &mut self,
+        cs_node: pcode::VarNode,
+        current_cpl: u8,
+        callback: Box<dyn Fn(u8, u8, u64)>,
+    ) -> u32 {
+        self.cs_node = Some(cs_node);
+        self.last_cpl = current_cpl;
+        return self.mode_hooks.add_hook(callback);
+    }
+
+    pub fn remove_mode_hook(&mut self, id: u32) {
+        self.mode_hooks.remove_hook(id);
+
+        if self.mode_hooks.is_empty() {
+            self.cs_node = None;
+        }
+    }
+
+    fn run_tracepoints(&self, address: u64, icount: u64) {
+        let mapping = match self.tracepoint_mapping.get(&address) {
+            Some(mapping) => mapping,
+            None => return,
+        };
+
+        for id in mapping {
+            if let Some(func) = self.tracepoints.get(*id) {
+                func(address, icount);
+            }
+        }
+    }
+
     pub fn execute(&mut self, cpu: &mut icicle_cpu::Cpu, address: u64) {
+        self.current_pc.set(address);
+
+        if let Some((node, value)) = &self.stack_pointer {
+            value.set(cpu.read_reg(*node));
+        }
+
+        if let (Some(nodes), Some(recorder)) = (&self.trace_nodes, &mut self.trace) {
+            let values = nodes.map(|node| cpu.read_reg(node));
+            recorder.record(cpu.icount, address, values);
+        }
+
+        self.run_tracepoints(address, cpu.icount);
         self.run_hooks(address);
 
         if *self.stop.borrow() {
@@ -203,6 +820,48 @@ impl ExecutionHooks {
         }
     }
 
+    pub fn on_register_read(&mut self, address: u64, packed_node: u64) {
+        let node = unpack_var_node(packed_node);
+
+        for (id, watched) in &self.register_read_nodes {
+            if !var_nodes_overlap(node, *watched) {
+                continue;
+            }
+
+            let func = self.register_read_hooks.get(*id);
+            if let Some(func) = func {
+                func(address);
+            }
+        }
+    }
+
+    pub fn is_register_watched(&self, var: pcode::VarNode) -> bool {
+        return self
+            .register_read_nodes
+            .values()
+            .any(|watched| var_nodes_overlap(var, *watched));
+    }
+
+    pub fn add_register_read_hook(
+        &mut self,
+        node: pcode::VarNode,
+        callback: Box<dyn Fn(u64)>,
+    ) -> u32 {
+        let id = self.register_read_hooks.add_hook(callback);
+        if id == 0 {
+            return 0;
+        }
+
+        self.register_read_nodes.insert(id, node);
+
+        return id;
+    }
+
+    pub fn remove_register_read_hook(&mut self, id: u32) {
+        self.register_read_nodes.remove(&id);
+        self.register_read_hooks.remove_hook(id);
+    }
+
     pub fn add_block_hook(&mut self, callback: Box<dyn Fn(u64, u64)>) -> u32 {
         self.block_hooks.add_hook(callback)
     }
@@ -217,506 +876,4600 @@ impl ExecutionHooks {
 
     pub fn add_specific_hook(&mut self, address: u64, callback: Box<dyn Fn(u64)>) -> u32 {
         let id = self.specific_hooks.add_hook(callback);
+        if id == 0 {
+            return 0;
+        }
 
-        let mapping = self.address_mapping.entry(address).or_insert_with(Vec::new);
+        let mapping = self.address_mapping.entry(address).or_default();
         mapping.push(id);
 
         return id;
     }
 
-    pub fn remove_generic_hook(&mut self, id: u32) {
-        self.generic_hooks.remove_hook(id);
+    pub fn add_tracepoint(&mut self, address: u64, callback: Box<dyn Fn(u64, u64)>) -> u32 {
+        let id = self.tracepoints.add_hook(callback);
+        if id == 0 {
+            return 0;
+        }
+
+        let mapping = self.tracepoint_mapping.entry(address).or_default();
+        mapping.push(id);
+
+        return id;
     }
 
-    pub fn remove_specific_hook(&mut self, id: u32) {
-        self.address_mapping.retain(|_, vec| {
+    pub fn remove_tracepoint(&mut self, id: u32) {
+        self.tracepoint_mapping.retain(|_, vec| {
             vec.retain(|&x| x != id);
             !vec.is_empty()
         });
 
-        self.specific_hooks.remove_hook(id);
+        self.tracepoints.remove_hook(id);
     }
-}
-
-pub struct IcicleEmulator {
-    executing_thread: std::thread::ThreadId,
-    vm: icicle_vm::Vm,
-    reg: registers::X86RegisterNodes,
-    syscall_hooks: HookContainer<dyn Fn()>,
-    interrupt_hooks: HookContainer<dyn Fn(i32)>,
-    violation_hooks: HookContainer<dyn Fn(u64, u8, bool) -> bool>,
-    execution_hooks: Rc<RefCell<ExecutionHooks>>,
-    stop: Rc<RefCell<bool>>,
-    snapshots: Vec<Box<icicle_vm::Snapshot>>,
-}
 
-struct MemoryHook {
-    callback: Box<dyn Fn(u64, &[u8])>,
-}
-
-impl icicle_cpu::mem::WriteHook for MemoryHook {
-    fn write(&mut self, _mem: &mut icicle_cpu::Mmu, addr: u64, value: &[u8]) {
-        (self.callback)(addr, value);
-    }
-}
+    pub fn on_call(&mut self, return_address: u64) {
+        self.update_depth(1);
 
-impl icicle_cpu::mem::ReadAfterHook for MemoryHook {
-    fn read(&mut self, _mem: &mut icicle_cpu::Mmu, addr: u64, value: &[u8]) {
-        (self.callback)(addr, value);
+        if self.return_check.is_some() {
+            self.return_addresses.push(return_address);
+        }
     }
-}
 
-pub struct MmioHandler {
-    read_handler: Box<dyn Fn(u64, &mut [u8])>,
-    write_handler: Box<dyn Fn(u64, &[u8])>,
-}
+    fn update_depth(&mut self, delta: i64) {
+        self.call_depth += delta;
 
-impl MmioHandler {
-    pub fn new(
-        read_function: Box<dyn Fn(u64, &mut [u8])>,
-        write_function: Box<dyn Fn(u64, &[u8])>,
-    ) -> Self {
-        Self {
-            read_handler: read_function,
-            write_handler: write_function,
+        if self.depth_target == Some(self.call_depth) {
+            self.depth_target = None;
+            self.depth_reached = true;
+            *self.stop.borrow_mut() = true;
         }
     }
-}
 
-impl icicle_cpu::mem::IoMemory for MmioHandler {
-    fn read(&mut self, addr: u64, buf: &mut [u8]) -> icicle_cpu::mem::MemResult<()> {
-        (self.read_handler)(addr, buf);
-        return Ok(());
+    pub fn call_depth(&self) -> i64 {
+        return self.call_depth;
     }
 
-    fn write(&mut self, addr: u64, value: &[u8]) -> icicle_cpu::mem::MemResult<()> {
-        (self.write_handler)(addr, value);
-        return Ok(());
+    pub fn set_depth_target(&mut self, target: Option<i64>) {
+        self.depth_target = target;
+        self.depth_reached = false;
     }
-}
 
-impl IcicleEmulator {
-    pub fn new() -> Self {
-        let mut virtual_machine = create_x64_vm();
-        let stop_value = Rc::new(RefCell::new(false));
-        let exec_hooks = Rc::new(RefCell::new(ExecutionHooks::new(stop_value.clone())));
+    pub fn depth_reached(&self) -> bool {
+        return self.depth_reached;
+    }
 
-        let inst_exec_hooks = Rc::clone(&exec_hooks);
+    // Returns that do not match a recorded call (e.g. into frames entered before the
+    // check was enabled) are not reported.
+    pub fn on_return(&mut self, address: u64, target: u64) {
+        self.update_depth(-1);
 
-        let inst_hook = icicle_cpu::InstHook::new(move |cpu: &mut icicle_cpu::Cpu, addr: u64| {
-            inst_exec_hooks.borrow_mut().execute(cpu, addr);
-        });
+        let callback = match &self.return_check {
+            Some(callback) => callback,
+            None => return,
+        };
 
-        let block_exec_hooks = Rc::clone(&exec_hooks);
+        if let Some(expected) = self.return_addresses.pop()
+            && expected != target
+        {
+            callback(address, expected, target);
+        }
+    }
 
-        let block_hook = icicle_cpu::InstHook::new(move |cpu: &mut icicle_cpu::Cpu, addr: u64| {
+    pub fn set_return_check(&mut self, callback: Option<ReturnCheckHook>) {
+        self.return_check = callback;
+        self.return_check_tag = 0;
+        self.return_addresses.clear();
+    }
+
+    pub fn tag_mut(&mut self, hook_id: u32, hook_type: HookType) -> Option<&mut u64> {
+        return match hook_type {
+            HookType::ExecuteGeneric => self.generic_hooks.tag_mut(hook_id),
+            HookType::ExecuteSpecific => self.specific_hooks.tag_mut(hook_id),
+            HookType::Block => self.block_hooks.tag_mut(hook_id),
+            HookType::RegisterRead => self.register_read_hooks.tag_mut(hook_id),
+            HookType::Tracepoint => self.tracepoints.tag_mut(hook_id),
+            HookType::ModeTransition => self.mode_hooks.tag_mut(hook_id),
+            HookType::ReturnCheck if hook_id == 1 && self.return_check.is_some() => {
+                Some(&mut self.return_check_tag)
+            }
+            _ => None,
+        };
+    }
+
+    // Starting discards the previous trace, stopping keeps it available for export.
+    pub fn start_trace(
+        &mut self,
+        nodes: [pcode::VarNode; trace::TRACE_REGISTERS.len()],
+        max_entries: usize,
+    ) {
+        self.trace = Some(trace::TraceRecorder::new(max_entries));
+        self.trace_nodes = Some(nodes);
+    }
+
+    pub fn stop_trace(&mut self) {
+        self.trace_nodes = None;
+    }
+
+    pub fn trace(&self) -> Option<&trace::TraceRecorder> {
+        return self.trace.as_ref();
+    }
+
+    pub fn set_coverage(&mut self, coverage: Option<coverage::CoverageMap>) {
+        self.coverage = coverage;
+    }
+
+    pub fn coverage(&mut self) -> Option<&mut coverage::CoverageMap> {
+        return self.coverage.as_mut();
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<std::time::Instant>) {
+        self.deadline = deadline;
+        self.deadline_expired = false;
+    }
+
+    pub fn deadline_expired(&self) -> bool {
+        return self.deadline_expired;
+    }
+
+    // Records the stack pointer before every instruction for hooks that cannot access the CPU.
+    pub fn set_stack_pointer_tracking(
+        &mut self,
+        tracking: Option<(pcode::VarNode, Rc<Cell<u64>>)>,
+    ) {
+        self.stack_pointer = tracking;
+    }
+
+    pub fn remove_generic_hook(&mut self, id: u32) {
+        self.generic_hooks.remove_hook(id);
+    }
+
+    pub fn remove_specific_hook(&mut self, id: u32) {
+        self.address_mapping.retain(|_, vec| {
+            vec.retain(|&x| x != id);
+            !vec.is_empty()
+        });
+
+        self.specific_hooks.remove_hook(id);
+    }
+
+    // Tracing, coverage and stack pointer tracking are not hooks and stay active.
+    pub fn remove_all_hooks(&mut self) {
+        self.generic_hooks = HookContainer::new();
+        self.specific_hooks = HookContainer::new();
+        self.block_hooks = HookContainer::new();
+        self.address_mapping.clear();
+        self.register_read_hooks = HookContainer::new();
+        self.register_read_nodes.clear();
+        self.tracepoints = HookContainer::new();
+        self.tracepoint_mapping.clear();
+        self.mode_hooks = HookContainer::new();
+        self.cs_node = None;
+        self.set_return_check(None);
+    }
+}
+
+enum NamedRegister {
+    Flags(registers::X86Register),
+    Node(pcode::VarNode),
+}
+
+struct EmulatorSnapshot {
+    vm: icicle_vm::Snapshot,
+    mapped_regions: regions::RegionMap,
+    mmio_regions: regions::RegionMap,
+    msrs: HashMap<u32, u64>,
+}
+
+// Stops the run of an emulator from any thread. The run ends with StopReason::StopRequested
+// at the next block boundary. A request made while no run is in progress stops the next run
+// before it executes anything.
+#[derive(Clone)]
+pub struct StopHandle {
+    requested: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    interrupt_flag: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl StopHandle {
+    pub fn request_stop(&self) {
+        self.requested
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+        self.interrupt_flag
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+struct Breakpoint {
+    address: u64,
+    flags: u32,
+    tag: u64,
+}
+
+pub struct IcicleEmulator {
+    executing_thread: std::thread::ThreadId,
+    vm: icicle_vm::Vm,
+    reg: registers::X86RegisterNodes,
+    syscall_hooks: HookContainer<SyscallHook>,
+    interrupt_hooks: HookContainer<dyn Fn(i32)>,
+    uninit_read_hooks: HookContainer<dyn Fn(u64)>,
+    exception_hooks: HookContainer<dyn Fn(ExitException, u64) -> bool>,
+    violation_hooks: HookContainer<dyn Fn(u64, u8, bool) -> bool>,
+    wx_hooks: HookContainer<dyn Fn(u64, u64, u8)>,
+    wx_policy: WxPolicy,
+    execution_hooks: Rc<RefCell<ExecutionHooks>>,
+    stop: Rc<RefCell<bool>>,
+    stop_requested: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    current_pc: Rc<Cell<u64>>,
+    snapshots: Vec<Option<Box<EmulatorSnapshot>>>,
+    timers: Vec<AttachedTimer>,
+    exit_address: Option<u64>,
+    intercept_syscalls: bool,
+    region_names: BTreeMap<u64, (u64, String)>,
+    doorbell_exit: Rc<Cell<bool>>,
+    watchpoint_exit: Rc<Cell<bool>>,
+    decoder: decode::Decoder,
+    limit_policy: LimitPolicy,
+    limit_callback: Option<Box<dyn Fn(u64) -> u64>>,
+    limit_extended: bool,
+    port_handler: Option<PortHandler>,
+    mapped_regions: regions::RegionMap,
+    mmio_regions: regions::RegionMap,
+    stack_watch: Option<StackWatch>,
+    fault_instruction: Option<decode::FaultInsn>,
+    exit_exception: Option<(ExceptionCode, u64)>,
+    mmio_log: Rc<RefCell<mmio_log::MmioLog>>,
+    translated_ops: Rc<Cell<u64>>,
+    code_cache_limit: Rc<Cell<Option<u64>>>,
+    msrs: HashMap<u32, u64>,
+    syscall_mode: SyscallMode,
+    breakpoints: BTreeMap<u32, Breakpoint>,
+    breakpoint_id: u32,
+    breakpoint_hit: Option<(u32, u64)>,
+    memory_hooks: HashMap<u32, u64>,
+    initial_registers: Vec<u8>,
+}
+
+type MemoryWriteCallback = Box<dyn Fn(u64, &[u8])>;
+
+struct MemoryHook {
+    callback: MemoryWriteCallback,
+}
+
+impl icicle_cpu::mem::WriteHook for MemoryHook {
+    fn write(&mut self, _mem: &mut icicle_cpu::Mmu, addr: u64, value: &[u8]) {
+        (self.callback)(addr, value);
+    }
+}
+
+impl icicle_cpu::mem::ReadAfterHook for MemoryHook {
+    fn read(&mut self, _mem: &mut icicle_cpu::Mmu, addr: u64, value: &[u8]) {
+        (self.callback)(addr, value);
+    }
+}
+
+// The block hook polls the device, it belongs to the timer and is removed with it.
+struct AttachedTimer {
+    base: u64,
+    device: Rc<RefCell<timer::TimerDevice>>,
+    block_hook: u32,
+}
+
+struct StackWatch {
+    tracker: Rc<RefCell<stack_tracker::StackTracker>>,
+    read_hook: u32,
+    write_hook: u32,
+    tag: u64,
+}
+
+pub const ENDIANNESS_LITTLE: u8 = 0;
+pub const ENDIANNESS_BIG: u8 = 1;
+
+// `name` is the NUL-terminated architecture of the target triple, e.g. "x86_64".
+#[repr(C)]
+#[derive(Default)]
+pub struct ArchInfo {
+    pub pointer_size: u32,
+    pub endianness: u8,
+    pub name: [u8; 16],
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct PermissionAudit {
+    pub rwx_bytes: u64,
+    pub rw_bytes: u64,
+    pub rx_bytes: u64,
+    pub ro_bytes: u64,
+    pub other_bytes: u64,
+}
+
+pub struct PortHandler {
+    pub read: Box<dyn Fn(u16, u8) -> u32>,
+    pub write: Box<dyn Fn(u16, u8, u32)>,
+}
+
+// Stable counterpart of icicle's MemError, shared by every memory access and mapping
+// function that reports a status. New values are only ever appended.
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum MemoryStatus {
+    Success = 0,
+    Unmapped,
+    Uninitialized,
+    ReadViolation,
+    WriteViolation,
+    ExecViolation,
+    Unaligned,
+    OutOfMemory,
+    AddressOverflow,
+    WxDenied,
+    Unknown,
+    InvalidArgument,
+    InvalidLength,
+    Overlap,
+    InvalidRanges,
+    MappingFailed,
+}
+
+impl MemoryStatus {
+    fn from_error(error: icicle_vm::cpu::mem::MemError) -> Self {
+        use icicle_vm::cpu::mem::MemError;
+
+        match error {
+            MemError::Unmapped => MemoryStatus::Unmapped,
+            MemError::Uninitalized => MemoryStatus::Uninitialized,
+            MemError::ReadViolation => MemoryStatus::ReadViolation,
+            MemError::WriteViolation => MemoryStatus::WriteViolation,
+            MemError::ExecViolation => MemoryStatus::ExecViolation,
+            MemError::Unaligned => MemoryStatus::Unaligned,
+            MemError::OutOfMemory => MemoryStatus::OutOfMemory,
+            MemError::AddressOverflow => MemoryStatus::AddressOverflow,
+            _ => MemoryStatus::Unknown,
+        }
+    }
+}
+
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum AddressingMode {
+    Flat = 0,
+    FourLevel,
+    Pae,
+    Unsupported,
+}
+
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum RunUntilStatus {
+    Reached = 0,
+    InstructionLimit,
+    Exception,
+    Stopped,
+}
+
+// General purpose registers in a fixed layout for bindings, RIP is the CPU's PC.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct GpRegs {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+}
+
+// `conflict` is the existing mapping (start, end) for MemoryStatus::Overlap.
+pub struct MmioMapError {
+    pub status: MemoryStatus,
+    pub conflict: Option<(u64, u64)>,
+}
+
+impl MmioMapError {
+    fn new(status: MemoryStatus) -> Self {
+        Self {
+            status,
+            conflict: None,
+        }
+    }
+}
+
+// Handlers return false to reject the access, which then fails like an access to unmapped
+// memory.
+pub struct MmioRange {
+    pub offset: u64,
+    pub length: u64,
+    pub read_handler: MmioReadHandler,
+    pub write_handler: MmioWriteHandler,
+}
+
+pub struct MmioHandler {
+    base: u64,
+    ranges: Vec<MmioRange>,
+    log: Rc<RefCell<mmio_log::MmioLog>>,
+}
+
+impl MmioHandler {
+    pub fn new(
+        read_function: MmioReadHandler,
+        write_function: MmioWriteHandler,
+        log: Rc<RefCell<mmio_log::MmioLog>>,
+    ) -> Self {
+        Self {
+            base: 0,
+            ranges: vec![MmioRange {
+                offset: 0,
+                length: u64::MAX,
+                read_handler: read_function,
+                write_handler: write_function,
+            }],
+            log,
+        }
+    }
+
+    // Ranges must be sorted by offset and must not overlap.
+    pub fn with_ranges(
+        base: u64,
+        ranges: Vec<MmioRange>,
+        log: Rc<RefCell<mmio_log::MmioLog>>,
+    ) -> Self {
+        Self { base, ranges, log }
+    }
+
+    fn find_range(&self, addr: u64) -> Option<&MmioRange> {
+        let offset = addr.wrapping_sub(self.base);
+        return self
+            .ranges
+            .iter()
+            .find(|range| offset >= range.offset && offset - range.offset < range.length);
+    }
+}
+
+impl icicle_cpu::mem::IoMemory for MmioHandler {
+    fn read(&mut self, addr: u64, buf: &mut [u8]) -> icicle_cpu::mem::MemResult<()> {
+        if self.log.borrow_mut().replay_read(addr, buf) {
+            return Ok(());
+        }
+
+        let accepted = match self.find_range(addr) {
+            Some(range) => (range.read_handler)(addr, buf),
+            None => {
+                buf.fill(0);
+                true
+            }
+        };
+
+        if !accepted {
+            return Err(icicle_cpu::mem::MemError::Unmapped);
+        }
+
+        self.log.borrow_mut().record(false, addr, buf);
+        return Ok(());
+    }
+
+    fn write(&mut self, addr: u64, value: &[u8]) -> icicle_cpu::mem::MemResult<()> {
+        if self.log.borrow_mut().replay_write(addr, value) {
+            return Ok(());
+        }
+
+        if let Some(range) = self.find_range(addr)
+            && !(range.write_handler)(addr, value)
+        {
+            return Err(icicle_cpu::mem::MemError::Unmapped);
+        }
+
+        self.log.borrow_mut().record(true, addr, value);
+        return Ok(());
+    }
+}
+
+fn write_chunk(cpu: &mut icicle_cpu::Cpu, node: pcode::VarNode, bytes: &[u8]) {
+    match bytes.len() {
+        1 => cpu.write_var::<[u8; 1]>(node, bytes.try_into().unwrap()),
+        2 => cpu.write_var::<[u8; 2]>(node, bytes.try_into().unwrap()),
+        3 => cpu.write_var::<[u8; 3]>(node, bytes.try_into().unwrap()),
+        4 => cpu.write_var::<[u8; 4]>(node, bytes.try_into().unwrap()),
+        5 => cpu.write_var::<[u8; 5]>(node, bytes.try_into().unwrap()),
+        6 => cpu.write_var::<[u8; 6]>(node, bytes.try_into().unwrap()),
+        7 => cpu.write_var::<[u8; 7]>(node, bytes.try_into().unwrap()),
+        8 => cpu.write_var::<[u8; 8]>(node, bytes.try_into().unwrap()),
+        9 => cpu.write_var::<[u8; 9]>(node, bytes.try_into().unwrap()),
+        10 => cpu.write_var::<[u8; 10]>(node, bytes.try_into().unwrap()),
+        11 => cpu.write_var::<[u8; 11]>(node, bytes.try_into().unwrap()),
+        12 => cpu.write_var::<[u8; 12]>(node, bytes.try_into().unwrap()),
+        13 => cpu.write_var::<[u8; 13]>(node, bytes.try_into().unwrap()),
+        14 => cpu.write_var::<[u8; 14]>(node, bytes.try_into().unwrap()),
+        15 => cpu.write_var::<[u8; 15]>(node, bytes.try_into().unwrap()),
+        16 => cpu.write_var::<[u8; 16]>(node, bytes.try_into().unwrap()),
+        _ => {}
+    }
+}
+
+impl Default for IcicleEmulator {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+// True if `buffer_size` bytes hold the sizes of the batch and `lengths` has an entry for
+// every address.
+fn batch_fits(addresses: &[u64], sizes: &[usize], buffer_size: usize, lengths: &[usize]) -> bool {
+    let count = std::cmp::min(addresses.len(), sizes.len());
+    let total = sizes[..count]
+        .iter()
+        .try_fold(0usize, |total, size| total.checked_add(*size));
+
+    return lengths.len() >= count && matches!(total, Some(total) if total <= buffer_size);
+}
+
+impl IcicleEmulator {
+    pub fn new() -> Self {
+        return Self::with_flags(0);
+    }
+
+    pub fn with_flags(flags: u32) -> Self {
+        return Self::with_config(&VmConfig::from_flags(flags));
+    }
+
+    pub fn with_config(config: &VmConfig) -> Self {
+        let mut virtual_machine = create_x64_vm(config);
+        let stop_value = Rc::new(RefCell::new(false));
+        let current_pc = Rc::new(Cell::new(0u64));
+        let exec_hooks = Rc::new(RefCell::new(ExecutionHooks::new(
+            stop_value.clone(),
+            Rc::clone(&current_pc),
+        )));
+
+        let inst_exec_hooks = Rc::clone(&exec_hooks);
+
+        let inst_hook = icicle_cpu::InstHook::new(move |cpu: &mut icicle_cpu::Cpu, addr: u64| {
+            inst_exec_hooks.borrow_mut().execute(cpu, addr);
+        });
+
+        let block_exec_hooks = Rc::clone(&exec_hooks);
+
+        let block_hook = icicle_cpu::InstHook::new(move |cpu: &mut icicle_cpu::Cpu, addr: u64| {
             let instructions = cpu.args[0] as u64;
-            block_exec_hooks.borrow_mut().on_block(addr, instructions);
+            let mut hooks = block_exec_hooks.borrow_mut();
+            hooks.check_privilege_level(cpu, addr);
+            hooks.on_block(addr, instructions);
+        });
+
+        let register_exec_hooks = Rc::clone(&exec_hooks);
+
+        let register_read_hook =
+            icicle_cpu::InstHook::new(move |cpu: &mut icicle_cpu::Cpu, addr: u64| {
+                let packed_node = cpu.args[0] as u64;
+                register_exec_hooks
+                    .borrow_mut()
+                    .on_register_read(addr, packed_node);
+            });
+
+        let call_exec_hooks = Rc::clone(&exec_hooks);
+
+        let call_hook = icicle_cpu::InstHook::new(move |cpu: &mut icicle_cpu::Cpu, _addr: u64| {
+            let return_address = cpu.args[0] as u64;
+            call_exec_hooks.borrow_mut().on_call(return_address);
         });
 
+        let return_exec_hooks = Rc::clone(&exec_hooks);
+
+        let return_hook = icicle_cpu::InstHook::new(move |cpu: &mut icicle_cpu::Cpu, addr: u64| {
+            let target = cpu.args[0] as u64;
+            return_exec_hooks.borrow_mut().on_return(addr, target);
+        });
+
+        let translated_ops = Rc::new(Cell::new(0u64));
+        let code_cache_limit = Rc::new(Cell::new(None));
+
         let inst_hook_id = virtual_machine.cpu.add_hook(inst_hook);
         let block_hook_id = virtual_machine.cpu.add_hook(block_hook);
+        let register_read_hook_id = virtual_machine.cpu.add_hook(register_read_hook);
+        let call_hook_id = virtual_machine.cpu.add_hook(call_hook);
+        let return_hook_id = virtual_machine.cpu.add_hook(return_hook);
         virtual_machine.add_injector(InstructionHookInjector {
             inst_hook: inst_hook_id,
             block_hook: block_hook_id,
+            register_read_hook: register_read_hook_id,
+            call_hook: call_hook_id,
+            return_hook: return_hook_id,
+            hooks: Rc::clone(&exec_hooks),
+            translated_ops: Rc::clone(&translated_ops),
+            code_cache_limit: Rc::clone(&code_cache_limit),
+            interrupt_flag: virtual_machine.interrupt_flag.clone(),
         });
 
-        Self {
+        let mut emulator = Self {
             stop: stop_value,
+            stop_requested: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            current_pc,
             executing_thread: std::thread::current().id(),
             reg: registers::X86RegisterNodes::new(&virtual_machine.cpu.arch),
             vm: virtual_machine,
             syscall_hooks: HookContainer::new(),
             interrupt_hooks: HookContainer::new(),
+            uninit_read_hooks: HookContainer::new(),
+            exception_hooks: HookContainer::new(),
             violation_hooks: HookContainer::new(),
+            wx_hooks: HookContainer::new(),
+            wx_policy: WxPolicy::Allow,
             execution_hooks: exec_hooks,
             snapshots: Vec::new(),
-        }
+            timers: Vec::new(),
+            exit_address: None,
+            intercept_syscalls: false,
+            region_names: BTreeMap::new(),
+            doorbell_exit: Rc::new(Cell::new(false)),
+            watchpoint_exit: Rc::new(Cell::new(false)),
+            decoder: decode::Decoder::new(),
+            limit_policy: LimitPolicy::Stop,
+            limit_callback: None,
+            limit_extended: false,
+            port_handler: None,
+            mapped_regions: regions::RegionMap::new(),
+            mmio_regions: regions::RegionMap::new(),
+            stack_watch: None,
+            fault_instruction: None,
+            exit_exception: None,
+            mmio_log: Rc::new(RefCell::new(mmio_log::MmioLog::new())),
+            translated_ops,
+            code_cache_limit,
+            msrs: HashMap::new(),
+            syscall_mode: SyscallMode::Intercept,
+            breakpoints: BTreeMap::new(),
+            breakpoint_id: 0,
+            breakpoint_hit: None,
+            memory_hooks: HashMap::new(),
+            initial_registers: Vec::new(),
+        };
+
+        emulator.initial_registers = emulator.save_registers();
+        return emulator;
     }
 
     fn get_mem(&mut self) -> &mut icicle_vm::cpu::Mmu {
         return &mut self.vm.cpu.mem;
     }
 
-    pub fn start(&mut self, count: u64) {
-        self.executing_thread = std::thread::current().id();
+    pub fn start(&mut self, count: u64) -> StopReason {
+        self.prepare_run(count);
+        let exit = self.run_loop();
 
-        self.vm.icount_limit = match count {
-            0 => u64::MAX,
-            _ => self.vm.cpu.icount.saturating_add(count),
+        return self.stop_reason(exit);
+    }
+
+    // Stops with the PC still on the syscall instruction and without running the syscall hooks.
+    // The host is responsible for advancing past the instruction before resuming.
+    pub fn run_until_syscall(&mut self, max_instructions: u64) -> StopReason {
+        self.intercept_syscalls = true;
+        let reason = self.start(max_instructions);
+        self.intercept_syscalls = false;
+
+        return reason;
+    }
+
+    fn stop_reason(&self, exit: icicle_vm::VmExit) -> StopReason {
+        let reason = map_vm_exit(exit);
+
+        if reason == StopReason::Breakpoint && self.exit_address == Some(self.vm.cpu.read_pc()) {
+            return StopReason::HarnessExit;
+        }
+
+        if matches!(exit, icicle_vm::VmExit::Interrupted) && self.doorbell_exit.replace(false) {
+            return StopReason::Doorbell;
+        }
+
+        if matches!(exit, icicle_vm::VmExit::Interrupted) && self.watchpoint_exit.replace(false) {
+            return StopReason::Watchpoint;
+        }
+
+        if matches!(exit, icicle_vm::VmExit::Interrupted)
+            && self
+                .stop_requested
+                .swap(false, std::sync::atomic::Ordering::Relaxed)
+        {
+            return StopReason::StopRequested;
+        }
+
+        if reason == StopReason::InstructionLimit
+            && self.execution_hooks.borrow().deadline_expired()
+        {
+            return StopReason::Timeout;
+        }
+
+        if reason == StopReason::InstructionLimit && self.execution_hooks.borrow().depth_reached() {
+            return StopReason::DepthReached;
+        }
+
+        if reason == StopReason::InstructionLimit {
+            return match self.limit_policy {
+                LimitPolicy::Stop => StopReason::InstructionLimit,
+                LimitPolicy::Pause => StopReason::LimitPaused,
+                LimitPolicy::Extend if self.limit_extended => StopReason::LimitExtended,
+                LimitPolicy::Extend => StopReason::InstructionLimit,
+            };
+        }
+
+        return reason;
+    }
+
+    // Instructions executed since the emulator was created, across all runs.
+    pub fn get_instruction_count(&self) -> u64 {
+        return self.vm.cpu.icount;
+    }
+
+    pub fn get_arch_info(&self) -> ArchInfo {
+        let triple = &self.vm.cpu.arch.triple;
+        let mut info = ArchInfo {
+            pointer_size: triple
+                .pointer_width()
+                .map_or(0, |width| width.bytes() as u32),
+            endianness: match triple.endianness() {
+                Ok(target_lexicon::Endianness::Big) => ENDIANNESS_BIG,
+                _ => ENDIANNESS_LITTLE,
+            },
+            ..Default::default()
         };
 
-        loop {
-            self.vm.cpu.block_id = u64::MAX;
-            self.vm.cpu.block_offset = 0;
-            self.vm.cpu.pending_exception = None;
-            self.vm.cpu.exception.clear();
-            *self.stop.borrow_mut() = false;
+        let name = triple.architecture.to_string();
+        let length = std::cmp::min(name.len(), info.name.len() - 1);
+        info.name[..length].copy_from_slice(&name.as_bytes()[..length]);
+
+        return info;
+    }
+
+    // The PC as tracked by the CPU, which is what runs resume from.
+    pub fn get_pc(&self) -> u64 {
+        return self.vm.cpu.read_pc();
+    }
+
+    pub fn set_pc(&mut self, pc: u64) {
+        self.vm.cpu.write_pc(pc);
+    }
+
+    pub fn set_limit_policy(&mut self, policy: LimitPolicy) {
+        self.limit_policy = policy;
+    }
+
+    pub fn set_limit_callback(&mut self, callback: Box<dyn Fn(u64) -> u64>) {
+        self.limit_callback = Some(callback);
+    }
+
+    fn extend_limit(&mut self) -> bool {
+        if self.limit_policy != LimitPolicy::Extend || self.limit_extended {
+            return false;
+        }
+
+        // Explicit stops and deadlines also exit through the instruction limit.
+        if *self.stop.borrow() {
+            return false;
+        }
+
+        let extra = match &self.limit_callback {
+            Some(callback) => callback(self.vm.cpu.icount),
+            None => 0,
+        };
+
+        if extra == 0 {
+            return false;
+        }
+
+        self.limit_extended = true;
+        self.vm.icount_limit = self.vm.cpu.icount.saturating_add(extra);
+        return true;
+    }
+
+    // For internal runs split into pieces, where reaching the end of a piece is not the
+    // caller's instruction limit and must not pause or ask the limit callback.
+    pub fn start_ignoring_limit_policy(&mut self, count: u64) -> StopReason {
+        let policy = std::mem::replace(&mut self.limit_policy, LimitPolicy::Stop);
+        let reason = self.start(count);
+        self.limit_policy = policy;
+
+        return reason;
+    }
+
+    // Executes a single instruction. Iterations of a rep-prefixed string instruction count
+    // as separate instructions unless STEP_OVER_REP is set, in which case the whole
+    // instruction runs without an instruction limit, however large RCX is. A completed step
+    // reports StopReason::InstructionLimit, the limit policy does not apply.
+    pub fn step(&mut self, flags: u32) -> StopReason {
+        let pc = self.vm.cpu.read_pc();
+
+        if (flags & STEP_OVER_REP) == 0 {
+            return self.start_ignoring_limit_policy(1);
+        }
+
+        let bytes = self.read_instruction_bytes(pc);
+        let next = match self.decode_instruction(pc) {
+            Ok(instruction) if decode::is_rep_string(&bytes) => pc + instruction.length,
+            _ => return self.start_ignoring_limit_policy(1),
+        };
+
+        let mut hit: u64 = 0;
+        let reason = self.run_until_any(&[next], 0, &mut hit);
+        if reason == StopReason::Breakpoint && hit == next {
+            return StopReason::InstructionLimit;
+        }
+
+        return reason;
+    }
+
+    // Executes at most `count` instructions through the instruction limit of the VM. Stops
+    // early on breakpoints, exceptions and other stop conditions, StopReason::InstructionLimit
+    // means all of them ran. The limit policy does not apply.
+    pub fn step_count(&mut self, count: u64, executed: &mut u64) -> StopReason {
+        if count == 0 {
+            *executed = 0;
+            return StopReason::InstructionLimit;
+        }
+
+        let start_icount = self.vm.cpu.icount;
+        let reason = self.start_ignoring_limit_policy(count);
+
+        *executed = self.vm.cpu.icount - start_icount;
+        return reason;
+    }
+
+    // Runs without an instruction limit until `millis` of wall-clock time have passed. The
+    // deadline is checked at block boundaries, so a run may overshoot by one block.
+    pub fn run_with_deadline(&mut self, millis: u64, executed: &mut u64) -> StopReason {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_millis(millis);
+        let start_icount = self.vm.cpu.icount;
+
+        self.execution_hooks
+            .borrow_mut()
+            .set_deadline(Some(deadline));
+        let reason = self.start(0);
+        self.execution_hooks.borrow_mut().set_deadline(None);
+
+        *executed = self.vm.cpu.icount - start_icount;
+        return reason;
+    }
+
+    // Number of calls minus returns executed since the emulator was created. It goes
+    // negative when returning out of the frame execution started in.
+    pub fn get_call_depth(&self) -> i64 {
+        return self.execution_hooks.borrow().call_depth();
+    }
+
+    // Runs until a call or return brings the call depth to `target_depth`, stopping before
+    // the first instruction at the new depth. Step out is `get_call_depth() - 1`.
+    pub fn run_until_depth(&mut self, target_depth: i64, max_instructions: u64) -> StopReason {
+        if self.get_call_depth() == target_depth {
+            return StopReason::DepthReached;
+        }
+
+        self.execution_hooks
+            .borrow_mut()
+            .set_depth_target(Some(target_depth));
+        let reason = self.start(max_instructions);
+        self.execution_hooks.borrow_mut().set_depth_target(None);
+
+        return reason;
+    }
+
+    fn has_breakpoint_at(&self, address: u64) -> bool {
+        return self
+            .breakpoints
+            .values()
+            .any(|breakpoint| breakpoint.address == address);
+    }
+
+    // Runs stop with StopReason::Breakpoint before executing `address`. `flags` combines the
+    // BREAKPOINT_* constants, temporary breakpoints are removed once they are hit. Several
+    // breakpoints may share an address, the one added first is reported as hit.
+    pub fn add_breakpoint(&mut self, address: u64, flags: u32) -> u32 {
+        let breakpoint_id = next_hook_id(self.breakpoint_id, |id| {
+            let id = qualify_hook_id(id, HookType::Breakpoint);
+            return self.breakpoints.contains_key(&id);
+        });
+
+        let breakpoint_id = match breakpoint_id {
+            Some(breakpoint_id) => breakpoint_id,
+            None => return 0,
+        };
+
+        self.vm.add_breakpoint(address);
+
+        self.breakpoint_id = breakpoint_id;
+        let id = qualify_hook_id(breakpoint_id, HookType::Breakpoint);
+        self.breakpoints.insert(
+            id,
+            Breakpoint {
+                address,
+                flags,
+                tag: 0,
+            },
+        );
+
+        return id;
+    }
+
+    pub fn remove_breakpoint(&mut self, id: u32) -> bool {
+        let breakpoint = match self.breakpoints.remove(&id) {
+            Some(breakpoint) => breakpoint,
+            None => return false,
+        };
+
+        let address = breakpoint.address;
+        if !self.has_breakpoint_at(address) && self.exit_address != Some(address) {
+            self.vm.remove_breakpoint(address);
+        }
+
+        return true;
+    }
+
+    // (id, address, flags) of every breakpoint, ordered by id.
+    pub fn list_breakpoints(&self) -> Vec<(u32, u64, u32)> {
+        return self
+            .breakpoints
+            .iter()
+            .map(|(id, breakpoint)| (*id, breakpoint.address, breakpoint.flags))
+            .collect();
+    }
+
+    // Id and address of the breakpoint the last run stopped on.
+    pub fn get_breakpoint_hit(&self) -> Option<(u32, u64)> {
+        return self.breakpoint_hit;
+    }
+
+    fn record_breakpoint_hit(&mut self) {
+        let pc = self.vm.cpu.read_pc();
+        let hit = self
+            .breakpoints
+            .iter()
+            .find(|(_id, breakpoint)| breakpoint.address == pc)
+            .map(|(id, breakpoint)| (*id, breakpoint.flags));
+
+        let (id, flags) = match hit {
+            Some(hit) => hit,
+            None => return,
+        };
+
+        self.breakpoint_hit = Some((id, pc));
+
+        if (flags & BREAKPOINT_TEMPORARY) != 0 {
+            self.remove_breakpoint(id);
+        }
+    }
+
+    pub fn set_exit_address(&mut self, address: Option<u64>) {
+        if let Some(previous) = self.exit_address
+            && !self.has_breakpoint_at(previous)
+        {
+            self.vm.remove_breakpoint(previous);
+        }
+
+        if let Some(exit) = address {
+            self.vm.add_breakpoint(exit);
+        }
+
+        self.exit_address = address;
+    }
+
+    // Calls `function` using the SysV calling convention, returning to the exit address.
+    pub fn call(
+        &mut self,
+        function: u64,
+        args: &[u64],
+        max_instructions: u64,
+        result: &mut u64,
+    ) -> Option<StopReason> {
+        const ARGUMENT_REGISTERS: [registers::X86Register; 6] = [
+            registers::X86Register::Rdi,
+            registers::X86Register::Rsi,
+            registers::X86Register::Rdx,
+            registers::X86Register::Rcx,
+            registers::X86Register::R8,
+            registers::X86Register::R9,
+        ];
+
+        let exit_address = self.exit_address?;
+        if args.len() > ARGUMENT_REGISTERS.len() {
+            return None;
+        }
+
+        let rsp = self.read_register_value(registers::X86Register::Rsp);
+        let return_slot = (rsp.wrapping_sub(signal::RED_ZONE_SIZE) & !15).wrapping_sub(8);
+
+        if !self.write_memory(return_slot, &exit_address.to_le_bytes()) {
+            return None;
+        }
+
+        for (reg, value) in ARGUMENT_REGISTERS.iter().zip(args.iter()) {
+            self.write_register_value(*reg, *value);
+        }
+
+        self.write_register_value(registers::X86Register::Rsp, return_slot);
+        self.vm.cpu.write_pc(function);
+
+        let reason = self.start(max_instructions);
+        if reason == StopReason::HarnessExit {
+            *result = self.read_register_value(registers::X86Register::Rax);
+        }
+
+        return Some(reason);
+    }
+
+    // Runs until `address` is about to execute again. When the PC is already there, that
+    // instruction executes first. Reaching `address` counts even if it is also the exit
+    // address. RunUntilStatus::Stopped covers every other stop reason, e.g. other
+    // breakpoints, syscalls or stop requests.
+    pub fn run_until(&mut self, address: u64, max_instructions: u64) -> RunUntilStatus {
+        let mut hit: u64 = 0;
+        let reason = self.run_until_any(&[address], max_instructions, &mut hit);
+
+        return self.run_until_status(reason, address);
+    }
+
+    fn run_until_status(&self, reason: StopReason, address: u64) -> RunUntilStatus {
+        let at_address = self.vm.cpu.read_pc() == address;
+
+        return match reason {
+            StopReason::Breakpoint | StopReason::HarnessExit if at_address => {
+                RunUntilStatus::Reached
+            }
+            StopReason::InstructionLimit if at_address => RunUntilStatus::Reached,
+            StopReason::InstructionLimit | StopReason::LimitPaused => {
+                RunUntilStatus::InstructionLimit
+            }
+            StopReason::Exception => RunUntilStatus::Exception,
+            _ => RunUntilStatus::Stopped,
+        };
+    }
+
+    // Runs until one of `targets` is about to execute and reports it as a breakpoint hit.
+    // When the PC is already on a target, that instruction executes first.
+    pub fn run_until_any(
+        &mut self,
+        targets: &[u64],
+        max_instructions: u64,
+        hit: &mut u64,
+    ) -> StopReason {
+        let mut remaining = max_instructions;
+
+        if targets.contains(&self.vm.cpu.read_pc()) {
+            let reason = self.start_ignoring_limit_policy(1);
+            if reason != StopReason::InstructionLimit {
+                return reason;
+            }
+
+            let pc = self.vm.cpu.read_pc();
+            if targets.contains(&pc) {
+                *hit = pc;
+                return StopReason::Breakpoint;
+            }
+
+            if remaining == 1 {
+                return StopReason::InstructionLimit;
+            }
+
+            remaining = remaining.saturating_sub(1);
+        }
+
+        let mut installed = Vec::new();
+
+        for target in targets {
+            if self.vm.add_breakpoint(*target) {
+                installed.push(*target);
+            }
+        }
+
+        self.prepare_run(remaining);
+        let exit = self.run_loop();
+        let reason = self.stop_reason(exit);
+
+        for target in installed {
+            self.vm.remove_breakpoint(target);
+        }
+
+        let pc = self.vm.cpu.read_pc();
+        if reason == StopReason::Breakpoint && targets.contains(&pc) {
+            *hit = pc;
+        }
+
+        return reason;
+    }
+
+    fn prepare_run(&mut self, count: u64) {
+        self.executing_thread = std::thread::current().id();
+        self.limit_extended = false;
+        self.fault_instruction = None;
+        self.exit_exception = None;
+        self.breakpoint_hit = None;
+
+        self.flush_code_cache_over_limit();
+
+        self.vm.icount_limit = match count {
+            0 => u64::MAX,
+            _ => self.vm.cpu.icount.saturating_add(count),
+        };
+    }
+
+    // Shared by every way of running the emulator, so the state describing how the run
+    // ended is the same for all of them.
+    fn run_loop(&mut self) -> icicle_vm::VmExit {
+        let exit = self.run_until_exit();
+
+        if let icicle_vm::VmExit::UnhandledException(exception) = exit {
+            self.exit_exception = Some(exception);
+        }
+
+        // Also when a run_until_any target or the exit address shares the address, a user
+        // breakpoint there has been hit and a temporary one is consumed.
+        if matches!(exit, icicle_vm::VmExit::Breakpoint) {
+            self.record_breakpoint_hit();
+        }
+
+        return exit;
+    }
+
+    fn run_until_exit(&mut self) -> icicle_vm::VmExit {
+        loop {
+            self.vm.cpu.block_id = u64::MAX;
+            self.vm.cpu.block_offset = 0;
+            self.vm.cpu.pending_exception = None;
+            self.vm.cpu.exception.clear();
+            *self.stop.borrow_mut() = false;
+
+            let reason = self.vm.run();
+
+            match reason {
+                icicle_vm::VmExit::UnhandledException((code, value)) => {
+                    let continue_execution = self.handle_exception(code, value);
+                    if !continue_execution {
+                        if code != ExceptionCode::Syscall {
+                            self.capture_fault_instruction();
+                        }
+
+                        return reason;
+                    }
+                }
+                icicle_vm::VmExit::Interrupted => {
+                    self.vm
+                        .interrupt_flag
+                        .store(false, std::sync::atomic::Ordering::Relaxed);
+
+                    let stop_requested = self
+                        .stop_requested
+                        .load(std::sync::atomic::Ordering::Relaxed);
+
+                    let flushed = self.flush_code_cache_over_limit();
+                    let dispatched = self.dispatch_device_interrupts();
+                    if !(dispatched || flushed)
+                        || self.doorbell_exit.get()
+                        || self.watchpoint_exit.get()
+                        || stop_requested
+                    {
+                        return reason;
+                    }
+                }
+                icicle_vm::VmExit::InstructionLimit => {
+                    if !self.extend_limit() {
+                        return reason;
+                    }
+                }
+                _ => return reason,
+            };
+        }
+    }
+
+    fn capture_fault_instruction(&mut self) {
+        let address = self.vm.cpu.read_pc();
+        let bytes = self.read_instruction_bytes(address);
+
+        let mut fault = decode::FaultInsn {
+            address,
+            status: decode::DecodeStatus::Success as i32,
+            length: bytes.len() as u32,
+            bytes: [0u8; decode::MAX_INSTRUCTION_SIZE],
+            disasm: [0u8; decode::DISASM_SIZE],
+        };
+
+        fault.bytes[..bytes.len()].copy_from_slice(&bytes);
+
+        match self.decode_instruction(address) {
+            Ok(instruction) => decode::copy_string(&mut fault.disasm, &instruction.disasm),
+            Err(status) => fault.status = status as i32,
+        }
+
+        self.fault_instruction = Some(fault);
+    }
+
+    pub fn get_fault_instruction(&self) -> Option<&decode::FaultInsn> {
+        return self.fault_instruction.as_ref();
+    }
+
+    fn dispatch_device_interrupts(&mut self) -> bool {
+        let mut dispatched = false;
+
+        for i in 0..self.timers.len() {
+            let vector = self.timers[i].device.borrow_mut().take_pending();
+            if let Some(code) = vector {
+                self.handle_interrupt(code);
+                dispatched = true;
+            }
+        }
+
+        return dispatched;
+    }
+
+    // Exception the last run stopped on and its value, usually the faulting address.
+    pub fn get_exit_exception(&self) -> (ExitException, u64) {
+        return match self.exit_exception {
+            Some((code, value)) => (map_exception_code(code), value),
+            None => (ExitException::None, 0),
+        };
+    }
+
+    pub fn has_pending_exception(&self) -> bool {
+        let cpu = &self.vm.cpu;
+        return cpu.exception.code != ExceptionCode::None as u32 || cpu.pending_exception.is_some();
+    }
+
+    // start() clears the exception state itself before running, so clearing is only required
+    // when the host wants to observe an acknowledged state between runs.
+    pub fn clear_exception(&mut self) {
+        self.vm.cpu.exception.clear();
+        self.vm.cpu.pending_exception = None;
+    }
+
+    fn handle_interrupt(&self, code: i32) -> bool {
+        for func in self.interrupt_hooks.callbacks() {
+            func(code);
+        }
+
+        return true;
+    }
+
+    fn handle_exception(&mut self, code: ExceptionCode, value: u64) -> bool {
+        let continue_execution = match code {
+            ExceptionCode::Syscall => self.handle_syscall(value),
+            ExceptionCode::ReadPerm => self.handle_violation(value, FOREIGN_READ, false),
+            ExceptionCode::WritePerm => self.handle_violation(value, FOREIGN_WRITE, false),
+            ExceptionCode::ReadUnmapped => self.handle_violation(value, FOREIGN_READ, true),
+            ExceptionCode::WriteUnmapped => self.handle_violation(value, FOREIGN_WRITE, true),
+            ExceptionCode::SoftwareBreakpoint => self.handle_interrupt(3),
+            ExceptionCode::InvalidInstruction => self.handle_port_io() || self.handle_interrupt(6),
+            ExceptionCode::UnimplementedOp => {
+                self.handle_port_io() || self.handle_sysret() || self.handle_invlpg()
+            }
+            ExceptionCode::DivisionException => self.handle_interrupt(0),
+            ExceptionCode::ReadUninitialized => self.handle_uninit_read(value),
+            _ => false,
+        };
+
+        if continue_execution || code == ExceptionCode::Syscall {
+            return continue_execution;
+        }
+
+        return self.handle_unhandled_exception(code, value);
+    }
+
+    // Execution resumes at the faulting instruction if every exception hook returns true.
+    fn handle_unhandled_exception(&mut self, code: ExceptionCode, value: u64) -> bool {
+        if self.exception_hooks.is_empty() {
+            return false;
+        }
+
+        let exception = map_exception_code(code);
+        let mut continue_execution = true;
+
+        for func in self.exception_hooks.callbacks() {
+            continue_execution &= func(exception, value);
+        }
+
+        return continue_execution;
+    }
+
+    // The bytes read by the faulting access are marked as initialized, keeping their value,
+    // so the read succeeds when it is retried. Later reads of them are not reported. The
+    // access size is the largest load of the instruction at the PC. If it cannot be decoded,
+    // MAX_ACCESS_SIZE bytes are initialized so that the retry cannot fault again forever.
+    fn handle_uninit_read(&mut self, address: u64) -> bool {
+        const MAX_ACCESS_SIZE: u64 = 64;
+
+        if self.uninit_read_hooks.is_empty() {
+            return false;
+        }
+
+        for func in self.uninit_read_hooks.callbacks() {
+            func(address);
+        }
+
+        let pc = self.vm.cpu.read_pc();
+        let size = match self.decode_instruction(pc) {
+            Ok(instruction) if instruction.load_size != 0 => instruction.load_size,
+            _ => MAX_ACCESS_SIZE,
+        };
+
+        let mem = self.get_mem();
+        for offset in 0..size {
+            let byte_address = address.wrapping_add(offset);
+            let mut byte = [0u8; 1];
+
+            let initialized = mem
+                .read_bytes(byte_address, &mut byte, icicle_vm::cpu::mem::perm::NONE)
+                .and_then(|_| {
+                    mem.write_bytes(byte_address, &byte, icicle_vm::cpu::mem::perm::NONE)
+                });
+
+            if initialized.is_err() {
+                break;
+            }
+        }
+
+        return true;
+    }
+
+    fn handle_violation(&mut self, address: u64, permission: u8, unmapped: bool) -> bool {
+        if self.violation_hooks.is_empty() {
+            return false;
+        }
+
+        let mut continue_execution = true;
+
+        for func in self.violation_hooks.callbacks() {
+            continue_execution &= func(address, permission, unmapped);
+        }
+
+        return continue_execution;
+    }
+
+    fn handle_port_io(&mut self) -> bool {
+        if self.port_handler.is_none() {
+            return false;
+        }
+
+        let pc = self.vm.cpu.read_pc();
+        let bytes = self.read_instruction_bytes(pc);
+
+        let access = match port::parse(&bytes) {
+            Some(access) => access,
+            None => return false,
+        };
+
+        let port = match access.immediate {
+            Some(immediate) => immediate,
+            None => self.read_register_value(registers::X86Register::Rdx) as u16,
+        };
+
+        let mask = u64::MAX >> (64 - access.width as u32 * 8);
+        let rax = self.read_register_value(registers::X86Register::Rax);
+        let handler = self.port_handler.as_ref().unwrap();
+
+        if access.write {
+            (handler.write)(port, access.width, (rax & mask) as u32);
+        } else {
+            let value = (handler.read)(port, access.width) as u64 & mask;
+
+            // 32-bit results zero-extend into RAX, narrower ones preserve the upper bits.
+            let result = match access.width {
+                4 => value,
+                _ => (rax & !mask) | value,
+            };
+
+            self.write_register_value(registers::X86Register::Rax, result);
+        }
+
+        self.vm.cpu.write_pc(pc + access.length);
+        return true;
+    }
+
+    fn handle_syscall(&mut self, value: u64) -> bool {
+        if value != 0 {
+            return self.handle_interrupt(value as i32);
+        }
+
+        if self.intercept_syscalls {
+            return false;
+        }
+
+        let pc = self.vm.cpu.read_pc();
+
+        if self.syscall_mode == SyscallMode::Emulate {
+            return self.emulate_syscall(pc);
+        }
+
+        // Hooks may add or remove syscall hooks through the emulator. The container is not
+        // borrowed while they run, hooks removed by an earlier one are skipped and hooks
+        // added meanwhile run from the next syscall on.
+        let mut action = SyscallAction::Continue;
+        for (id, func) in self.syscall_hooks.snapshot() {
+            if !self.syscall_hooks.is_installed(id, &func) {
+                continue;
+            }
+
+            let result = func(self);
+            if result > action {
+                action = result;
+            }
+        }
+
+        // Hooks that redirect execution (e.g. rt_sigreturn) must not be advanced past.
+        if action != SyscallAction::Handled && self.vm.cpu.read_pc() == pc {
+            let length = self.syscall_length(pc);
+            self.vm.cpu.write_pc(pc + length);
+        }
+
+        return action != SyscallAction::Stop;
+    }
+
+    // SYSCALL, SYSENTER and INT 0x80 are 2 bytes, but prefixes make them longer.
+    fn syscall_length(&mut self, pc: u64) -> u64 {
+        return match self.decode_instruction(pc) {
+            Ok(instruction) => instruction.length,
+            Err(_) => 2,
+        };
+    }
+
+    // Hardware does not switch stacks on SYSCALL, the kernel entry code loads its stack
+    // itself (usually after SWAPGS), so RSP is left untouched.
+    fn emulate_syscall(&mut self, pc: u64) -> bool {
+        if (self.read_msr(msr::IA32_EFER) & msr::EFER_SCE) == 0 {
+            return self.handle_interrupt(6);
+        }
+
+        let star = self.read_msr(msr::IA32_STAR);
+        let (cs, ss) = msr::syscall_selectors(star);
+        let flags = self.reg.get_flags(&mut self.vm.cpu);
+        let mask = self.read_msr(msr::IA32_FMASK);
+
+        let length = self.syscall_length(pc);
+        self.write_register_value(registers::X86Register::Rcx, pc + length);
+        self.write_register_value(registers::X86Register::R11, flags);
+        self.reg
+            .set_flags(&mut self.vm.cpu, flags & !mask & !msr::RFLAGS_RF);
+        self.write_register_value(registers::X86Register::Cs, cs as u64);
+        self.write_register_value(registers::X86Register::Ss, ss as u64);
+
+        let entry = self.read_msr(msr::IA32_LSTAR);
+        self.vm.cpu.write_pc(entry);
+        return true;
+    }
+
+    // ia.sinc lifts INVLPG to the `invlpg()` user op, which raises UnimplementedOp. Guest
+    // memory is never translated through the page tables, so there is nothing to
+    // invalidate and the instruction is skipped.
+    fn handle_invlpg(&mut self) -> bool {
+        let pc = self.vm.cpu.read_pc();
+
+        let length = match self.decode_instruction(pc) {
+            Ok(instruction) if instruction.mnemonic() == "INVLPG" => instruction.length,
+            _ => return false,
+        };
+
+        self.vm.cpu.write_pc(pc + length);
+        return true;
+    }
+
+    // ia.sinc lifts SYSRET to the `sysret()` user op followed by `RIP=RCX`. The op raises
+    // UnimplementedOp before the assignment executes, so every SYSRET ends up here and the
+    // whole instruction is performed by this handler. Execution resumes at the new PC, so
+    // the pending `RIP=RCX` never runs and cannot undo the 32-bit truncation of the target.
+    fn handle_sysret(&mut self) -> bool {
+        if self.syscall_mode != SyscallMode::Emulate {
+            return false;
+        }
+
+        let pc = self.vm.cpu.read_pc();
+        let bytes = self.read_instruction_bytes(pc);
+
+        let long_mode = match msr::parse_sysret(&bytes) {
+            Some(long_mode) => long_mode,
+            None => return false,
+        };
+
+        let star = self.read_msr(msr::IA32_STAR);
+        let (cs, ss) = msr::sysret_selectors(star, long_mode);
+        let r11 = self.read_register_value(registers::X86Register::R11);
+        let mut target = self.read_register_value(registers::X86Register::Rcx);
+
+        if !long_mode {
+            target &= 0xFFFF_FFFF;
+        }
+
+        self.reg.set_flags(
+            &mut self.vm.cpu,
+            (r11 & msr::SYSRET_FLAGS_MASK) | msr::RFLAGS_FIXED,
+        );
+        self.write_register_value(registers::X86Register::Cs, cs as u64);
+        self.write_register_value(registers::X86Register::Ss, ss as u64);
+
+        self.vm.cpu.write_pc(target);
+        return true;
+    }
+
+    // Records the PC and general purpose register changes of every executed instruction,
+    // at most `max_entries` of them (0 for no limit).
+    pub fn start_trace(&mut self, max_entries: usize) {
+        let nodes = trace::TRACE_REGISTERS.map(|(reg, _)| self.reg.get_node(reg));
+        self.execution_hooks
+            .borrow_mut()
+            .start_trace(nodes, max_entries);
+    }
+
+    pub fn stop_trace(&mut self) {
+        self.execution_hooks.borrow_mut().stop_trace();
+    }
+
+    pub fn get_trace_length(&self) -> usize {
+        let hooks = self.execution_hooks.borrow();
+        return hooks.trace().map_or(0, |recorder| recorder.len());
+    }
+
+    // Writes the recorded trace to `path` in one of the trace::TRACE_FORMAT_* formats.
+    pub fn export_trace(&self, path: &str, format: i32) -> bool {
+        let hooks = self.execution_hooks.borrow();
+
+        let data = match hooks.trace().and_then(|recorder| recorder.export(format)) {
+            Some(data) => data,
+            None => return false,
+        };
+
+        return std::fs::write(path, data).is_ok();
+    }
+
+    // Updates the caller's coverage::COVERAGE_BLOCK or COVERAGE_EDGE bitmap of `map_size`
+    // bytes in place while running, coverage::COVERAGE_DISABLED stops using it. `map` must
+    // stay valid until coverage is disabled or the emulator is destroyed.
+    pub unsafe fn enable_coverage(&mut self, mode: i32, map: *mut u8, map_size: usize) -> bool {
+        let coverage = if mode == coverage::COVERAGE_DISABLED {
+            None
+        } else {
+            match unsafe { coverage::CoverageMap::new(mode, map, map_size) } {
+                Some(coverage) => Some(coverage),
+                None => return false,
+            }
+        };
+
+        self.execution_hooks.borrow_mut().set_coverage(coverage);
+        return true;
+    }
+
+    // Copies at most `buffer.len()` bytes of the map and returns the full map size.
+    pub fn get_coverage(&self, buffer: &mut [u8]) -> usize {
+        let mut hooks = self.execution_hooks.borrow_mut();
+        let map = match hooks.coverage() {
+            Some(coverage) => coverage.map(),
+            None => return 0,
+        };
+
+        let length = std::cmp::min(buffer.len(), map.len());
+        buffer[..length].copy_from_slice(&map[..length]);
+        return map.len();
+    }
+
+    pub fn reset_coverage(&mut self) {
+        if let Some(coverage) = self.execution_hooks.borrow_mut().coverage() {
+            coverage.reset();
+        }
+    }
+
+    pub fn set_syscall_mode(&mut self, mode: SyscallMode) {
+        self.syscall_mode = mode;
+    }
+
+    // FS_BASE and GS_BASE are backed by the FsBase/GsBase registers.
+    pub fn read_msr(&self, index: u32) -> u64 {
+        return match index {
+            msr::IA32_FS_BASE => self.read_register_value(registers::X86Register::FsBase),
+            msr::IA32_GS_BASE => self.read_register_value(registers::X86Register::GsBase),
+            _ => *self.msrs.get(&index).unwrap_or(&0),
+        };
+    }
+
+    pub fn write_msr(&mut self, index: u32, value: u64) {
+        match index {
+            msr::IA32_FS_BASE => self.write_register_value(registers::X86Register::FsBase, value),
+            msr::IA32_GS_BASE => self.write_register_value(registers::X86Register::GsBase, value),
+            _ => {
+                self.msrs.insert(index, value);
+            }
+        }
+    }
+
+    // Returns the base and limit of the table.
+    pub fn get_descriptor_table(&mut self, table: DescriptorTable) -> (u64, u32) {
+        let mut data = [0u8; 16];
+        self.read_generic_register(table.register(), &mut data);
+
+        let limit = u32::from_le_bytes(data[0..4].try_into().unwrap());
+        let base = u64::from_le_bytes(data[4..12].try_into().unwrap());
+        return (base, limit);
+    }
+
+    pub fn set_descriptor_table(&mut self, table: DescriptorTable, base: u64, limit: u32) -> bool {
+        if limit > table.max_limit() {
+            return false;
+        }
+
+        let reg = table.register();
+
+        let mut data = [0u8; 16];
+        let size = self.read_generic_register(reg, &mut data);
+
+        data[0..4].copy_from_slice(&limit.to_le_bytes());
+        data[4..12].copy_from_slice(&base.to_le_bytes());
+
+        self.write_generic_register(reg, &data[..size]);
+        return true;
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        return StopHandle {
+            requested: self.stop_requested.clone(),
+            interrupt_flag: self.vm.interrupt_flag.clone(),
+        };
+    }
+
+    // Unlike stop, this only touches atomics and may be called while another thread runs
+    // the emulator.
+    pub fn request_stop(&self) {
+        self.stop_handle().request_stop();
+    }
+
+    pub fn stop(&mut self) {
+        self.vm.icount_limit = 0;
+
+        if self.executing_thread == std::thread::current().id() {
+            *self.stop.borrow_mut() = true;
+        }
+    }
+
+    pub fn add_block_hook(&mut self, callback: Box<dyn Fn(u64, u64)>) -> u32 {
+        let hook_id = self.execution_hooks.borrow_mut().add_block_hook(callback);
+        return qualify_hook_id(hook_id, HookType::Block);
+    }
+
+    // Called for exceptions that would end the run, after the built-in handling and the
+    // violation hooks. Syscalls are reported to the syscall hooks instead.
+    pub fn add_exception_hook(&mut self, callback: Box<dyn Fn(ExitException, u64) -> bool>) -> u32 {
+        let hook_id = self.exception_hooks.add_hook(callback);
+        return qualify_hook_id(hook_id, HookType::Exception);
+    }
+
+    pub fn add_violation_hook(&mut self, callback: Box<dyn Fn(u64, u8, bool) -> bool>) -> u32 {
+        let hook_id = self.violation_hooks.add_hook(callback);
+        return qualify_hook_id(hook_id, HookType::Violation);
+    }
+
+    pub fn add_wx_violation_hook(&mut self, callback: Box<dyn Fn(u64, u64, u8)>) -> u32 {
+        let hook_id = self.wx_hooks.add_hook(callback);
+        return qualify_hook_id(hook_id, HookType::WxViolation);
+    }
+
+    pub fn add_execution_hook(&mut self, address: u64, callback: Box<dyn Fn(u64)>) -> u32 {
+        let hook_id = self
+            .execution_hooks
+            .borrow_mut()
+            .add_specific_hook(address, callback);
+        return qualify_hook_id(hook_id, HookType::ExecuteSpecific);
+    }
+
+    // Unlike a breakpoint, reaching a tracepoint never stops the run. The callback
+    // receives the address and the instruction count before the instruction executes.
+    pub fn add_tracepoint(&mut self, address: u64, callback: Box<dyn Fn(u64, u64)>) -> u32 {
+        let hook_id = self
+            .execution_hooks
+            .borrow_mut()
+            .add_tracepoint(address, callback);
+        return qualify_hook_id(hook_id, HookType::Tracepoint);
+    }
+
+    pub fn add_generic_execution_hook(&mut self, callback: Box<dyn Fn(u64)>) -> u32 {
+        let hook_id = self.execution_hooks.borrow_mut().add_generic_hook(callback);
+        return qualify_hook_id(hook_id, HookType::ExecuteGeneric);
+    }
+
+    // Only code translated after registration is instrumented, so the code cache is flushed.
+    pub fn add_register_read_hook(
+        &mut self,
+        reg: registers::X86Register,
+        callback: Box<dyn Fn(u64)>,
+    ) -> u32 {
+        if !self.reg.is_available(reg) {
+            return 0;
+        }
+
+        let node = self.reg.get_node(reg);
+        let hook_id = self
+            .execution_hooks
+            .borrow_mut()
+            .add_register_read_hook(node, callback);
+
+        self.flush_code_cache();
+        return qualify_hook_id(hook_id, HookType::RegisterRead);
+    }
+
+    fn flush_code_cache(&mut self) {
+        self.vm.code.flush_code();
+        self.vm.jit.clear();
+        self.translated_ops.set(0);
+    }
+
+    // Execution ignores guest paging: memory is accessed without translation through the
+    // page tables, so CR3 writes and INVLPG change nothing for it. The only state tied to an
+    // address space is translated code. Hosts that switch address spaces by remapping memory
+    // call this to not run blocks translated from the old mappings.
+    pub fn flush_tlb(&mut self) {
+        self.flush_code_cache();
+    }
+
+    // Bounds translated code by the number of p-code ops in its blocks, a proxy for the
+    // memory of the code cache and the JIT, which icicle does not report. icicle cannot
+    // evict single blocks, so there is no LRU eviction: translating past the limit
+    // interrupts the run at the next block boundary, the whole cache is flushed and code is
+    // retranslated on demand. The cache exceeds the limit by at most the last translated
+    // block group. Limits close to the working set of the guest cause repeated
+    // retranslation and slow execution down.
+    pub fn set_code_cache_limit(&mut self, max_ops: Option<u64>) {
+        self.code_cache_limit.set(max_ops);
+    }
+
+    fn flush_code_cache_over_limit(&mut self) -> bool {
+        let over_limit = match self.code_cache_limit.get() {
+            Some(limit) => self.translated_ops.get() > limit,
+            None => false,
+        };
+
+        if over_limit {
+            self.flush_code_cache();
+        }
+
+        return over_limit;
+    }
+
+    // Number of p-code ops translated since the last flush.
+    pub fn get_code_cache_size(&self) -> u64 {
+        return self.translated_ops.get();
+    }
+
+    pub fn set_port_handler(&mut self, handler: PortHandler) {
+        self.port_handler = Some(handler);
+    }
+
+    // Syscall hooks run between instructions, outside of icicle's execution loop, and get
+    // the emulator to read the arguments and write the result.
+    pub fn add_syscall_hook(&mut self, callback: Box<SyscallHook>) -> u32 {
+        let hook_id = self.syscall_hooks.add_hook(callback);
+        return qualify_hook_id(hook_id, HookType::Syscall);
+    }
+
+    pub fn add_interrupt_hook(&mut self, callback: Box<dyn Fn(i32)>) -> u32 {
+        let hook_id = self.interrupt_hooks.add_hook(callback);
+        return qualify_hook_id(hook_id, HookType::Interrupt);
+    }
+
+    // Called with the address of guest reads of mapped memory that was never written. Only
+    // reported for emulators created with uninitialized memory tracking enabled.
+    pub fn add_uninit_read_hook(&mut self, callback: Box<dyn Fn(u64)>) -> u32 {
+        let hook_id = self.uninit_read_hooks.add_hook(callback);
+        return qualify_hook_id(hook_id, HookType::UninitRead);
+    }
+
+    // Memory hook callbacks receive the address of the accessing instruction, the
+    // accessed address and the data. Accesses made by the host report the PC of the last
+    // instruction executed.
+    fn with_current_pc(&self, callback: MemoryAccessHook) -> Box<MemoryHook> {
+        let current_pc = Rc::clone(&self.current_pc);
+
+        return Box::new(MemoryHook {
+            callback: Box::new(move |address: u64, data: &[u8]| {
+                callback(current_pc.get(), address, data);
+            }),
+        });
+    }
+
+    pub fn add_read_hook(&mut self, start: u64, end: u64, callback: MemoryAccessHook) -> u32 {
+        let hook = self.with_current_pc(callback);
+        let id = self.get_mem().add_read_after_hook(start, end, hook);
+        if id.is_none() {
+            return 0;
+        }
+
+        let qualified_id = qualify_hook_id(id.unwrap(), HookType::Read);
+        if qualified_id == 0 {
+            self.get_mem().remove_read_after_hook(id.unwrap());
+        } else {
+            self.memory_hooks.insert(qualified_id, 0);
+        }
+
+        return qualified_id;
+    }
+
+    pub fn add_write_hook(&mut self, start: u64, end: u64, callback: MemoryAccessHook) -> u32 {
+        let hook = self.with_current_pc(callback);
+        let id = self.get_mem().add_write_hook(start, end, hook);
+        if id.is_none() {
+            return 0;
+        }
+
+        let qualified_id = qualify_hook_id(id.unwrap(), HookType::Write);
+        if qualified_id == 0 {
+            self.get_mem().remove_write_hook(id.unwrap());
+        } else {
+            self.memory_hooks.insert(qualified_id, 0);
+        }
+
+        return qualified_id;
+    }
+
+    // Stops the run with StopReason::Watchpoint after a guest write leaves the `size` byte
+    // little-endian value at `address` satisfying `condition`. The callback receives the
+    // address, the value before and the value after the write.
+    pub fn add_watchpoint(
+        &mut self,
+        address: u64,
+        size: usize,
+        condition: watchpoint::WatchCondition,
+        value: u64,
+        callback: Box<dyn Fn(u64, u64, u64)>,
+    ) -> u32 {
+        if size == 0 || size > 8 {
+            return 0;
+        }
+
+        let mut current = [0u8; 8];
+        if !self.read_memory(address, &mut current[..size]) {
+            return 0;
+        }
+
+        let watch = RefCell::new(watchpoint::Watchpoint::new(
+            address, size, condition, value, current,
+        ));
+        let end = watch.borrow().end();
+
+        let watchpoint_exit = Rc::clone(&self.watchpoint_exit);
+        let interrupt_flag = self.vm.interrupt_flag.clone();
+
+        let id = self.get_mem().add_write_hook(
+            address,
+            end,
+            Box::new(MemoryHook {
+                callback: Box::new(move |write_address: u64, data: &[u8]| {
+                    let matched = watch.borrow_mut().on_write(write_address, data);
+
+                    if let Some((old, new)) = matched {
+                        callback(address, old, new);
+                        watchpoint_exit.set(true);
+                        interrupt_flag.store(true, std::sync::atomic::Ordering::Relaxed);
+                    }
+                }),
+            }),
+        );
+
+        let id = match id {
+            Some(id) => id,
+            None => return 0,
+        };
+
+        let qualified_id = qualify_hook_id(id, HookType::Watchpoint);
+        if qualified_id == 0 {
+            self.get_mem().remove_write_hook(id);
+        } else {
+            self.memory_hooks.insert(qualified_id, 0);
+        }
+
+        return qualified_id;
+    }
+
+    // Treats [base, base + size) as uninitialized and reports guest reads of bytes that were
+    // not written since, by the guest or the host. The callback receives the address, the
+    // access size and the offset from RSP at the start of the reading instruction.
+    // Installing a new watch replaces the previous one.
+    pub fn watch_stack_uninit(
+        &mut self,
+        base: u64,
+        size: u64,
+        callback: Box<dyn Fn(u64, usize, i64)>,
+    ) -> u32 {
+        self.remove_stack_watch();
+
+        let end = base.saturating_add(size);
+        let tracker = Rc::new(RefCell::new(stack_tracker::StackTracker::new(base, size)));
+        let stack_pointer = Rc::new(Cell::new(0u64));
+
+        let write_tracker = Rc::clone(&tracker);
+        let write_hook = self.get_mem().add_write_hook(
+            base,
+            end,
+            Box::new(MemoryHook {
+                callback: Box::new(move |address: u64, value: &[u8]| {
+                    write_tracker
+                        .borrow_mut()
+                        .mark_written(address, value.len());
+                }),
+            }),
+        );
+
+        let read_tracker = Rc::clone(&tracker);
+        let read_stack_pointer = Rc::clone(&stack_pointer);
+        let read_hook = self.get_mem().add_read_after_hook(
+            base,
+            end,
+            Box::new(MemoryHook {
+                callback: Box::new(move |address: u64, value: &[u8]| {
+                    if read_tracker.borrow().is_uninitialized(address, value.len()) {
+                        let offset = address.wrapping_sub(read_stack_pointer.get()) as i64;
+                        callback(address, value.len(), offset);
+                    }
+                }),
+            }),
+        );
+
+        let (write_hook, read_hook) = match (write_hook, read_hook) {
+            (Some(write_hook), Some(read_hook)) => (write_hook, read_hook),
+            (write_hook, read_hook) => {
+                if let Some(id) = write_hook {
+                    self.get_mem().remove_write_hook(id);
+                }
+
+                if let Some(id) = read_hook {
+                    self.get_mem().remove_read_after_hook(id);
+                }
+
+                return 0;
+            }
+        };
+
+        let rsp = self.reg.get_node(registers::X86Register::Rsp);
+        self.execution_hooks
+            .borrow_mut()
+            .set_stack_pointer_tracking(Some((rsp, stack_pointer)));
+
+        self.stack_watch = Some(StackWatch {
+            tracker,
+            read_hook,
+            write_hook,
+            tag: 0,
+        });
+
+        return qualify_hook_id(1, HookType::StackUninit);
+    }
+
+    // Records the return address of every call and reports returns to a different address.
+    // The callback receives the address of the return, the expected and the actual target.
+    // Only one return check can be active, installing a new one replaces it.
+    pub fn add_return_check_hook(&mut self, callback: ReturnCheckHook) -> u32 {
+        self.execution_hooks
+            .borrow_mut()
+            .set_return_check(Some(callback));
+        return qualify_hook_id(1, HookType::ReturnCheck);
+    }
+
+    // Current privilege level, taken from the RPL bits of CS.
+    pub fn get_cpl(&self) -> u8 {
+        return (self.read_register_value(registers::X86Register::Cs) & 3) as u8;
+    }
+
+    // The callback receives the old and new privilege level and the address execution
+    // continues at.
+    pub fn add_mode_transition_hook(&mut self, callback: Box<dyn Fn(u8, u8, u64)>) -> u32 {
+        let cs = self.reg.get_node(registers::X86Register::Cs);
+        let cpl = self.get_cpl();
+
+        let hook_id = self
+            .execution_hooks
+            .borrow_mut()
+            .add_mode_hook(cs, cpl, callback);
+        return qualify_hook_id(hook_id, HookType::ModeTransition);
+    }
+
+    fn remove_stack_watch(&mut self) {
+        if let Some(watch) = self.stack_watch.take() {
+            self.get_mem().remove_read_after_hook(watch.read_hook);
+            self.get_mem().remove_write_hook(watch.write_hook);
+            self.execution_hooks
+                .borrow_mut()
+                .set_stack_pointer_tracking(None);
+        }
+    }
+
+    // Calls `access` with the tag stored next to the hook, None if no such hook exists.
+    fn with_hook_tag<R>(&mut self, id: u32, access: impl FnOnce(&mut u64) -> R) -> Option<R> {
+        let (hook_id, hook_type) = split_hook_id(id);
+
+        let tag = match hook_type {
+            HookType::Syscall => self.syscall_hooks.tag_mut(hook_id),
+            HookType::Violation => self.violation_hooks.tag_mut(hook_id),
+            HookType::Interrupt => self.interrupt_hooks.tag_mut(hook_id),
+            HookType::UninitRead => self.uninit_read_hooks.tag_mut(hook_id),
+            HookType::Exception => self.exception_hooks.tag_mut(hook_id),
+            HookType::WxViolation => self.wx_hooks.tag_mut(hook_id),
+            HookType::Breakpoint => self
+                .breakpoints
+                .get_mut(&id)
+                .map(|breakpoint| &mut breakpoint.tag),
+            HookType::Read | HookType::Write | HookType::Watchpoint => {
+                self.memory_hooks.get_mut(&id)
+            }
+            HookType::StackUninit if hook_id == 1 => {
+                self.stack_watch.as_mut().map(|watch| &mut watch.tag)
+            }
+            _ => {
+                let mut hooks = self.execution_hooks.borrow_mut();
+                return hooks.tag_mut(hook_id, hook_type).map(access);
+            }
+        };
+
+        return tag.map(access);
+    }
+
+    // Tags are removed together with the hook. Returns false if no hook has the id.
+    pub fn set_hook_tag(&mut self, id: u32, tag: u64) -> bool {
+        return self.with_hook_tag(id, |slot| *slot = tag).is_some();
+    }
+
+    pub fn get_hook_tag(&mut self, id: u32) -> u64 {
+        return self.with_hook_tag(id, |slot| *slot).unwrap_or(0);
+    }
+
+    pub fn remove_hook(&mut self, id: u32) {
+        let (hook_id, hook_type) = split_hook_id(id);
+
+        match hook_type {
+            HookType::Syscall => self.syscall_hooks.remove_hook(hook_id),
+            HookType::Violation => self.violation_hooks.remove_hook(hook_id),
+            HookType::Interrupt => self.interrupt_hooks.remove_hook(hook_id),
+            HookType::UninitRead => self.uninit_read_hooks.remove_hook(hook_id),
+            HookType::Exception => self.exception_hooks.remove_hook(hook_id),
+            HookType::Breakpoint => {
+                self.remove_breakpoint(id);
+            }
+            HookType::WxViolation => self.wx_hooks.remove_hook(hook_id),
+            HookType::ExecuteGeneric => self
+                .execution_hooks
+                .borrow_mut()
+                .remove_generic_hook(hook_id),
+            HookType::ExecuteSpecific => self
+                .execution_hooks
+                .borrow_mut()
+                .remove_specific_hook(hook_id),
+            HookType::Block => self.execution_hooks.borrow_mut().remove_block_hook(hook_id),
+            HookType::RegisterRead => self
+                .execution_hooks
+                .borrow_mut()
+                .remove_register_read_hook(hook_id),
+            HookType::Tracepoint => self.execution_hooks.borrow_mut().remove_tracepoint(hook_id),
+            HookType::StackUninit => self.remove_stack_watch(),
+            HookType::ReturnCheck => self.execution_hooks.borrow_mut().set_return_check(None),
+            HookType::ModeTransition => self.execution_hooks.borrow_mut().remove_mode_hook(hook_id),
+            HookType::Read => {
+                self.memory_hooks.remove(&id);
+                self.get_mem().remove_read_after_hook(hook_id);
+            }
+            HookType::Write | HookType::Watchpoint => {
+                self.memory_hooks.remove(&id);
+                self.get_mem().remove_write_hook(hook_id);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn set_wx_policy(&mut self, policy: WxPolicy) {
+        self.wx_policy = policy;
+    }
+
+    // Returns false if the requested permissions must be rejected under the current policy.
+    fn check_wx(&self, address: u64, length: u64, permissions: u8) -> bool {
+        const WX: u8 = FOREIGN_WRITE | FOREIGN_EXEC;
+
+        if self.wx_policy == WxPolicy::Allow || (permissions & WX) != WX {
+            return true;
+        }
+
+        for func in self.wx_hooks.callbacks() {
+            func(address, length, permissions);
+        }
+
+        return self.wx_policy != WxPolicy::Deny;
+    }
+
+    pub fn map_memory(&mut self, address: u64, length: u64, permissions: u8) -> bool {
+        return self
+            .map_memory_checked(address, length, permissions)
+            .is_ok();
+    }
+
+    // `address` must be page aligned, `length` is rounded up to whole pages.
+    pub fn map_memory_checked(
+        &mut self,
+        address: u64,
+        length: u64,
+        permissions: u8,
+    ) -> Result<(), MemoryStatus> {
+        const PAGE_MASK: u64 = 0xFFF;
+
+        let length = match length.checked_add(PAGE_MASK) {
+            Some(end) if length != 0 => end & !PAGE_MASK,
+            _ => return Err(MemoryStatus::InvalidLength),
+        };
+
+        if address.checked_add(length).is_none() {
+            return Err(MemoryStatus::InvalidLength);
+        }
+
+        if (address & PAGE_MASK) != 0 {
+            return Err(MemoryStatus::Unaligned);
+        }
+
+        let overlap = self
+            .mapped_regions
+            .find_overlap(address, length)
+            .or_else(|| self.mmio_regions.find_overlap(address, length));
+
+        if overlap.is_some() {
+            return Err(MemoryStatus::Overlap);
+        }
+
+        if !self.check_wx(address, length, permissions) {
+            return Err(MemoryStatus::WxDenied);
+        }
+
+        const MAPPING_PERMISSIONS: u8 = icicle_vm::cpu::mem::perm::MAP
+            | icicle_vm::cpu::mem::perm::INIT
+            | icicle_vm::cpu::mem::perm::IN_CODE_CACHE;
+
+        let native_permissions = map_permissions(permissions);
+
+        let mapping = icicle_vm::cpu::mem::Mapping {
+            perm: native_permissions | MAPPING_PERMISSIONS,
+            value: 0x0,
+        };
+
+        let layout = icicle_vm::cpu::mem::AllocLayout {
+            addr: Some(address),
+            size: length,
+            align: 0x1000,
+        };
+
+        return match self.get_mem().alloc_memory(layout, mapping) {
+            Ok(_) => {
+                self.mapped_regions.add(address, length);
+                Ok(())
+            }
+            Err(icicle_vm::cpu::mem::MemError::OutOfMemory) => Err(MemoryStatus::OutOfMemory),
+            Err(_) => Err(MemoryStatus::MappingFailed),
+        };
+    }
+
+    pub fn map_mmio(
+        &mut self,
+        address: u64,
+        length: u64,
+        read_function: MmioReadHandler,
+        write_function: MmioWriteHandler,
+    ) -> bool {
+        return self
+            .map_mmio_checked(address, length, read_function, write_function)
+            .is_ok();
+    }
+
+    pub fn map_mmio_checked(
+        &mut self,
+        address: u64,
+        length: u64,
+        read_function: MmioReadHandler,
+        write_function: MmioWriteHandler,
+    ) -> Result<(), MmioMapError> {
+        let handler = MmioHandler::new(read_function, write_function, Rc::clone(&self.mmio_log));
+        return self.map_mmio_handler(address, length, handler);
+    }
+
+    // Dispatches accesses within the window to the sub-range containing them.
+    // Accesses outside every sub-range read as zero and discard writes.
+    pub fn map_mmio_ranges(
+        &mut self,
+        address: u64,
+        length: u64,
+        mut ranges: Vec<MmioRange>,
+    ) -> Result<(), MmioMapError> {
+        ranges.sort_by_key(|range| range.offset);
+
+        let invalid_ranges = Err(MmioMapError::new(MemoryStatus::InvalidRanges));
+
+        let mut next_free: u64 = 0;
+        for range in &ranges {
+            if range.length == 0 || range.offset < next_free {
+                return invalid_ranges;
+            }
+
+            next_free = match range.offset.checked_add(range.length) {
+                Some(end) if end <= length => end,
+                _ => return invalid_ranges,
+            };
+        }
+
+        let handler = MmioHandler::with_ranges(address, ranges, Rc::clone(&self.mmio_log));
+        return self.map_mmio_handler(address, length, handler);
+    }
+
+    fn check_mmio_window(&self, address: u64, length: u64) -> Result<(), MmioMapError> {
+        if length == 0 || address.checked_add(length).is_none() {
+            return Err(MmioMapError::new(MemoryStatus::InvalidLength));
+        }
+
+        if (address & 0xFFF) != 0 {
+            return Err(MmioMapError::new(MemoryStatus::Unaligned));
+        }
+
+        let conflict = self
+            .mapped_regions
+            .find_overlap(address, length)
+            .or_else(|| self.mmio_regions.find_overlap(address, length));
+
+        if conflict.is_some() {
+            return Err(MmioMapError {
+                status: MemoryStatus::Overlap,
+                conflict,
+            });
+        }
+
+        return Ok(());
+    }
+
+    fn map_mmio_handler(
+        &mut self,
+        address: u64,
+        length: u64,
+        handler: MmioHandler,
+    ) -> Result<(), MmioMapError> {
+        self.check_mmio_window(address, length)?;
+
+        let mem = self.get_mem();
+        let handler_id = mem.register_io_handler(handler);
+
+        let layout = icicle_vm::cpu::mem::AllocLayout {
+            addr: Some(address),
+            size: length,
+            align: 0x1000,
+        };
+
+        if mem.alloc_memory(layout, handler_id).is_err() {
+            return Err(MmioMapError::new(MemoryStatus::MappingFailed));
+        }
+
+        self.mmio_regions.add(address, length);
+        return Ok(());
+    }
+
+    pub fn attach_timer(&mut self, base: u64, frequency: u64, vector: i32) -> bool {
+        let timer = Rc::new(RefCell::new(timer::TimerDevice::new(frequency, vector)));
+
+        let read_timer = Rc::clone(&timer);
+        let write_timer = Rc::clone(&timer);
+
+        let mapped = self.map_mmio(
+            base,
+            timer::TIMER_SIZE,
+            Box::new(move |addr: u64, data: &mut [u8]| {
+                read_timer.borrow_mut().read(addr - base, data);
+                true
+            }),
+            Box::new(move |addr: u64, data: &[u8]| {
+                write_timer.borrow_mut().write(addr - base, data);
+                true
+            }),
+        );
+
+        if !mapped {
+            return false;
+        }
+
+        let poll_timer = Rc::clone(&timer);
+        let interrupt_flag = self.vm.interrupt_flag.clone();
+
+        let block_hook = self.execution_hooks.borrow_mut().add_block_hook(Box::new(
+            move |_address: u64, _instructions: u64| {
+                if poll_timer.borrow_mut().poll() {
+                    interrupt_flag.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+            },
+        ));
+
+        if block_hook == 0 {
+            self.unmap_memory(base, timer::TIMER_SIZE);
+            return false;
+        }
+
+        self.timers.push(AttachedTimer {
+            base,
+            device: timer,
+            block_hook,
+        });
+
+        return true;
+    }
+
+    // Unmaps the timer attached at `base` and stops polling it.
+    pub fn detach_timer(&mut self, base: u64) -> bool {
+        let index = match self.timers.iter().position(|timer| timer.base == base) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        let timer = self.timers.remove(index);
+        self.execution_hooks
+            .borrow_mut()
+            .remove_block_hook(timer.block_hook);
+        self.unmap_memory(base, timer::TIMER_SIZE);
+
+        return true;
+    }
+
+    // Maps a page at `address`; any guest write to it invokes `callback` with the written value.
+    // With `exit_on_ring`, the current run additionally stops with StopReason::Doorbell.
+    pub fn add_doorbell(
+        &mut self,
+        address: u64,
+        exit_on_ring: bool,
+        callback: Box<dyn Fn(u64, u64)>,
+    ) -> bool {
+        let doorbell_exit = Rc::clone(&self.doorbell_exit);
+        let interrupt_flag = self.vm.interrupt_flag.clone();
+
+        return self.map_mmio(
+            address,
+            0x1000,
+            Box::new(move |_addr: u64, data: &mut [u8]| {
+                data.fill(0);
+                true
+            }),
+            Box::new(move |addr: u64, data: &[u8]| {
+                let mut bytes = [0u8; 8];
+                let len = std::cmp::min(data.len(), bytes.len());
+                bytes[..len].copy_from_slice(&data[..len]);
+
+                callback(addr, u64::from_le_bytes(bytes));
+
+                if exit_on_ring {
+                    doorbell_exit.set(true);
+                    interrupt_flag.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+
+                true
+            }),
+        );
+    }
+
+    // Classifies every page of guest RAM by its permissions and calls `rwx_callback` once
+    // for each contiguous writable and executable range. MMIO ranges are not included.
+    pub fn audit_permissions(&mut self, rwx_callback: &dyn Fn(u64, u64)) -> PermissionAudit {
+        use icicle_vm::cpu::mem::perm;
+
+        const PAGE_SIZE: u64 = 0x1000;
+        const RWX: u8 = perm::READ | perm::WRITE | perm::EXEC;
+
+        let mut audit = PermissionAudit::default();
+        let mut rwx_range: Option<(u64, u64)> = None;
+
+        for (start, end) in self.mapped_regions.iter() {
+            let mut address = start;
+            while address < end {
+                let size = std::cmp::min(PAGE_SIZE - (address % PAGE_SIZE), end - address);
+                let permissions = self.vm.cpu.mem.get_perm(address) & RWX;
+
+                let counter = match permissions {
+                    RWX => &mut audit.rwx_bytes,
+                    p if p == perm::READ | perm::WRITE => &mut audit.rw_bytes,
+                    p if p == perm::READ | perm::EXEC => &mut audit.rx_bytes,
+                    perm::READ => &mut audit.ro_bytes,
+                    _ => &mut audit.other_bytes,
+                };
+                *counter += size;
+
+                // Extend the current RWX range while pages stay RWX and contiguous.
+                let contiguous = matches!(rwx_range, Some((_, range_end)) if range_end == address);
+
+                if permissions == RWX && contiguous {
+                    rwx_range = rwx_range.map(|(range_start, _)| (range_start, address + size));
+                } else {
+                    if let Some((range_start, range_end)) = rwx_range.take() {
+                        rwx_callback(range_start, range_end - range_start);
+                    }
+
+                    if permissions == RWX {
+                        rwx_range = Some((address, address + size));
+                    }
+                }
+
+                address += size;
+            }
+        }
+
+        if let Some((range_start, range_end)) = rwx_range {
+            rwx_callback(range_start, range_end - range_start);
+        }
+
+        return audit;
+    }
+
+    // Calls `callback` with the address, length and FOREIGN_* permissions of every range of
+    // guest RAM, in address order. Ranges are split where the permissions change, MMIO
+    // ranges are not included.
+    pub fn get_mappings(&mut self, callback: &dyn Fn(u64, u64, u8)) {
+        const PAGE_SIZE: u64 = 0x1000;
+
+        for (start, end) in self.mapped_regions.iter() {
+            let mut range: Option<(u64, u64, u8)> = None;
+
+            let mut address = start;
+            while address < end {
+                let size = std::cmp::min(PAGE_SIZE - (address % PAGE_SIZE), end - address);
+                let permissions = foreign_permissions(self.vm.cpu.mem.get_perm(address));
+
+                range = match range {
+                    Some((range_start, _, range_permissions))
+                        if range_permissions == permissions =>
+                    {
+                        Some((range_start, address + size, permissions))
+                    }
+                    Some((range_start, range_end, range_permissions)) => {
+                        callback(range_start, range_end - range_start, range_permissions);
+                        Some((address, address + size, permissions))
+                    }
+                    None => Some((address, address + size, permissions)),
+                };
+
+                address += size;
+            }
+
+            if let Some((range_start, range_end, range_permissions)) = range {
+                callback(range_start, range_end - range_start, range_permissions);
+            }
+        }
+    }
+
+    pub fn start_mmio_recording(&mut self) {
+        self.mmio_log.borrow_mut().start_recording();
+    }
+
+    pub fn stop_mmio_recording(&mut self) -> Vec<u8> {
+        return self.mmio_log.borrow_mut().stop_recording();
+    }
+
+    pub fn start_mmio_replay(&mut self, log: &[u8]) -> bool {
+        return self.mmio_log.borrow_mut().start_replay(log);
+    }
+
+    pub fn stop_mmio_replay(&mut self) {
+        self.mmio_log.borrow_mut().stop_replay();
+    }
+
+    pub fn get_mmio_divergence(&self) -> Option<usize> {
+        return self.mmio_log.borrow().divergence();
+    }
+
+    pub fn unmap_memory(&mut self, address: u64, length: u64) -> bool {
+        return self.unmap_memory_checked(address, length).is_ok();
+    }
+
+    pub fn unmap_memory_checked(&mut self, address: u64, length: u64) -> Result<(), MemoryStatus> {
+        if !self.get_mem().unmap_memory_len(address, length) {
+            return Err(MemoryStatus::Unmapped);
+        }
+
+        let end = address.saturating_add(length);
+        self.region_names
+            .retain(|start, (size, _)| *start < address || start.saturating_add(*size) > end);
+        self.mapped_regions.remove(address, length);
+        self.mmio_regions.remove(address, length);
+
+        return Ok(());
+    }
+
+    pub fn name_region(&mut self, address: u64, length: u64, name: &str) {
+        if name.is_empty() {
+            self.region_names.remove(&address);
+            return;
+        }
+
+        self.region_names
+            .insert(address, (length, name.to_string()));
+    }
+
+    pub fn get_region_name(&self, address: u64) -> Option<&str> {
+        let (start, (length, name)) = self.region_names.range(..=address).next_back()?;
+        if address - start >= *length {
+            return None;
+        }
+
+        return Some(name.as_str());
+    }
+
+    pub fn protect_memory(&mut self, address: u64, length: u64, permissions: u8) -> bool {
+        return self
+            .protect_memory_checked(address, length, permissions)
+            .is_ok();
+    }
+
+    pub fn protect_memory_checked(
+        &mut self,
+        address: u64,
+        length: u64,
+        permissions: u8,
+    ) -> Result<(), MemoryStatus> {
+        if !self.check_wx(address, length, permissions) {
+            return Err(MemoryStatus::WxDenied);
+        }
+
+        let native_permissions = map_permissions(permissions);
+        return self
+            .get_mem()
+            .update_perm(address, length, native_permissions)
+            .map_err(MemoryStatus::from_error);
+    }
+
+    pub fn write_memory(&mut self, address: u64, data: &[u8]) -> bool {
+        return self.write_memory_checked(address, data).is_ok();
+    }
+
+    pub fn write_memory_checked(&mut self, address: u64, data: &[u8]) -> Result<(), MemoryStatus> {
+        return self.write_memory_perm(address, data, false);
+    }
+
+    // With `check_perm` the write fails with WriteViolation on pages that are not writable,
+    // like a guest write would.
+    pub fn write_memory_perm(
+        &mut self,
+        address: u64,
+        data: &[u8],
+        check_perm: bool,
+    ) -> Result<(), MemoryStatus> {
+        let required = if check_perm {
+            icicle_vm::cpu::mem::perm::WRITE
+        } else {
+            icicle_vm::cpu::mem::perm::NONE
+        };
+
+        let res = self.get_mem().write_bytes(address, data, required);
+
+        if let (Ok(_), Some(watch)) = (&res, &self.stack_watch) {
+            watch.tracker.borrow_mut().mark_written(address, data.len());
+        }
+
+        return res.map_err(MemoryStatus::from_error);
+    }
+
+    pub fn read_memory(&mut self, address: u64, data: &mut [u8]) -> bool {
+        return self.read_memory_checked(address, data).is_ok();
+    }
+
+    pub fn read_memory_checked(
+        &mut self,
+        address: u64,
+        data: &mut [u8],
+    ) -> Result<(), MemoryStatus> {
+        return self
+            .get_mem()
+            .read_bytes(address, data, icicle_vm::cpu::mem::perm::NONE)
+            .map_err(MemoryStatus::from_error);
+    }
+
+    // Returns the addresses in [start, end) of guest RAM where `pattern` occurs, at most
+    // `max_results` of them. Bytes whose `mask` byte is 0 match anything, other mask bits
+    // select the bits that have to be equal. MMIO ranges are not searched.
+    pub fn search_memory(
+        &mut self,
+        start: u64,
+        end: u64,
+        pattern: &[u8],
+        mask: Option<&[u8]>,
+        max_results: usize,
+    ) -> Vec<u64> {
+        const CHUNK_SIZE: u64 = 0x100000;
+
+        let mut results = Vec::new();
+        if pattern.is_empty() || start >= end || max_results == 0 {
+            return results;
+        }
+
+        let matches = |data: &[u8]| {
+            return data
+                .iter()
+                .zip(pattern)
+                .enumerate()
+                .all(|(index, (byte, expected))| {
+                    let bits = mask.and_then(|m| m.get(index)).map_or(0xFF, |bits| *bits);
+                    return ((byte ^ expected) & bits) == 0;
+                });
+        };
+
+        let overlap = pattern.len() as u64 - 1;
+        let regions: Vec<(u64, u64)> = self.mapped_regions.iter().collect();
+        let mut buffer = Vec::new();
+
+        // Adjacent mappings are merged into one range, so matches crossing from one mapping
+        // into the next are found like any other.
+        for (region_start, region_end) in regions {
+            let search_start = std::cmp::max(region_start, start);
+            let search_end = std::cmp::min(region_end, end);
+
+            // Chunks overlap by the pattern length so matches crossing a boundary are found.
+            let mut address = search_start;
+            while address < search_end && search_end - address > overlap {
+                let chunk_end = address
+                    .checked_add(CHUNK_SIZE)
+                    .and_then(|chunk_end| chunk_end.checked_add(overlap))
+                    .map_or(search_end, |chunk_end| std::cmp::min(chunk_end, search_end));
+                buffer.resize((chunk_end - address) as usize, 0);
+
+                if self.read_memory(address, &mut buffer) {
+                    for (offset, window) in buffer.windows(pattern.len()).enumerate() {
+                        if !matches(window) {
+                            continue;
+                        }
+
+                        results.push(address + offset as u64);
+                        if results.len() >= max_results {
+                            return results;
+                        }
+                    }
+                }
+
+                address = match address.checked_add(CHUNK_SIZE) {
+                    Some(next) => next,
+                    None => break,
+                };
+            }
+        }
+
+        return results;
+    }
+
+    // Reads `sizes[i]` bytes at `addresses[i]` into consecutive parts of `buffer`, which
+    // holds the sum of `sizes`. `lengths[i]` receives the bytes read, 0 if the read failed.
+    // Returns the number of successful reads, 0 without reading if `buffer` or `lengths`
+    // is too short.
+    pub fn read_memory_batch(
+        &mut self,
+        addresses: &[u64],
+        sizes: &[usize],
+        buffer: &mut [u8],
+        lengths: &mut [usize],
+    ) -> usize {
+        if !batch_fits(addresses, sizes, buffer.len(), lengths) {
+            return 0;
+        }
+
+        let mem = self.get_mem();
+        let mut offset = 0;
+        let mut successful = 0;
+
+        for (index, (address, size)) in addresses.iter().zip(sizes).enumerate() {
+            let data = &mut buffer[offset..offset + size];
+            offset += size;
+
+            let res = mem.read_bytes(*address, data, icicle_vm::cpu::mem::perm::NONE);
+            if res.is_err() {
+                lengths[index] = 0;
+                continue;
+            }
+
+            lengths[index] = *size;
+            successful += 1;
+        }
+
+        return successful;
+    }
+
+    // Counterpart of read_memory_batch, `data` holds the values back to back.
+    pub fn write_memory_batch(
+        &mut self,
+        addresses: &[u64],
+        sizes: &[usize],
+        data: &[u8],
+        lengths: &mut [usize],
+    ) -> usize {
+        if !batch_fits(addresses, sizes, data.len(), lengths) {
+            return 0;
+        }
+
+        let mut offset = 0;
+        let mut successful = 0;
+
+        for (index, (address, size)) in addresses.iter().zip(sizes).enumerate() {
+            let value = &data[offset..offset + size];
+            offset += size;
+
+            if !self.write_memory(*address, value) {
+                lengths[index] = 0;
+                continue;
+            }
+
+            lengths[index] = *size;
+            successful += 1;
+        }
+
+        return successful;
+    }
+
+    // Linear base of a segment in 64-bit mode. CS/DS/ES/SS are flat, FS and GS use
+    // FS_BASE/GS_BASE. Returns None for registers that are not segment registers.
+    fn segment_base(&self, segment: registers::X86Register) -> Option<u64> {
+        match segment {
+            registers::X86Register::Cs
+            | registers::X86Register::Ds
+            | registers::X86Register::Es
+            | registers::X86Register::Ss => Some(0),
+            registers::X86Register::Fs => {
+                Some(self.read_register_value(registers::X86Register::FsBase))
+            }
+            registers::X86Register::Gs => {
+                Some(self.read_register_value(registers::X86Register::GsBase))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn read_memory_segment(
+        &mut self,
+        segment: registers::X86Register,
+        offset: u64,
+        data: &mut [u8],
+    ) -> bool {
+        return match self.segment_base(segment) {
+            Some(base) => self.read_memory(base.wrapping_add(offset), data),
+            None => false,
+        };
+    }
+
+    pub fn write_memory_segment(
+        &mut self,
+        segment: registers::X86Register,
+        offset: u64,
+        data: &[u8],
+    ) -> bool {
+        return match self.segment_base(segment) {
+            Some(base) => self.write_memory(base.wrapping_add(offset), data),
+            None => false,
+        };
+    }
+
+    pub fn read_array(
+        &mut self,
+        address: u64,
+        stride: u64,
+        elem_size: usize,
+        out: &mut [u8],
+    ) -> usize {
+        let mut count: usize = 0;
+
+        for element in out.chunks_exact_mut(elem_size) {
+            let element_address = address.wrapping_add(stride.wrapping_mul(count as u64));
+            if !self.read_memory(element_address, element) {
+                break;
+            }
+
+            count += 1;
+        }
+
+        return count;
+    }
+
+    // Reads as many instruction bytes as are mapped, so instructions right before
+    // an unmapped page still decode if they fit. Only RAM is read, MMIO handlers may have
+    // side effects.
+    fn read_instruction_bytes(&mut self, address: u64) -> Vec<u8> {
+        let length = std::cmp::min(
+            self.mapped_regions.remaining(address),
+            decode::MAX_INSTRUCTION_SIZE as u64,
+        );
+        let mut bytes = Vec::with_capacity(length as usize);
+
+        for offset in 0..length {
+            let mut byte = [0u8; 1];
+            if !self.read_memory(address.wrapping_add(offset), &mut byte) {
+                break;
+            }
+
+            bytes.push(byte[0]);
+        }
+
+        return bytes;
+    }
+
+    pub fn decode_instruction(
+        &mut self,
+        address: u64,
+    ) -> Result<decode::Instruction, decode::DecodeStatus> {
+        let bytes = self.read_instruction_bytes(address);
+        if bytes.is_empty() {
+            return Err(decode::DecodeStatus::Unreadable);
+        }
+
+        let cpu = &self.vm.cpu;
+        let context = cpu.arch.isa_mode_context[cpu.isa_mode() as usize];
+
+        return self
+            .decoder
+            .decode(&cpu.arch.sleigh, context, address, &bytes)
+            .ok_or(decode::DecodeStatus::Invalid);
+    }
+
+    // FNV-1a over the register file and the dirty pages of guest RAM, MMIO ranges are not
+    // read. Identical states hash to the same value across runs of the same build.
+    //
+    // icicle does not track dirty pages and tracking guest stores would slow down every
+    // write. Mappings start zero-filled, so the pages that differ from their state at map
+    // time are the non-zero ones. A page that was written and zeroed again is skipped, which
+    // is fine because it cannot be told apart from one that was never written.
+    pub fn state_hash(&mut self) -> u64 {
+        const PAGE_SIZE: u64 = 0x1000;
+
+        let mut hasher = Fnv1a::new();
+        hasher.write(&self.save_registers());
+
+        let ranges: Vec<(u64, u64)> = self.mapped_regions.iter().collect();
+        let mut page = [0u8; PAGE_SIZE as usize];
+
+        for (start, end) in ranges {
+            hasher.write_u64(start);
+            hasher.write_u64(end);
+
+            let mut address = start;
+            while address < end {
+                let size = std::cmp::min(PAGE_SIZE, end - address) as usize;
+                let data = &mut page[..size];
+
+                if self.read_memory(address, data) && data.iter().any(|byte| *byte != 0) {
+                    hasher.write_u64(address);
+                    hasher.write(data);
+                }
+
+                address += size as u64;
+            }
+        }
+
+        return hasher.finish();
+    }
+
+    // Register blob format (all integers little-endian):
+    //
+    //   magic      4 bytes  "ICRG"
+    //   version    u32      REGISTER_BLOB_VERSION
+    //   size       u32      size of icicle's register file
+    //   layout     u64      register_layout_fingerprint()
+    //   registers  `size` bytes, icicle's register file as is
+    //
+    // The register file layout is defined by icicle and the SLEIGH specification, so blobs
+    // are only meant to be restored by the same build. Blobs with a different register file
+    // size or layout fingerprint are rejected.
+    pub fn save_registers(&self) -> Vec<u8> {
+        const REG_SIZE: usize = std::mem::size_of::<icicle_cpu::Regs>();
+
+        let mut blob = Vec::with_capacity(REGISTER_BLOB_HEADER_SIZE + REG_SIZE);
+        blob.extend_from_slice(REGISTER_BLOB_MAGIC);
+        blob.extend_from_slice(&REGISTER_BLOB_VERSION.to_le_bytes());
+        blob.extend_from_slice(&(REG_SIZE as u32).to_le_bytes());
+        blob.extend_from_slice(&self.register_layout_fingerprint().to_le_bytes());
+
+        unsafe {
+            let data: [u8; REG_SIZE] = self.vm.cpu.regs.read_at(0);
+            blob.extend_from_slice(&data);
+        }
+
+        return blob;
+    }
+
+    // Hash of the name and location of every register the SLEIGH specification defines, so
+    // that a specification change moving registers around is detected.
+    fn register_layout_fingerprint(&self) -> u64 {
+        let sleigh = &self.vm.cpu.arch.sleigh;
+        let mut hasher = Fnv1a::new();
+
+        for info in sleigh.named_registers.iter() {
+            let name = sleigh.get_str(info.name);
+            hasher.write_u64(name.len() as u64);
+            hasher.write(name.as_bytes());
+            hasher.write(&info.var.id.to_le_bytes());
+            hasher.write(&[info.var.offset, info.var.size]);
+        }
+
+        return hasher.finish();
+    }
+
+    // Nothing is written unless the header matches and the blob has the exact size.
+    pub fn restore_registers(&mut self, data: &[u8]) -> bool {
+        const REG_SIZE: usize = std::mem::size_of::<icicle_cpu::Regs>();
+
+        if data.len() != REGISTER_BLOB_HEADER_SIZE + REG_SIZE
+            || &data[0..4] != REGISTER_BLOB_MAGIC
+            || data[4..8] != REGISTER_BLOB_VERSION.to_le_bytes()
+            || data[8..12] != (REG_SIZE as u32).to_le_bytes()
+            || data[12..20] != self.register_layout_fingerprint().to_le_bytes()
+        {
+            return false;
+        }
+
+        let buffer: [u8; REG_SIZE] = data[REGISTER_BLOB_HEADER_SIZE..].try_into().unwrap();
+
+        unsafe {
+            self.vm.cpu.regs.write_at(0, buffer);
+        };
+
+        return true;
+    }
+
+    fn read_checkpoint_registers(&mut self) -> Vec<(registers::X86Register, Vec<u8>)> {
+        const CHECKPOINT_REGISTERS: [registers::X86Register; 42] = [
+            registers::X86Register::Rax,
+            registers::X86Register::Rbx,
+            registers::X86Register::Rcx,
+            registers::X86Register::Rdx,
+            registers::X86Register::Rsi,
+            registers::X86Register::Rdi,
+            registers::X86Register::Rbp,
+            registers::X86Register::Rsp,
+            registers::X86Register::R8,
+            registers::X86Register::R9,
+            registers::X86Register::R10,
+            registers::X86Register::R11,
+            registers::X86Register::R12,
+            registers::X86Register::R13,
+            registers::X86Register::R14,
+            registers::X86Register::R15,
+            registers::X86Register::Rip,
+            registers::X86Register::Rflags,
+            registers::X86Register::Cs,
+            registers::X86Register::Ds,
+            registers::X86Register::Es,
+            registers::X86Register::Fs,
+            registers::X86Register::Gs,
+            registers::X86Register::Ss,
+            registers::X86Register::FsBase,
+            registers::X86Register::GsBase,
+            registers::X86Register::Fpcw,
+            registers::X86Register::Fpsw,
+            registers::X86Register::Fptag,
+            registers::X86Register::Mxcsr,
+            registers::X86Register::St0,
+            registers::X86Register::St1,
+            registers::X86Register::St2,
+            registers::X86Register::St3,
+            registers::X86Register::St4,
+            registers::X86Register::St5,
+            registers::X86Register::St6,
+            registers::X86Register::St7,
+            registers::X86Register::Cr0,
+            registers::X86Register::Cr2,
+            registers::X86Register::Cr3,
+            registers::X86Register::Cr4,
+        ];
+
+        return CHECKPOINT_REGISTERS
+            .iter()
+            .chain(vector::YMM_REGISTERS.iter())
+            .map(|reg| {
+                let mut value = vec![0u8; self.register_size(*reg)];
+                self.read_register(*reg, &mut value);
+                (*reg, value)
+            })
+            .collect();
+    }
+
+    // Reports every architectural register that differs from `checkpoint`, a blob produced
+    // by save_registers, with its old and new little-endian value. Partial registers are
+    // not reported separately from the register containing them.
+    pub fn diff_registers(
+        &mut self,
+        checkpoint: &[u8],
+        callback: &mut RegisterDiffCallback<'_>,
+    ) -> bool {
+        let current = self.save_registers();
+        if checkpoint.len() != current.len() {
+            return false;
+        }
+
+        if checkpoint == current.as_slice() {
+            return true;
+        }
+
+        let new_values = self.read_checkpoint_registers();
+
+        if !self.restore_registers(checkpoint) {
+            return false;
+        }
+
+        let old_values = self.read_checkpoint_registers();
+        self.restore_registers(&current);
+
+        for ((reg, old), (_, new)) in old_values.iter().zip(new_values.iter()) {
+            if old != new {
+                callback(*reg, old, new);
+            }
+        }
+
+        return true;
+    }
+
+    pub fn create_register_delta(&self, base: &[u8]) -> Option<Vec<u8>> {
+        let current = self.save_registers();
+        return delta::encode(base, &current);
+    }
+
+    pub fn apply_register_delta(&mut self, base: &[u8], delta: &[u8]) -> bool {
+        let registers = match delta::apply(base, delta) {
+            Some(registers) => registers,
+            None => return false,
+        };
+
+        return self.restore_registers(&registers);
+    }
+
+    fn read_generic_register(&mut self, reg: registers::X86Register, buffer: &mut [u8]) -> usize {
+        let reg_node = self.reg.get_node(reg);
+        return self.read_node(reg_node, buffer);
+    }
+
+    fn read_node(&mut self, reg_node: pcode::VarNode, buffer: &mut [u8]) -> usize {
+        const CHUNK_SIZE: usize = 32;
+
+        let size: usize = reg_node.size.into();
+
+        // ZMM registers do not fit a dynamic value and are read in halves.
+        let mut bytes = [0u8; 64];
+        for offset in (0..size).step_by(CHUNK_SIZE) {
+            let length = std::cmp::min(size - offset, CHUNK_SIZE);
+            let node = reg_node.slice(offset as u8, length as u8);
+
+            let res = self.vm.cpu.read_dynamic(pcode::Value::Var(node));
+            let chunk: [u8; CHUNK_SIZE] = res.zxt();
+            bytes[offset..offset + length].copy_from_slice(&chunk[..length]);
+        }
+
+        let len = std::cmp::min(bytes.len(), buffer.len());
+        buffer[..len].copy_from_slice(&bytes[..len]);
+
+        return size;
+    }
+
+    fn read_flags<T>(&mut self, data: &mut [u8]) -> usize {
+        const REAL_SIZE: usize = std::mem::size_of::<u64>();
+        let limit: usize = std::mem::size_of::<T>();
+        let size = std::cmp::min(REAL_SIZE, limit);
+
+        let flags: u64 = self.reg.get_flags(&mut self.vm.cpu);
+
+        let copy_size = std::cmp::min(data.len(), size);
+        data[..copy_size].copy_from_slice(&flags.to_ne_bytes()[..copy_size]);
+
+        return limit;
+    }
+
+    pub fn read_register(&mut self, reg: registers::X86Register, data: &mut [u8]) -> usize {
+        match reg {
+            registers::X86Register::Rflags => self.read_flags::<u64>(data),
+            registers::X86Register::Eflags => self.read_flags::<u32>(data),
+            registers::X86Register::Flags => self.read_flags::<u16>(data),
+            _ if !self.reg.is_available(reg) => 0,
+            _ => self.read_generic_register(reg, data),
+        }
+    }
+
+    fn read_register_value(&self, reg: registers::X86Register) -> u64 {
+        return self.vm.cpu.read_reg(self.reg.get_node(reg));
+    }
+
+    fn write_register_value(&mut self, reg: registers::X86Register, value: u64) {
+        self.vm.cpu.write_reg(self.reg.get_node(reg), value);
+    }
+
+    // Configures all segment selectors and the FS/GS bases at once. Nothing is written
+    // if CS is a null selector, SS and CS disagree on the privilege level, or a base
+    // is not canonical.
+    pub fn setup_segments(&mut self, selectors: &[u16; 6], fs_base: u64, gs_base: u64) -> bool {
+        const SEGMENT_REGISTERS: [registers::X86Register; 6] = [
+            registers::X86Register::Cs,
+            registers::X86Register::Ds,
+            registers::X86Register::Es,
+            registers::X86Register::Fs,
+            registers::X86Register::Gs,
+            registers::X86Register::Ss,
+        ];
+
+        let cs = selectors[0];
+        let ss = selectors[5];
+
+        if (cs & !3) == 0 || (cs & 3) != (ss & 3) {
+            return false;
+        }
+
+        let is_canonical = |base: u64| ((base as i64) << 16 >> 16) as u64 == base;
+        if !is_canonical(fs_base) || !is_canonical(gs_base) {
+            return false;
+        }
+
+        for (reg, selector) in SEGMENT_REGISTERS.iter().zip(selectors.iter()) {
+            self.write_register_value(*reg, *selector as u64);
+        }
+
+        self.write_register_value(registers::X86Register::FsBase, fs_base);
+        self.write_register_value(registers::X86Register::GsBase, gs_base);
+        return true;
+    }
+
+    // Puts the CPU into 64-bit ring 0 with flat segments and maps a read/write stack of
+    // `stack_size` bytes (rounded up to pages) below the highest free address under
+    // FLAT_STACK_TOP. Returns the lowest address of the stack. The resulting state is:
+    //
+    //   CR0     PE | MP | ET | NE | WP | PG
+    //   CR4     PAE | OSFXSR | OSXMMEXCPT
+    //   EFER    SCE | LME | LMA | NXE
+    //   CS      0x08, DS/ES/SS 0x10, FS/GS 0 with zero bases
+    //   RSP     end of the stack, RFLAGS 0x2
+    //
+    // CR3 and the page tables are left alone, guest memory is accessed without them.
+    // Other registers, including RIP, are not modified.
+    pub fn init_flat_long_mode(&mut self, stack_size: u64) -> Option<u64> {
+        const FLAT_STACK_TOP: u64 = 0x7FFF_FFFF_F000;
+        const FLAT_CR0: u64 = 0x8005_0033;
+        const FLAT_CR4: u64 = 0x620;
+        const FLAT_CS: u16 = 0x08;
+        const FLAT_DS: u16 = 0x10;
+        const FLAT_FLAGS: u64 = 0x2;
+
+        let size = stack_size.checked_add(0xFFF)? & !0xFFF;
+        if size == 0 || size > FLAT_STACK_TOP {
+            return None;
+        }
+
+        let mut base = FLAT_STACK_TOP - size;
+        while let Some((start, _)) = self
+            .mapped_regions
+            .find_overlap(base, size)
+            .or_else(|| self.mmio_regions.find_overlap(base, size))
+        {
+            base = (start & !0xFFF).checked_sub(size)?;
+        }
+
+        if !self.map_memory(base, size, FOREIGN_READ | FOREIGN_WRITE) {
+            return None;
+        }
+
+        let selectors = [FLAT_CS, FLAT_DS, FLAT_DS, 0, 0, FLAT_DS];
+        self.setup_segments(&selectors, 0, 0);
+
+        self.write_register_value(registers::X86Register::Cr0, FLAT_CR0);
+        self.write_register_value(registers::X86Register::Cr4, FLAT_CR4);
+        self.write_msr(
+            msr::IA32_EFER,
+            msr::EFER_SCE | msr::EFER_LME | msr::EFER_LMA | msr::EFER_NXE,
+        );
+
+        self.write_register_value(registers::X86Register::Rsp, base + size);
+        self.reg.set_flags(&mut self.vm.cpu, FLAT_FLAGS);
+        return Some(base);
+    }
+
+    pub fn get_gpregs(&mut self) -> GpRegs {
+        return GpRegs {
+            rax: self.read_register_value(registers::X86Register::Rax),
+            rbx: self.read_register_value(registers::X86Register::Rbx),
+            rcx: self.read_register_value(registers::X86Register::Rcx),
+            rdx: self.read_register_value(registers::X86Register::Rdx),
+            rsi: self.read_register_value(registers::X86Register::Rsi),
+            rdi: self.read_register_value(registers::X86Register::Rdi),
+            rbp: self.read_register_value(registers::X86Register::Rbp),
+            rsp: self.read_register_value(registers::X86Register::Rsp),
+            r8: self.read_register_value(registers::X86Register::R8),
+            r9: self.read_register_value(registers::X86Register::R9),
+            r10: self.read_register_value(registers::X86Register::R10),
+            r11: self.read_register_value(registers::X86Register::R11),
+            r12: self.read_register_value(registers::X86Register::R12),
+            r13: self.read_register_value(registers::X86Register::R13),
+            r14: self.read_register_value(registers::X86Register::R14),
+            r15: self.read_register_value(registers::X86Register::R15),
+            rip: self.vm.cpu.read_pc(),
+            rflags: self.reg.get_flags(&mut self.vm.cpu),
+        };
+    }
+
+    pub fn set_gpregs(&mut self, regs: &GpRegs) {
+        self.write_register_value(registers::X86Register::Rax, regs.rax);
+        self.write_register_value(registers::X86Register::Rbx, regs.rbx);
+        self.write_register_value(registers::X86Register::Rcx, regs.rcx);
+        self.write_register_value(registers::X86Register::Rdx, regs.rdx);
+        self.write_register_value(registers::X86Register::Rsi, regs.rsi);
+        self.write_register_value(registers::X86Register::Rdi, regs.rdi);
+        self.write_register_value(registers::X86Register::Rbp, regs.rbp);
+        self.write_register_value(registers::X86Register::Rsp, regs.rsp);
+        self.write_register_value(registers::X86Register::R8, regs.r8);
+        self.write_register_value(registers::X86Register::R9, regs.r9);
+        self.write_register_value(registers::X86Register::R10, regs.r10);
+        self.write_register_value(registers::X86Register::R11, regs.r11);
+        self.write_register_value(registers::X86Register::R12, regs.r12);
+        self.write_register_value(registers::X86Register::R13, regs.r13);
+        self.write_register_value(registers::X86Register::R14, regs.r14);
+        self.write_register_value(registers::X86Register::R15, regs.r15);
+        self.reg.set_flags(&mut self.vm.cpu, regs.rflags);
+        self.vm.cpu.write_pc(regs.rip);
+    }
+
+    pub fn get_fpu_state(&self) -> fpu::FpuState {
+        let control = self.read_register_value(registers::X86Register::Fpcw) as u16;
+        let status = self.read_register_value(registers::X86Register::Fpsw) as u16;
+        let tag = self.read_register_value(registers::X86Register::Fptag) as u16;
+        let mxcsr = self.read_register_value(registers::X86Register::Mxcsr) as u32;
+
+        return fpu::FpuState {
+            control: fpu::FpuControlWord::decode(control),
+            status: fpu::FpuStatusWord::decode(status),
+            tag: fpu::FpuTagWord::decode(tag),
+            mxcsr: fpu::MxcsrRegister::decode(mxcsr),
+        };
+    }
+
+    fn paging_mode(&self) -> Option<paging::PagingMode> {
+        let cr0 = self.read_register_value(registers::X86Register::Cr0);
+        let cr4 = self.read_register_value(registers::X86Register::Cr4);
+        let efer = self.read_msr(msr::IA32_EFER);
+
+        return paging::PagingMode::detect(cr0, cr4, (efer & msr::EFER_LME) != 0);
+    }
+
+    // Reads a page table entry from guest RAM. Entries in MMIO ranges or unmapped memory
+    // are missing, device handlers are never called.
+    fn read_table_entry(&mut self, address: u64) -> Option<u64> {
+        let mut entry = [0u8; 8];
+        if self.mapped_regions.remaining(address) < entry.len() as u64 {
+            return None;
+        }
+
+        if !self.read_memory(address, &mut entry) {
+            return None;
+        }
+
+        return Some(u64::from_le_bytes(entry));
+    }
+
+    // The paging mode configured by CR0, CR4 and EFER, whether or not CR3 points at page
+    // tables.
+    pub fn get_guest_paging_mode(&self) -> AddressingMode {
+        let cr0 = self.read_register_value(registers::X86Register::Cr0);
+        if !paging::is_enabled(cr0) {
+            return AddressingMode::Flat;
+        }
+
+        return match self.paging_mode() {
+            Some(paging::PagingMode::FourLevel) => AddressingMode::FourLevel,
+            Some(paging::PagingMode::Pae) => AddressingMode::Pae,
+            None => AddressingMode::Unsupported,
+        };
+    }
+
+    // Guest accesses always use the mapped addresses directly. Paging with a CR3 that holds
+    // no page tables, as left by init_flat_long_mode, is reported as flat, otherwise this
+    // is the guest paging mode the page table tooling walks.
+    pub fn get_addressing_mode(&mut self) -> AddressingMode {
+        let guest_mode = self.get_guest_paging_mode();
+
+        return match (guest_mode, self.paging_mode()) {
+            (AddressingMode::FourLevel | AddressingMode::Pae, Some(mode)) => {
+                let cr3 = self.read_register_value(registers::X86Register::Cr3);
+                let mut read_entry = |entry_address: u64| self.read_table_entry(entry_address);
+
+                if paging::has_present_entry(mode, cr3, &mut read_entry) {
+                    guest_mode
+                } else {
+                    AddressingMode::Flat
+                }
+            }
+            _ => guest_mode,
+        };
+    }
+
+    // Walks the guest page tables for `address`. Addresses translate to themselves in flat
+    // mode. Returns None if the address is not mapped or the mode is unsupported.
+    pub fn translate_address(&mut self, address: u64) -> Option<u64> {
+        let mode = match self.get_addressing_mode() {
+            AddressingMode::Flat => return Some(address),
+            _ => self.paging_mode()?,
+        };
+
+        let cr3 = self.read_register_value(registers::X86Register::Cr3);
+        let mut read_entry = |entry_address: u64| self.read_table_entry(entry_address);
+
+        return paging::translate(mode, cr3, address, &mut read_entry);
+    }
+
+    // Returns false if paging is disabled or uses a mode the walker does not support.
+    pub fn walk_page_tables(&mut self, callback: &mut dyn FnMut(&paging::Mapping)) -> bool {
+        let cr3 = self.read_register_value(registers::X86Register::Cr3);
+        let efer = self.read_msr(msr::IA32_EFER);
+
+        let mode = match self.paging_mode() {
+            Some(mode) => mode,
+            None => return false,
+        };
+
+        let mut read_entry = |address: u64| self.read_table_entry(address);
+
+        let mut walker = paging::Walker {
+            mode,
+            nx_enabled: (efer & msr::EFER_NXE) != 0,
+            read_entry: &mut read_entry,
+            callback,
+        };
+
+        walker.walk(cr3);
+        return true;
+    }
+
+    // Puts the registers into the state documented for power-on (Intel SDM Vol. 3, 9.1.1)
+    // without touching memory or mappings. Segment bases are not modelled, so execution
+    // resumes at the linear reset vector 0xFFFFFFF0 rather than CS:IP F000:FFF0.
+    pub fn cpu_reset(&mut self) {
+        const CLEARED_REGISTERS: [registers::X86Register; 43] = [
+            registers::X86Register::Rax,
+            registers::X86Register::Rbx,
+            registers::X86Register::Rcx,
+            registers::X86Register::Rsi,
+            registers::X86Register::Rdi,
+            registers::X86Register::Rbp,
+            registers::X86Register::Rsp,
+            registers::X86Register::R8,
+            registers::X86Register::R9,
+            registers::X86Register::R10,
+            registers::X86Register::R11,
+            registers::X86Register::R12,
+            registers::X86Register::R13,
+            registers::X86Register::R14,
+            registers::X86Register::R15,
+            registers::X86Register::Ds,
+            registers::X86Register::Es,
+            registers::X86Register::Fs,
+            registers::X86Register::Gs,
+            registers::X86Register::Ss,
+            registers::X86Register::FsBase,
+            registers::X86Register::GsBase,
+            registers::X86Register::Cr2,
+            registers::X86Register::Cr3,
+            registers::X86Register::Cr4,
+            registers::X86Register::Cr8,
+            registers::X86Register::Dr0,
+            registers::X86Register::Dr1,
+            registers::X86Register::Dr2,
+            registers::X86Register::Dr3,
+            registers::X86Register::Fpsw,
+            registers::X86Register::Fip,
+            registers::X86Register::Fdp,
+            registers::X86Register::Fds,
+            registers::X86Register::Fop,
+            registers::X86Register::St0,
+            registers::X86Register::St1,
+            registers::X86Register::St2,
+            registers::X86Register::St3,
+            registers::X86Register::St4,
+            registers::X86Register::St5,
+            registers::X86Register::St6,
+            registers::X86Register::St7,
+        ];
+
+        const RESET_VECTOR: u64 = 0xFFFF_FFF0;
+        const RESET_CS: u64 = 0xF000;
+        const RESET_RDX: u64 = 0x600;
+        const RESET_FLAGS: u64 = 0x2;
+        const RESET_CR0: u64 = 0x6000_0010;
+        const RESET_DR6: u64 = 0xFFFF_0FF0;
+        const RESET_DR7: u64 = 0x400;
+        const RESET_FPCW: u64 = 0x40;
+        const RESET_MXCSR: u64 = 0x1F80;
+
+        for reg in CLEARED_REGISTERS.iter().chain(vector::YMM_REGISTERS.iter()) {
+            let zero = vec![0u8; self.register_size(*reg)];
+            self.write_register(*reg, &zero);
+        }
+
+        self.write_register_value(registers::X86Register::Cs, RESET_CS);
+        self.write_register_value(registers::X86Register::Rdx, RESET_RDX);
+        self.write_register_value(registers::X86Register::Cr0, RESET_CR0);
+        self.write_register_value(registers::X86Register::Dr6, RESET_DR6);
+        self.write_register_value(registers::X86Register::Dr7, RESET_DR7);
+        self.write_register_value(registers::X86Register::Fpcw, RESET_FPCW);
+        self.write_register_value(registers::X86Register::Mxcsr, RESET_MXCSR);
+        self.reg.set_flags(&mut self.vm.cpu, RESET_FLAGS);
+
+        self.msrs.clear();
+        self.clear_exception();
+        self.vm.cpu.write_pc(RESET_VECTOR);
+    }
+
+    // Returns the emulator to the state it was created in while keeping the translated
+    // SLEIGH specification: all memory, MMIO devices and timers are removed, the registers
+    // get the values they had at creation back, MSRs are cleared and the instruction count
+    // restarts at 0. Hooks, breakpoints, the exit address and the port and limit handlers
+    // are removed if `remove_hooks` is set and kept otherwise. Snapshots and settings like
+    // the syscall mode or the W^X and limit policies are kept.
+    pub fn reset(&mut self, remove_hooks: bool) {
+        let ranges: Vec<(u64, u64)> = self
+            .mapped_regions
+            .iter()
+            .chain(self.mmio_regions.iter())
+            .collect();
+
+        for (start, end) in ranges {
+            self.unmap_memory(start, end - start);
+        }
+
+        for timer in std::mem::take(&mut self.timers) {
+            self.execution_hooks
+                .borrow_mut()
+                .remove_block_hook(timer.block_hook);
+        }
+
+        self.region_names.clear();
+
+        if remove_hooks {
+            self.remove_all_hooks();
+        }
+
+        let initial_registers = std::mem::take(&mut self.initial_registers);
+        self.restore_registers(&initial_registers);
+        self.initial_registers = initial_registers;
+
+        self.msrs.clear();
+        self.clear_exception();
+        self.vm.cpu.icount = 0;
+        self.breakpoint_hit = None;
+        self.fault_instruction = None;
+        self.exit_exception = None;
+        self.limit_extended = false;
+        self.reset_coverage();
+        self.flush_tlb();
+    }
+
+    fn remove_all_hooks(&mut self) {
+        for id in std::mem::take(&mut self.memory_hooks).into_keys() {
+            self.remove_hook(id);
+        }
+
+        let breakpoints: Vec<u32> = self.breakpoints.keys().copied().collect();
+        for id in breakpoints {
+            self.remove_breakpoint(id);
+        }
+
+        self.set_exit_address(None);
+        self.remove_stack_watch();
+        self.execution_hooks.borrow_mut().remove_all_hooks();
+
+        self.syscall_hooks = HookContainer::new();
+        self.interrupt_hooks = HookContainer::new();
+        self.uninit_read_hooks = HookContainer::new();
+        self.exception_hooks = HookContainer::new();
+        self.violation_hooks = HookContainer::new();
+        self.wx_hooks = HookContainer::new();
+        self.port_handler = None;
+        self.limit_callback = None;
+    }
+
+    pub fn get_fpu_top(&self) -> u8 {
+        let status = self.read_register_value(registers::X86Register::Fpsw) as u16;
+        return fpu::FpuStatusWord::decode(status).top;
+    }
+
+    pub fn set_fpu_top(&mut self, top: u8) -> bool {
+        if top > 7 {
+            return false;
+        }
+
+        let status = self.read_register_value(registers::X86Register::Fpsw) as u16;
+        let mut status_word = fpu::FpuStatusWord::decode(status);
+        status_word.top = top;
+
+        self.write_register_value(registers::X86Register::Fpsw, status_word.encode().into());
+        return true;
+    }
+
+    pub fn set_fpu_state(&mut self, state: &fpu::FpuState) {
+        let control = self.read_register_value(registers::X86Register::Fpcw) as u16;
+        let mxcsr = self.read_register_value(registers::X86Register::Mxcsr) as u32;
+
+        self.write_register_value(
+            registers::X86Register::Fpcw,
+            state.control.encode(control).into(),
+        );
+        self.write_register_value(registers::X86Register::Fpsw, state.status.encode().into());
+        self.write_register_value(registers::X86Register::Fptag, state.tag.encode().into());
+        self.write_register_value(
+            registers::X86Register::Mxcsr,
+            state.mxcsr.encode(mxcsr).into(),
+        );
+    }
+
+    fn save_fpu_area(&mut self, area: &mut [u8], components: u64) {
+        if (components & xsave::COMPONENT_X87) != 0 {
+            let tag = self.read_register_value(registers::X86Register::Fptag) as u16;
+            let fields = [
+                (xsave::FCW_OFFSET, registers::X86Register::Fpcw, 2),
+                (xsave::FSW_OFFSET, registers::X86Register::Fpsw, 2),
+                (xsave::FOP_OFFSET, registers::X86Register::Fop, 2),
+                (xsave::FIP_OFFSET, registers::X86Register::Fip, 8),
+                (xsave::FDP_OFFSET, registers::X86Register::Fdp, 8),
+            ];
+
+            for (offset, reg, size) in fields {
+                let value = self.read_register_value(reg).to_le_bytes();
+                area[offset..offset + size].copy_from_slice(&value[..size]);
+            }
+
+            area[xsave::FTW_OFFSET] = xsave::abridge_tag(tag);
+            area[xsave::FTW_OFFSET + 1] = 0;
+
+            for (index, reg) in xsave::ST_REGISTERS.iter().enumerate() {
+                let offset = xsave::ST_OFFSET + index * xsave::SLOT_SIZE;
+                let slot = &mut area[offset..offset + xsave::SLOT_SIZE];
+
+                slot.fill(0);
+                self.read_generic_register(*reg, &mut slot[..xsave::ST_SIZE]);
+            }
+        }
+
+        if (components & (xsave::COMPONENT_SSE | xsave::COMPONENT_AVX)) != 0 {
+            let mxcsr = self.read_register_value(registers::X86Register::Mxcsr) as u32;
+            area[xsave::MXCSR_OFFSET..xsave::MXCSR_OFFSET + 4]
+                .copy_from_slice(&mxcsr.to_le_bytes());
+            area[xsave::MXCSR_MASK_OFFSET..xsave::MXCSR_MASK_OFFSET + 4]
+                .copy_from_slice(&xsave::MXCSR_MASK.to_le_bytes());
+        }
+
+        if (components & xsave::COMPONENT_SSE) != 0 {
+            for (index, reg) in xsave::XMM_REGISTERS.iter().enumerate() {
+                let offset = xsave::XMM_OFFSET + index * xsave::SLOT_SIZE;
+                self.read_generic_register(*reg, &mut area[offset..offset + xsave::SLOT_SIZE]);
+            }
+        }
+
+        if (components & xsave::COMPONENT_AVX) != 0 {
+            for (index, reg) in vector::YMM_REGISTERS.iter().enumerate() {
+                let mut ymm = [0u8; vector::YMM_SIZE];
+                self.read_generic_register(*reg, &mut ymm);
+
+                let offset = xsave::AVX_OFFSET + index * xsave::SLOT_SIZE;
+                area[offset..offset + xsave::SLOT_SIZE].copy_from_slice(&ymm[xsave::SLOT_SIZE..]);
+            }
+        }
+    }
+
+    // Components in `components` but not in `from_memory` are set to their initial state.
+    fn load_fpu_area(&mut self, area: &[u8], components: u64, from_memory: u64) {
+        let empty = [0u8; xsave::FXSAVE_SIZE];
+        let source = |component: u64| {
+            if (from_memory & component) != 0 {
+                return area;
+            }
+
+            return &empty[..];
+        };
+
+        if (components & xsave::COMPONENT_X87) != 0 {
+            let x87 = source(xsave::COMPONENT_X87);
+            let mut stack = [[0u8; xsave::ST_SIZE]; 8];
+
+            for (index, reg) in xsave::ST_REGISTERS.iter().enumerate() {
+                let offset = xsave::ST_OFFSET + index * xsave::SLOT_SIZE;
+                stack[index].copy_from_slice(&x87[offset..offset + xsave::ST_SIZE]);
+                self.write_generic_register(*reg, &stack[index]);
+            }
+
+            let (control, status, tag) = if (from_memory & xsave::COMPONENT_X87) != 0 {
+                let status = xsave::read_u16(x87, xsave::FSW_OFFSET);
+                let top = fpu::FpuStatusWord::decode(status).top;
+                let tag = xsave::expand_tag(x87[xsave::FTW_OFFSET], top, &stack);
+
+                (xsave::read_u16(x87, xsave::FCW_OFFSET), status, tag)
+            } else {
+                (xsave::INIT_FCW, 0, xsave::INIT_FTW)
+            };
+
+            self.write_register_value(registers::X86Register::Fpcw, control.into());
+            self.write_register_value(registers::X86Register::Fpsw, status.into());
+            self.write_register_value(registers::X86Register::Fptag, tag.into());
+            self.write_register_value(
+                registers::X86Register::Fop,
+                xsave::read_u16(x87, xsave::FOP_OFFSET).into(),
+            );
+            self.write_register_value(
+                registers::X86Register::Fip,
+                xsave::read_u64(x87, xsave::FIP_OFFSET),
+            );
+            self.write_register_value(
+                registers::X86Register::Fdp,
+                xsave::read_u64(x87, xsave::FDP_OFFSET),
+            );
+        }
+
+        // MXCSR is always taken from memory, even when the SSE state is initialized.
+        if (components & (xsave::COMPONENT_SSE | xsave::COMPONENT_AVX)) != 0 {
+            let mxcsr = xsave::read_u32(area, xsave::MXCSR_OFFSET);
+            self.write_register_value(registers::X86Register::Mxcsr, mxcsr.into());
+        }
+
+        if (components & xsave::COMPONENT_SSE) != 0 {
+            let sse = source(xsave::COMPONENT_SSE);
+
+            for (index, reg) in xsave::XMM_REGISTERS.iter().enumerate() {
+                let offset = xsave::XMM_OFFSET + index * xsave::SLOT_SIZE;
+                self.write_generic_register(*reg, &sse[offset..offset + xsave::SLOT_SIZE]);
+            }
+        }
+
+        if (components & xsave::COMPONENT_AVX) != 0 {
+            for (index, reg) in vector::YMM_REGISTERS.iter().enumerate() {
+                let mut ymm = [0u8; vector::YMM_SIZE];
+                self.read_generic_register(*reg, &mut ymm);
+
+                ymm[xsave::SLOT_SIZE..].fill(0);
+                if (from_memory & xsave::COMPONENT_AVX) != 0 {
+                    let offset = xsave::AVX_OFFSET + index * xsave::SLOT_SIZE;
+                    ymm[xsave::SLOT_SIZE..]
+                        .copy_from_slice(&area[offset..offset + xsave::SLOT_SIZE]);
+                }
+
+                self.write_generic_register(*reg, &ymm);
+            }
+        }
+    }
+
+    // Reserved and available bytes of the area are left untouched. Returns false if the
+    // area is misaligned or not fully mapped, like the #GP/#PF real hardware would raise.
+    pub fn fxsave(&mut self, address: u64) -> bool {
+        if !address.is_multiple_of(xsave::FXSAVE_ALIGNMENT) {
+            return false;
+        }
+
+        let mut area = [0u8; xsave::FXSAVE_SIZE];
+        if !self.read_memory(address, &mut area) {
+            return false;
+        }
+
+        self.save_fpu_area(&mut area, xsave::COMPONENT_X87 | xsave::COMPONENT_SSE);
+        return self.write_memory(address, &area);
+    }
+
+    pub fn fxrstor(&mut self, address: u64) -> bool {
+        if !address.is_multiple_of(xsave::FXSAVE_ALIGNMENT) {
+            return false;
+        }
+
+        let mut area = [0u8; xsave::FXSAVE_SIZE];
+        if !self.read_memory(address, &mut area) {
+            return false;
+        }
+
+        let components = xsave::COMPONENT_X87 | xsave::COMPONENT_SSE;
+        self.load_fpu_area(&area, components, components);
+        return true;
+    }
+
+    fn xsave_area_size(components: u64) -> usize {
+        if (components & xsave::COMPONENT_AVX) != 0 {
+            return xsave::XSAVE_SIZE;
+        }
+
+        return xsave::AVX_OFFSET;
+    }
+
+    // Saves the components in `mask` that are supported and marks them in XSTATE_BV.
+    pub fn xsave(&mut self, address: u64, mask: u64) -> bool {
+        if !address.is_multiple_of(xsave::XSAVE_ALIGNMENT) {
+            return false;
+        }
+
+        let components = mask & xsave::SUPPORTED_COMPONENTS;
+        let mut area = vec![0u8; Self::xsave_area_size(components)];
+        if !self.read_memory(address, &mut area) {
+            return false;
+        }
+
+        self.save_fpu_area(&mut area, components);
+
+        let state = xsave::read_u64(&area, xsave::HEADER_OFFSET) | components;
+        area[xsave::HEADER_OFFSET..xsave::HEADER_OFFSET + 8].copy_from_slice(&state.to_le_bytes());
+
+        return self.write_memory(address, &area);
+    }
+
+    // Components in `mask` whose XSTATE_BV bit is clear are set to their initial state.
+    pub fn xrstor(&mut self, address: u64, mask: u64) -> bool {
+        const COMPACTED_FORMAT: u64 = 1 << 63;
+
+        if !address.is_multiple_of(xsave::XSAVE_ALIGNMENT) {
+            return false;
+        }
+
+        let components = mask & xsave::SUPPORTED_COMPONENTS;
+        let mut area = vec![0u8; Self::xsave_area_size(components)];
+        if !self.read_memory(address, &mut area) {
+            return false;
+        }
+
+        let state = xsave::read_u64(&area, xsave::HEADER_OFFSET);
+        let compaction = xsave::read_u64(&area, xsave::HEADER_OFFSET + 8);
+        if (compaction & COMPACTED_FORMAT) != 0 {
+            return false;
+        }
+
+        self.load_fpu_area(&area, components, components & state);
+        return true;
+    }
+
+    pub fn deliver_signal(
+        &mut self,
+        signo: i32,
+        handler: u64,
+        restorer: u64,
+        sigmask: u64,
+    ) -> bool {
+        let mut context = signal::SignalContext::default();
+
+        for (index, reg) in signal::SIGCONTEXT_REGISTERS.iter().enumerate() {
+            context.registers[index] = self.read_register_value(*reg);
+        }
+
+        for (index, reg) in signal::SIGCONTEXT_SEGMENTS.iter().enumerate() {
+            context.segments[index] = self.read_register_value(*reg) as u16;
+        }
+
+        context.eflags = self.reg.get_flags(&mut self.vm.cpu);
+        context.sigmask = sigmask;
+
+        let rsp = self.read_register_value(registers::X86Register::Rsp);
+        let frame_address = signal::frame_address(rsp);
+        let frame = signal::build_frame(&context, signo, restorer);
+
+        if !self.write_memory(frame_address, &frame) {
+            return false;
+        }
+
+        self.write_register_value(registers::X86Register::Rsp, frame_address);
+        self.write_register_value(registers::X86Register::Rdi, signo as u64);
+        self.write_register_value(
+            registers::X86Register::Rsi,
+            frame_address + signal::SIGINFO_OFFSET as u64,
+        );
+        self.write_register_value(
+            registers::X86Register::Rdx,
+            frame_address + signal::UCONTEXT_OFFSET as u64,
+        );
+        self.write_register_value(registers::X86Register::Rax, 0);
+        self.vm.cpu.write_pc(handler);
+
+        return true;
+    }
+
+    // Expects RSP to point just past pretcode, as it does when the restorer issues rt_sigreturn.
+    pub fn sigreturn(&mut self, sigmask: &mut u64) -> bool {
+        let rsp = self.read_register_value(registers::X86Register::Rsp);
+        let frame_address = rsp.wrapping_sub(8);
+
+        let mut frame = [0u8; signal::FRAME_SIZE];
+        if !self.read_memory(frame_address, &mut frame) {
+            return false;
+        }
+
+        let context = signal::parse_frame(&frame);
+
+        for (index, reg) in signal::SIGCONTEXT_REGISTERS.iter().enumerate() {
+            self.write_register_value(*reg, context.registers[index]);
+        }
+
+        for (index, reg) in signal::SIGCONTEXT_SEGMENTS.iter().enumerate() {
+            self.write_register_value(*reg, context.segments[index].into());
+        }
+
+        self.reg.set_flags(&mut self.vm.cpu, context.eflags);
+        self.vm.cpu.write_pc(context.registers[16]);
+
+        *sigmask = context.sigmask;
+        return true;
+    }
+
+    // Return addresses on the shadow stack, most recent first. Empty if the emulator
+    // was created without CREATE_SHADOW_STACK.
+    pub fn get_shadow_stack(&self) -> Vec<u64> {
+        let entries = &self.vm.cpu.shadow_stack.entries;
+        return entries.iter().rev().map(|entry| entry.addr).collect();
+    }
+
+    pub fn get_translation_state(&self, address: u64) -> TranslationState {
+        if self.vm.jit.entry_points.contains_key(&address) {
+            return TranslationState::Jitted;
+        }
+
+        if self.vm.code.map.contains_key(&address) {
+            return TranslationState::Interpreted;
+        }
+
+        return TranslationState::Untranslated;
+    }
+
+    pub fn get_vector_state(&mut self, data: &mut [u8; vector::VECTOR_STATE_SIZE]) {
+        data.fill(0);
+
+        for (index, zmm) in vector::ZMM_REGISTERS.iter().enumerate() {
+            let offset = index * vector::ZMM_SIZE;
+
+            if self.reg.is_available(*zmm) {
+                self.read_generic_register(*zmm, &mut data[offset..offset + vector::ZMM_SIZE]);
+            } else if let Some(ymm) = vector::YMM_REGISTERS.get(index) {
+                self.read_generic_register(*ymm, &mut data[offset..offset + vector::YMM_SIZE]);
+            }
+        }
+
+        for (index, k) in vector::K_REGISTERS.iter().enumerate() {
+            let offset = vector::K_OFFSET + index * vector::K_SIZE;
+            self.read_register(*k, &mut data[offset..offset + vector::K_SIZE]);
+        }
+    }
+
+    pub fn set_vector_state(&mut self, data: &[u8; vector::VECTOR_STATE_SIZE]) {
+        for (index, zmm) in vector::ZMM_REGISTERS.iter().enumerate() {
+            let offset = index * vector::ZMM_SIZE;
+
+            if self.reg.is_available(*zmm) {
+                self.write_generic_register(*zmm, &data[offset..offset + vector::ZMM_SIZE]);
+            } else if let Some(ymm) = vector::YMM_REGISTERS.get(index) {
+                self.write_generic_register(*ymm, &data[offset..offset + vector::YMM_SIZE]);
+            }
+        }
+
+        for (index, k) in vector::K_REGISTERS.iter().enumerate() {
+            let offset = vector::K_OFFSET + index * vector::K_SIZE;
+            self.write_register(*k, &data[offset..offset + vector::K_SIZE]);
+        }
+    }
+
+    pub fn create_snapshot(&mut self) -> u32 {
+        let snap = EmulatorSnapshot {
+            vm: self.vm.snapshot(),
+            mapped_regions: self.mapped_regions.clone(),
+            mmio_regions: self.mmio_regions.clone(),
+            msrs: self.msrs.clone(),
+        };
+
+        let id = self.snapshots.len() as u32;
+        self.snapshots.push(Some(Box::new(snap)));
+
+        return id;
+    }
+
+    // Memory contents, mappings and registers are restored, hooks are left as they are.
+    // A snapshot can be restored any number of times until it is deleted.
+    pub fn restore_snapshot(&mut self, id: u32) -> bool {
+        let snap = match self.snapshots.get(id as usize) {
+            Some(Some(snap)) => snap.as_ref(),
+            _ => return false,
+        };
+
+        self.vm.restore(&snap.vm);
+        self.mapped_regions = snap.mapped_regions.clone();
+        self.mmio_regions = snap.mmio_regions.clone();
+        self.msrs = snap.msrs.clone();
+        return true;
+    }
+
+    // Releases the memory held by the snapshot. Ids are not reused.
+    pub fn delete_snapshot(&mut self, id: u32) -> bool {
+        return match self.snapshots.get_mut(id as usize) {
+            Some(slot) => slot.take().is_some(),
+            None => false,
+        };
+    }
+
+    fn write_flags<T>(&mut self, data: &[u8]) -> usize {
+        const REAL_SIZE: usize = std::mem::size_of::<u64>();
+        let limit: usize = std::mem::size_of::<T>();
+        let size = std::cmp::min(REAL_SIZE, limit);
+        let copy_size = std::cmp::min(data.len(), size);
+
+        let mut buffer = [0u8; REAL_SIZE];
+        self.read_flags::<u64>(&mut buffer);
+
+        buffer[..copy_size].copy_from_slice(&data[..copy_size]);
+
+        let flags = u64::from_ne_bytes(buffer);
+        self.reg.set_flags(&mut self.vm.cpu, flags);
 
-            let reason = self.vm.run();
+        return copy_size;
+    }
 
-            match reason {
-                icicle_vm::VmExit::InstructionLimit => break,
-                icicle_vm::VmExit::UnhandledException((code, value)) => {
-                    let continue_execution = self.handle_exception(code, value);
-                    if !continue_execution {
-                        break;
-                    }
-                }
-                _ => break,
-            };
-        }
+    // False for registers the SLEIGH specification does not define. The vendored one has no
+    // AVX-512 state, so ZMM, K and XMM/YMM16-31 are unavailable and accessing them fails.
+    pub fn has_register(&self, reg: registers::X86Register) -> bool {
+        return self.reg.is_available(reg);
     }
 
-    fn handle_interrupt(&self, code: i32) -> bool {
-        for (_key, func) in self.interrupt_hooks.get_hooks() {
-            func(code);
+    pub fn register_size(&self, reg: registers::X86Register) -> usize {
+        match reg {
+            registers::X86Register::Rflags => 8,
+            registers::X86Register::Eflags => 4,
+            registers::X86Register::Flags => 2,
+            _ if !self.reg.is_available(reg) => 0,
+            _ => self.reg.get_node(reg).size.into(),
         }
-
-        return true;
     }
 
-    fn handle_exception(&mut self, code: ExceptionCode, value: u64) -> bool {
-        let continue_execution = match code {
-            ExceptionCode::Syscall => self.handle_syscall(value),
-            ExceptionCode::ReadPerm => self.handle_violation(value, FOREIGN_READ, false),
-            ExceptionCode::WritePerm => self.handle_violation(value, FOREIGN_WRITE, false),
-            ExceptionCode::ReadUnmapped => self.handle_violation(value, FOREIGN_READ, true),
-            ExceptionCode::WriteUnmapped => self.handle_violation(value, FOREIGN_WRITE, true),
-            ExceptionCode::SoftwareBreakpoint => self.handle_interrupt(3),
-            ExceptionCode::InvalidInstruction => self.handle_interrupt(6),
-            ExceptionCode::DivisionException => self.handle_interrupt(0),
-            _ => false,
-        };
+    // Only registers of at most 8 bytes, None for wider or unavailable registers.
+    pub fn read_register_u64(&mut self, reg: registers::X86Register) -> Option<u64> {
+        let size = self.register_size(reg);
+        if size == 0 || size > 8 {
+            return None;
+        }
 
-        return continue_execution;
+        let mut data = [0u8; 8];
+        self.read_register(reg, &mut data[..size]);
+        return Some(u64::from_le_bytes(data));
     }
 
-    fn handle_violation(&mut self, address: u64, permission: u8, unmapped: bool) -> bool {
-        let hooks = &self.violation_hooks.get_hooks();
-        if hooks.is_empty() {
+    // Fails for registers wider than 8 bytes and values that do not fit the register.
+    pub fn write_register_u64(&mut self, reg: registers::X86Register, value: u64) -> bool {
+        let size = self.register_size(reg);
+        if size == 0 || size > 8 {
             return false;
         }
 
-        let mut continue_execution = true;
-
-        for (_key, func) in self.violation_hooks.get_hooks() {
-            continue_execution &= func(address, permission, unmapped);
+        if size < 8 && (value >> (size * 8)) != 0 {
+            return false;
         }
 
-        return continue_execution;
+        self.write_register(reg, &value.to_le_bytes()[..size]);
+        return true;
     }
 
-    fn handle_syscall(&mut self, value: u64) -> bool {
-        if value != 0 {
-            return self.handle_interrupt(value as i32);
+    // `data` is little-endian. Returns the number of input bytes consumed, bytes past the
+    // register size are ignored. Short input zero-extends, except for the flags registers
+    // where the remaining bits are preserved.
+    pub fn write_register(&mut self, reg: registers::X86Register, data: &[u8]) -> usize {
+        match reg {
+            registers::X86Register::Rflags => self.write_flags::<u64>(data),
+            registers::X86Register::Eflags => self.write_flags::<u32>(data),
+            registers::X86Register::Flags => self.write_flags::<u16>(data),
+            _ if !self.reg.is_available(reg) => 0,
+            _ => self.write_generic_register(reg, data),
         }
+    }
 
-        for (_key, func) in self.syscall_hooks.get_hooks() {
-            func();
+    // Resolves any register the sleigh specification defines, including ones without an
+    // X86Register value. The exact sleigh name is tried first, many of them are mixed case
+    // (e.g. FPUControlWord or XMM0_Qa), then a case-insensitive match. The flags registers
+    // are assembled from the individual flag bits like for read_register.
+    fn find_register_by_name(&self, name: &str) -> Option<NamedRegister> {
+        const MAX_REGISTER_SIZE: u8 = 64;
+        const FLAGS_REGISTERS: [(&str, registers::X86Register); 3] = [
+            ("RFLAGS", registers::X86Register::Rflags),
+            ("EFLAGS", registers::X86Register::Eflags),
+            ("FLAGS", registers::X86Register::Flags),
+        ];
+
+        for (flags_name, reg) in FLAGS_REGISTERS {
+            if name.eq_ignore_ascii_case(flags_name) {
+                return Some(NamedRegister::Flags(reg));
+            }
         }
 
-        self.vm.cpu.write_pc(self.vm.cpu.read_pc() + 2);
-        return true;
-    }
-
-    pub fn stop(&mut self) {
-        self.vm.icount_limit = 0;
+        let sleigh = &self.vm.cpu.arch.sleigh;
+        let info = match sleigh.get_reg(name) {
+            Some(info) => info,
+            None => sleigh
+                .named_registers
+                .iter()
+                .find(|info| sleigh.get_str(info.name).eq_ignore_ascii_case(name))?,
+        };
 
-        if self.executing_thread == std::thread::current().id() {
-            *self.stop.borrow_mut() = true;
+        if info.var.size > MAX_REGISTER_SIZE {
+            return None;
         }
-    }
 
-    pub fn add_block_hook(&mut self, callback: Box<dyn Fn(u64, u64)>) -> u32 {
-        let hook_id = self.execution_hooks.borrow_mut().add_block_hook(callback);
-        return qualify_hook_id(hook_id, HookType::Block);
+        return Some(NamedRegister::Node(info.var));
     }
 
-    pub fn add_violation_hook(&mut self, callback: Box<dyn Fn(u64, u8, bool) -> bool>) -> u32 {
-        let hook_id = self.violation_hooks.add_hook(callback);
-        return qualify_hook_id(hook_id, HookType::Violation);
+    // Returns the register size, 0 if the name is unknown.
+    pub fn read_register_by_name(&mut self, name: &str, data: &mut [u8]) -> usize {
+        return match self.find_register_by_name(name) {
+            Some(NamedRegister::Flags(reg)) => self.read_register(reg, data),
+            Some(NamedRegister::Node(reg_node)) => self.read_node(reg_node, data),
+            None => 0,
+        };
     }
 
-    pub fn add_execution_hook(&mut self, address: u64, callback: Box<dyn Fn(u64)>) -> u32 {
-        let hook_id = self
-            .execution_hooks
-            .borrow_mut()
-            .add_specific_hook(address, callback);
-        return qualify_hook_id(hook_id, HookType::ExecuteSpecific);
+    // Returns the number of bytes consumed like write_register, 0 if the name is unknown.
+    pub fn write_register_by_name(&mut self, name: &str, data: &[u8]) -> usize {
+        return match self.find_register_by_name(name) {
+            Some(NamedRegister::Flags(reg)) => self.write_register(reg, data),
+            Some(NamedRegister::Node(reg_node)) => self.write_node(reg_node, data),
+            None => 0,
+        };
     }
 
-    pub fn add_generic_execution_hook(&mut self, callback: Box<dyn Fn(u64)>) -> u32 {
-        let hook_id = self.execution_hooks.borrow_mut().add_generic_hook(callback);
-        return qualify_hook_id(hook_id, HookType::ExecuteGeneric);
-    }
+    pub fn write_register_checked(
+        &mut self,
+        reg: registers::X86Register,
+        data: &[u8],
+    ) -> Option<usize> {
+        if data.len() > self.register_size(reg) {
+            return None;
+        }
 
-    pub fn add_syscall_hook(&mut self, callback: Box<dyn Fn()>) -> u32 {
-        let hook_id = self.syscall_hooks.add_hook(callback);
-        return qualify_hook_id(hook_id, HookType::Syscall);
+        return Some(self.write_register(reg, data));
     }
 
-    pub fn add_interrupt_hook(&mut self, callback: Box<dyn Fn(i32)>) -> u32 {
-        let hook_id = self.interrupt_hooks.add_hook(callback);
-        return qualify_hook_id(hook_id, HookType::Interrupt);
+    fn write_generic_register(&mut self, reg: registers::X86Register, data: &[u8]) -> usize {
+        let reg_node = self.reg.get_node(reg);
+        return self.write_node(reg_node, data);
     }
 
-    pub fn add_read_hook(
-        &mut self,
-        start: u64,
-        end: u64,
-        callback: Box<dyn Fn(u64, &[u8])>,
-    ) -> u32 {
-        let id = self
-            .get_mem()
-            .add_read_after_hook(start, end, Box::new(MemoryHook { callback }));
-        if id.is_none() {
-            return 0;
+    fn write_node(&mut self, reg_node: pcode::VarNode, data: &[u8]) -> usize {
+        const CHUNK_SIZE: usize = 16;
+
+        let size: usize = reg_node.size.into();
+
+        let mut buffer = [0u8; 64];
+        let len = std::cmp::min(data.len(), buffer.len());
+        buffer[..len].copy_from_slice(&data[..len]);
+
+        // Written in slices of at most 16 bytes so registers of any width are supported.
+        for offset in (0..size).step_by(CHUNK_SIZE) {
+            let length = std::cmp::min(size - offset, CHUNK_SIZE);
+            let node = reg_node.slice(offset as u8, length as u8);
+            write_chunk(&mut self.vm.cpu, node, &buffer[offset..offset + length]);
         }
 
-        return qualify_hook_id(id.unwrap(), HookType::Read);
+        return std::cmp::min(data.len(), reg_node.size.into());
     }
+}
 
-    pub fn add_write_hook(
-        &mut self,
-        start: u64,
-        end: u64,
-        callback: Box<dyn Fn(u64, &[u8])>,
-    ) -> u32 {
-        let id = self
-            .get_mem()
-            .add_write_hook(start, end, Box::new(MemoryHook { callback }));
-        if id.is_none() {
-            return 0;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: u64 = 0x1000;
+    const NOP: u8 = 0x90;
 
-        return qualify_hook_id(id.unwrap(), HookType::Write);
+    fn emulator_with_code(code: &[u8]) -> IcicleEmulator {
+        let mut emulator = IcicleEmulator::new();
+        assert!(emulator.map_memory(CODE, 0x1000, FOREIGN_READ | FOREIGN_EXEC));
+        assert!(emulator.write_memory(CODE, code));
+        emulator.set_pc(CODE);
+        return emulator;
     }
 
-    pub fn remove_hook(&mut self, id: u32) {
-        let (hook_id, hook_type) = split_hook_id(id);
+    #[test]
+    fn reused_hook_ids_start_without_a_tag() {
+        let mut container: HookContainer<dyn Fn() -> u32> = HookContainer::new();
+        let first = container.add_hook(Box::new(|| 1));
+        *container.tag_mut(first).unwrap() = 42;
+        container.remove_hook(first);
 
-        match hook_type {
-            HookType::Syscall => self.syscall_hooks.remove_hook(hook_id),
-            HookType::Violation => self.violation_hooks.remove_hook(hook_id),
-            HookType::Interrupt => self.interrupt_hooks.remove_hook(hook_id),
-            HookType::ExecuteGeneric => self
-                .execution_hooks
-                .borrow_mut()
-                .remove_generic_hook(hook_id),
-            HookType::ExecuteSpecific => self
-                .execution_hooks
-                .borrow_mut()
-                .remove_specific_hook(hook_id),
-            HookType::Block => self.execution_hooks.borrow_mut().remove_block_hook(hook_id),
-            HookType::Read => {
-                self.get_mem().remove_read_after_hook(hook_id);
-                ()
-            }
-            HookType::Write => {
-                self.get_mem().remove_write_hook(hook_id);
-                ()
-            }
-            _ => {}
-        }
+        // Wrap around so that the freed id is handed out again.
+        container.hook_id = MAX_HOOK_ID;
+        let second = container.add_hook(Box::new(|| 2));
+
+        assert_eq!(second, first);
+        assert_eq!(container.tag_mut(second).copied(), Some(0));
+        assert_eq!(container.get(second).map(|callback| callback()), Some(2));
     }
 
-    pub fn map_memory(&mut self, address: u64, length: u64, permissions: u8) -> bool {
-        const MAPPING_PERMISSIONS: u8 = icicle_vm::cpu::mem::perm::MAP
-            | icicle_vm::cpu::mem::perm::INIT
-            | icicle_vm::cpu::mem::perm::IN_CODE_CACHE;
+    #[test]
+    fn reused_breakpoint_ids_start_without_state() {
+        let mut emulator = IcicleEmulator::new();
+        let first = emulator.add_breakpoint(CODE, 0);
+        assert!(emulator.set_hook_tag(first, 7));
 
-        let native_permissions = map_permissions(permissions);
+        emulator.remove_hook(first);
+        assert!(!emulator.set_hook_tag(first, 7));
+        assert_eq!(emulator.get_hook_tag(first), 0);
 
-        let mapping = icicle_vm::cpu::mem::Mapping {
-            perm: native_permissions | MAPPING_PERMISSIONS,
-            value: 0x0,
-        };
+        emulator.breakpoint_id = MAX_HOOK_ID;
+        let second = emulator.add_breakpoint(CODE + 1, 0);
 
-        let layout = icicle_vm::cpu::mem::AllocLayout {
-            addr: Some(address),
-            size: length,
-            align: 0x1000,
-        };
+        assert_eq!(second, first);
+        assert_eq!(emulator.get_hook_tag(second), 0);
+        assert_eq!(emulator.list_breakpoints(), vec![(second, CODE + 1, 0)]);
+    }
+
+    #[test]
+    fn reused_execution_hook_ids_do_not_keep_addresses() {
+        let mut emulator = emulator_with_code(&[NOP; 4]);
+
+        let first_calls = Rc::new(Cell::new(0));
+        let counter = first_calls.clone();
+        let first =
+            emulator.add_execution_hook(CODE, Box::new(move |_| counter.set(counter.get() + 1)));
+        emulator.remove_hook(first);
 
-        let res = self.get_mem().alloc_memory(layout, mapping);
-        return res.is_ok();
+        emulator.execution_hooks.borrow_mut().specific_hooks.hook_id = MAX_HOOK_ID;
+
+        let second_calls = Rc::new(Cell::new(0));
+        let counter = second_calls.clone();
+        let second = emulator
+            .add_execution_hook(CODE + 1, Box::new(move |_| counter.set(counter.get() + 1)));
+        assert_eq!(second, first);
+
+        emulator.start(2);
+        assert_eq!(first_calls.get(), 0);
+        assert_eq!(second_calls.get(), 1);
     }
 
-    pub fn map_mmio(
-        &mut self,
-        address: u64,
-        length: u64,
-        read_function: Box<dyn Fn(u64, &mut [u8])>,
-        write_function: Box<dyn Fn(u64, &[u8])>,
-    ) -> bool {
-        let mem = self.get_mem();
+    const SYSCALL: [u8; 2] = [0x0F, 0x05];
+
+    #[test]
+    fn syscall_hooks_access_registers_through_the_emulator() {
+        let mut emulator = emulator_with_code(&[SYSCALL[0], SYSCALL[1], NOP]);
+        emulator.write_register_value(registers::X86Register::Rax, 60);
+
+        emulator.add_syscall_hook(Box::new(|emulator: &mut IcicleEmulator| {
+            let number = emulator.read_register_value(registers::X86Register::Rax);
+            emulator.write_register_value(registers::X86Register::Rax, number + 1);
+            return SyscallAction::Continue;
+        }));
+
+        emulator.start(1);
+        assert_eq!(
+            emulator.read_register_value(registers::X86Register::Rax),
+            61
+        );
+        assert_eq!(emulator.get_pc(), CODE + 2);
+    }
 
-        let handler = MmioHandler::new(read_function, write_function);
-        let handler_id = mem.register_io_handler(handler);
+    #[test]
+    fn syscall_hooks_may_remove_hooks_while_dispatching() {
+        let mut emulator = emulator_with_code(&[SYSCALL[0], SYSCALL[1], SYSCALL[0], SYSCALL[1]]);
+
+        // Whichever hook runs first removes both, the other one must not run anymore.
+        let ids = Rc::new(RefCell::new(Vec::new()));
+        let calls = Rc::new(Cell::new(0));
+        for _ in 0..2 {
+            let ids_hook = ids.clone();
+            let calls_hook = calls.clone();
+            let id = emulator.add_syscall_hook(Box::new(move |emulator: &mut IcicleEmulator| {
+                calls_hook.set(calls_hook.get() + 1);
+                for id in ids_hook.borrow().iter() {
+                    emulator.remove_hook(*id);
+                }
+                return SyscallAction::Continue;
+            }));
+            ids.borrow_mut().push(id);
+        }
 
-        let layout = icicle_vm::cpu::mem::AllocLayout {
-            addr: Some(address),
-            size: length,
-            align: 0x1000,
-        };
+        emulator.start(2);
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn syscall_hooks_added_while_dispatching_run_from_the_next_syscall() {
+        let mut emulator = emulator_with_code(&[SYSCALL[0], SYSCALL[1], SYSCALL[0], SYSCALL[1]]);
+
+        let calls = Rc::new(Cell::new(0));
+        let calls_hook = calls.clone();
+        emulator.add_syscall_hook(Box::new(move |emulator: &mut IcicleEmulator| {
+            if calls_hook.get() == 0 {
+                let calls_added = calls_hook.clone();
+                emulator.add_syscall_hook(Box::new(move |_: &mut IcicleEmulator| {
+                    calls_added.set(calls_added.get() + 10);
+                    return SyscallAction::Continue;
+                }));
+            }
+            calls_hook.set(calls_hook.get() + 1);
+            return SyscallAction::Continue;
+        }));
 
-        let res = mem.alloc_memory(layout, handler_id);
-        return res.is_ok();
+        emulator.start(1);
+        assert_eq!(calls.get(), 1);
+
+        emulator.start(1);
+        assert_eq!(calls.get(), 12);
     }
 
-    pub fn unmap_memory(&mut self, address: u64, length: u64) -> bool {
-        return self.get_mem().unmap_memory_len(address, length);
+    #[test]
+    fn reset_removes_timers_and_restores_the_creation_registers() {
+        let mut emulator = IcicleEmulator::new();
+        let initial_flags = emulator.read_register_value(registers::X86Register::Rflags);
+
+        assert!(emulator.attach_timer(0x10000, 1000, 32));
+        emulator.write_register_value(registers::X86Register::Rax, 5);
+        emulator.write_register_value(registers::X86Register::Rflags, initial_flags | 1);
+
+        emulator.reset(false);
+
+        assert!(emulator.timers.is_empty());
+        assert!(emulator.execution_hooks.borrow().block_hooks.is_empty());
+        assert_eq!(emulator.read_register_value(registers::X86Register::Rax), 0);
+        assert_eq!(
+            emulator.read_register_value(registers::X86Register::Rflags),
+            initial_flags
+        );
     }
 
-    pub fn protect_memory(&mut self, address: u64, length: u64, permissions: u8) -> bool {
-        let native_permissions = map_permissions(permissions);
-        let res = self
-            .get_mem()
-            .update_perm(address, length, native_permissions);
-        return res.is_ok();
+    #[test]
+    fn run_until_the_current_pc_executes_it_first() {
+        // jmp $
+        let mut emulator = emulator_with_code(&[0xEB, 0xFE]);
+        let start_icount = emulator.get_instruction_count();
+
+        let status = emulator.run_until(CODE, 10);
+        assert!(status == RunUntilStatus::Reached);
+        assert_eq!(emulator.get_pc(), CODE);
+        assert_eq!(emulator.get_instruction_count(), start_icount + 1);
     }
 
-    pub fn write_memory(&mut self, address: u64, data: &[u8]) -> bool {
-        let res = self
-            .get_mem()
-            .write_bytes(address, data, icicle_vm::cpu::mem::perm::NONE);
-        return res.is_ok();
+    #[test]
+    fn run_until_any_steps_off_a_target_under_the_pc() {
+        let mut emulator = emulator_with_code(&[NOP; 4]);
+        let start_icount = emulator.get_instruction_count();
+
+        let mut hit = 0;
+        let reason = emulator.run_until_any(&[CODE, CODE + 3], 0, &mut hit);
+        assert_eq!(reason, StopReason::Breakpoint);
+        assert_eq!(hit, CODE + 3);
+        assert_eq!(emulator.get_instruction_count(), start_icount + 3);
+
+        // A single instruction that lands on a target.
+        emulator.set_pc(CODE + 1);
+        let reason = emulator.run_until_any(&[CODE + 1, CODE + 2], 0, &mut hit);
+        assert_eq!(reason, StopReason::Breakpoint);
+        assert_eq!(hit, CODE + 2);
     }
 
-    pub fn read_memory(&mut self, address: u64, data: &mut [u8]) -> bool {
-        let res = self
-            .get_mem()
-            .read_bytes(address, data, icicle_vm::cpu::mem::perm::NONE);
-        return res.is_ok();
+    #[test]
+    fn run_until_the_exit_address_counts_as_reached() {
+        let mut emulator = emulator_with_code(&[NOP; 4]);
+        emulator.set_exit_address(Some(CODE + 2));
+
+        let status = emulator.run_until(CODE + 2, 0);
+        assert!(status == RunUntilStatus::Reached);
+        assert_eq!(emulator.get_pc(), CODE + 2);
     }
 
-    pub fn save_registers(&self) -> Vec<u8> {
-        const REG_SIZE: usize = std::mem::size_of::<icicle_cpu::Regs>();
-        unsafe {
-            let data: [u8; REG_SIZE] = self.vm.cpu.regs.read_at(0);
-            return data.to_vec();
-        }
+    #[test]
+    fn fnv1a_matches_the_reference_values() {
+        assert_eq!(Fnv1a::new().finish(), 0xCBF29CE484222325);
+
+        let mut hasher = Fnv1a::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xAF63DC4C8601EC8C);
+
+        let mut hasher = Fnv1a::new();
+        hasher.write(b"foobar");
+        assert_eq!(hasher.finish(), 0x85944171F73967E8);
     }
 
-    pub fn restore_registers(&mut self, data: &[u8]) {
-        const REG_SIZE: usize = std::mem::size_of::<icicle_cpu::Regs>();
+    #[test]
+    fn coverage_is_counted_in_the_callers_map() {
+        let mut emulator = emulator_with_code(&[NOP; 4]);
+        let mut map = vec![0u8; 1 << 8];
 
-        let mut buffer: [u8; REG_SIZE] = [0; REG_SIZE];
-        let size = std::cmp::min(REG_SIZE, data.len());
-        buffer.copy_from_slice(&data[..size]);
+        unsafe {
+            assert!(!emulator.enable_coverage(coverage::COVERAGE_BLOCK, map.as_mut_ptr(), 100));
+            assert!(!emulator.enable_coverage(
+                coverage::COVERAGE_BLOCK,
+                map.as_mut_ptr(),
+                coverage::MAX_MAP_SIZE * 2
+            ));
+            assert!(emulator.enable_coverage(
+                coverage::COVERAGE_BLOCK,
+                map.as_mut_ptr(),
+                map.len()
+            ));
+        }
 
+        emulator.start(4);
         unsafe {
-            self.vm.cpu.regs.write_at(0, buffer);
-        };
+            assert!(emulator.enable_coverage(coverage::COVERAGE_DISABLED, std::ptr::null_mut(), 0));
+        }
+
+        assert!(map.iter().any(|count| *count != 0));
     }
 
-    fn read_generic_register(&mut self, reg: registers::X86Register, buffer: &mut [u8]) -> usize {
-        let reg_node = self.reg.get_node(reg);
+    #[test]
+    fn failed_unmaps_keep_the_region_bookkeeping() {
+        let mut emulator = IcicleEmulator::new();
+        emulator.name_region(0x5000, 0x1000, "unmapped");
 
-        let res = self.vm.cpu.read_dynamic(pcode::Value::Var(reg_node));
-        let bytes: [u8; 32] = res.zxt();
+        let status = emulator.unmap_memory_checked(0x5000, 0x1000);
+        assert!(status == Err(MemoryStatus::Unmapped));
+        assert_eq!(emulator.get_region_name(0x5000), Some("unmapped"));
+    }
 
-        let len = std::cmp::min(bytes.len(), buffer.len());
-        buffer[..len].copy_from_slice(&bytes[..len]);
+    #[test]
+    fn decoding_does_not_read_mmio() {
+        const DEVICE: u64 = 0x8000;
+
+        let mut emulator = IcicleEmulator::new();
+        let reads = Rc::new(Cell::new(0));
+        let counter = reads.clone();
+        assert!(emulator.map_mmio(
+            DEVICE,
+            0x1000,
+            Box::new(move |_, data: &mut [u8]| {
+                counter.set(counter.get() + 1);
+                data.fill(NOP);
+                return true;
+            }),
+            Box::new(|_, _: &[u8]| true),
+        ));
+
+        let status = emulator.decode_instruction(DEVICE).err();
+        assert!(status == Some(decode::DecodeStatus::Unreadable));
+        assert_eq!(reads.get(), 0);
+    }
 
-        return reg_node.size.into();
+    #[test]
+    fn idtr_round_trips() {
+        let mut emulator = IcicleEmulator::new();
+        assert!(emulator.set_descriptor_table(DescriptorTable::Idtr, 0xFFFF_8000_0000_1000, 0xFFF));
+        assert_eq!(
+            emulator.get_descriptor_table(DescriptorTable::Idtr),
+            (0xFFFF_8000_0000_1000, 0xFFF)
+        );
+
+        assert!(!emulator.set_descriptor_table(DescriptorTable::Idtr, 0, 0x10000));
+        assert_eq!(
+            emulator.get_descriptor_table(DescriptorTable::Idtr),
+            (0xFFFF_8000_0000_1000, 0xFFF)
+        );
     }
 
-    fn read_flags<T>(&mut self, data: &mut [u8]) -> usize {
-        const REAL_SIZE: usize = std::mem::size_of::<u64>();
-        let limit: usize = std::mem::size_of::<T>();
-        let size = std::cmp::min(REAL_SIZE, limit);
+    #[test]
+    fn register_blobs_with_another_layout_are_rejected() {
+        let mut emulator = IcicleEmulator::new();
+        emulator.write_register_value(registers::X86Register::Rax, 0x1234);
+        let mut blob = emulator.save_registers();
+
+        emulator.write_register_value(registers::X86Register::Rax, 0);
+        blob[12] ^= 1;
+        assert!(!emulator.restore_registers(&blob));
+        assert_eq!(emulator.read_register_value(registers::X86Register::Rax), 0);
+
+        blob[12] ^= 1;
+        assert!(emulator.restore_registers(&blob));
+        assert_eq!(
+            emulator.read_register_value(registers::X86Register::Rax),
+            0x1234
+        );
+    }
 
-        let flags: u64 = self.reg.get_flags(&mut self.vm.cpu);
+    #[test]
+    fn mxcsr_rounding_control_round_trips() {
+        let mut emulator = IcicleEmulator::new();
 
-        let copy_size = std::cmp::min(data.len(), size);
-        data[..copy_size].copy_from_slice(&flags.to_ne_bytes()[..copy_size]);
+        for rounding_control in 0..4u8 {
+            let mut state = emulator.get_fpu_state();
+            state.mxcsr.rounding_control = rounding_control;
+            emulator.set_fpu_state(&state);
 
-        return limit;
+            assert_eq!(
+                emulator.get_fpu_state().mxcsr.rounding_control,
+                rounding_control
+            );
+
+            let mxcsr = emulator.read_register_value(registers::X86Register::Mxcsr);
+            assert_eq!((mxcsr >> 13) & 3, rounding_control as u64);
+        }
     }
 
-    pub fn read_register(&mut self, reg: registers::X86Register, data: &mut [u8]) -> usize {
-        match reg {
-            registers::X86Register::Rflags => self.read_flags::<u64>(data),
-            registers::X86Register::Eflags => self.read_flags::<u32>(data),
-            registers::X86Register::Flags => self.read_flags::<u16>(data),
-            _ => self.read_generic_register(reg, data),
+    // Maps the 4 KiB page at `virtual_address` with a PML4, PDPT, PD and PT placed in the
+    // pages following `root`.
+    fn write_page_tables(
+        emulator: &mut IcicleEmulator,
+        root: u64,
+        virtual_address: u64,
+        physical_address: u64,
+    ) {
+        const PRESENT_WRITE: u64 = 0x3;
+
+        let shifts = [39, 30, 21, 12];
+        for (level, shift) in shifts.iter().enumerate() {
+            let table = root + level as u64 * 0x1000;
+            let index = (virtual_address >> shift) & 0x1FF;
+            let target = match level {
+                3 => physical_address,
+                _ => table + 0x1000,
+            };
+
+            let entry = (target | PRESENT_WRITE).to_le_bytes();
+            assert!(emulator.write_memory(table + index * 8, &entry));
         }
     }
 
-    pub fn create_snapshot(&mut self) -> u32 {
-        let snap = self.vm.snapshot();
+    #[test]
+    fn page_table_walks_follow_cr3_switches() {
+        const FIRST_ROOT: u64 = 0x10000;
+        const SECOND_ROOT: u64 = 0x20000;
+
+        let mut emulator = IcicleEmulator::new();
+        assert!(emulator.map_memory(FIRST_ROOT, 0x20000, FOREIGN_READ | FOREIGN_WRITE));
+        write_page_tables(&mut emulator, FIRST_ROOT, 0x1000, 0x5000);
+        write_page_tables(&mut emulator, SECOND_ROOT, 0x4000_0000, 0x6000);
+
+        emulator.write_msr(msr::IA32_EFER, msr::EFER_LME);
+        emulator.write_register_value(registers::X86Register::Cr4, 0x20);
+        emulator.write_register_value(registers::X86Register::Cr0, 0x8000_0001);
+
+        let walk = |emulator: &mut IcicleEmulator| {
+            let mut mappings = Vec::new();
+            assert!(emulator.walk_page_tables(&mut |mapping: &paging::Mapping| {
+                mappings.push((mapping.virtual_address, mapping.physical_address));
+            }));
+            return mappings;
+        };
 
-        let id = self.snapshots.len() as u32;
-        self.snapshots.push(Box::new(snap));
+        emulator.write_register_value(registers::X86Register::Cr3, FIRST_ROOT);
+        assert_eq!(walk(&mut emulator), vec![(0x1000, 0x5000)]);
 
-        return id;
+        emulator.write_register_value(registers::X86Register::Cr3, SECOND_ROOT);
+        assert_eq!(walk(&mut emulator), vec![(0x4000_0000, 0x6000)]);
     }
 
-    pub fn restore_snapshot(&mut self, id: u32) {
-        let snap = self.snapshots[id as usize].as_ref();
-        self.vm.restore(&snap);
-    }
+    #[test]
+    fn flat_long_mode_without_page_tables_is_flat() {
+        const ROOT: u64 = 0x10000;
 
-    fn write_flags<T>(&mut self, data: &[u8]) -> usize {
-        const REAL_SIZE: usize = std::mem::size_of::<u64>();
-        let limit: usize = std::mem::size_of::<T>();
-        let size = std::cmp::min(REAL_SIZE, limit);
-        let copy_size = std::cmp::min(data.len(), size);
+        let mut emulator = emulator_with_code(&[NOP]);
+        assert!(emulator.map_memory(ROOT, 0x4000, FOREIGN_READ | FOREIGN_WRITE));
+        assert!(emulator.init_flat_long_mode(0x1000).is_some());
+        emulator.write_register_value(registers::X86Register::Cr3, ROOT);
 
-        let mut buffer = [0u8; REAL_SIZE];
-        self.read_flags::<u64>(&mut buffer);
+        assert!(emulator.get_guest_paging_mode() == AddressingMode::FourLevel);
+        assert!(emulator.get_addressing_mode() == AddressingMode::Flat);
+        assert_eq!(emulator.translate_address(CODE), Some(CODE));
 
-        buffer[..copy_size].copy_from_slice(&data[..copy_size]);
+        write_page_tables(&mut emulator, ROOT, CODE, 0x5000);
+        assert!(emulator.get_addressing_mode() == AddressingMode::FourLevel);
+        assert_eq!(emulator.translate_address(CODE), Some(0x5000));
+    }
 
-        let flags = u64::from_ne_bytes(buffer);
-        self.reg.set_flags(&mut self.vm.cpu, flags);
+    #[test]
+    fn fs_relative_loads_read_from_the_fs_base() {
+        const TLS: u64 = 0x4000;
+
+        // mov rax, qword ptr fs:[0x10]
+        let mut emulator =
+            emulator_with_code(&[0x64, 0x48, 0x8B, 0x04, 0x25, 0x10, 0x00, 0x00, 0x00]);
+        assert!(emulator.map_memory(TLS, 0x1000, FOREIGN_READ | FOREIGN_WRITE));
+        assert!(emulator.write_memory(TLS + 0x10, &0x1122_3344_5566_7788u64.to_le_bytes()));
+        emulator.write_register_value(registers::X86Register::FsBase, TLS);
+
+        emulator.start(1);
+        assert_eq!(
+            emulator.read_register_value(registers::X86Register::Rax),
+            0x1122_3344_5566_7788
+        );
+    }
 
-        return limit;
+    #[test]
+    fn search_finds_patterns_across_adjacent_mappings() {
+        let mut emulator = IcicleEmulator::new();
+        assert!(emulator.map_memory(0x10000, 0x1000, FOREIGN_READ));
+        assert!(emulator.map_memory(0x11000, 0x1000, FOREIGN_READ | FOREIGN_WRITE));
+        assert!(emulator.write_memory(0x10FFE, b"ABCD"));
+
+        let results = emulator.search_memory(0, u64::MAX, b"ABCD", None, 8);
+        assert_eq!(results, vec![0x10FFE]);
     }
 
-    pub fn write_register(&mut self, reg: registers::X86Register, data: &[u8]) -> usize {
-        match reg {
-            registers::X86Register::Rflags => self.write_flags::<u64>(data),
-            registers::X86Register::Eflags => self.write_flags::<u32>(data),
-            registers::X86Register::Flags => self.write_flags::<u16>(data),
-            _ => self.write_generic_register(reg, data),
+    #[test]
+    fn step_count_ignores_the_limit_policy() {
+        for policy in [LimitPolicy::Pause, LimitPolicy::Extend] {
+            let mut emulator = emulator_with_code(&[NOP; 8]);
+            emulator.set_limit_policy(policy);
+            emulator.set_limit_callback(Box::new(|_icount| 4));
+
+            let mut executed = 0;
+            assert_eq!(
+                emulator.step_count(2, &mut executed),
+                StopReason::InstructionLimit
+            );
+            assert_eq!(executed, 2);
+            assert_eq!(
+                emulator.read_register_value(registers::X86Register::Rip),
+                CODE + 2
+            );
         }
     }
 
-    fn write_generic_register(&mut self, reg: registers::X86Register, data: &[u8]) -> usize {
-        let reg_node = self.reg.get_node(reg);
+    #[test]
+    fn steps_ignore_the_limit_policy() {
+        let mut emulator = emulator_with_code(&[NOP; 8]);
+        emulator.set_limit_policy(LimitPolicy::Extend);
+        emulator.set_limit_callback(Box::new(|_icount| 4));
+
+        assert_eq!(emulator.step(0), StopReason::InstructionLimit);
+        assert_eq!(
+            emulator.read_register_value(registers::X86Register::Rip),
+            CODE + 1
+        );
+
+        emulator.set_limit_policy(LimitPolicy::Pause);
+        assert_eq!(emulator.step(STEP_OVER_REP), StopReason::InstructionLimit);
+        assert_eq!(
+            emulator.read_register_value(registers::X86Register::Rip),
+            CODE + 2
+        );
+    }
 
-        let mut buffer = [0u8; 32];
-        let len = std::cmp::min(data.len(), buffer.len());
-        buffer[..len].copy_from_slice(&data[..len]);
+    #[test]
+    fn invlpg_keeps_translated_code() {
+        // nop; nop; nop; nop; invlpg [rax]; nop
+        let mut emulator = emulator_with_code(&[NOP, NOP, NOP, NOP, 0x0F, 0x01, 0x38, NOP]);
+        emulator.write_register_value(registers::X86Register::Rax, CODE);
+
+        assert_eq!(emulator.start(4), StopReason::InstructionLimit);
+        let cached = emulator.get_code_cache_size();
+        assert_ne!(cached, 0);
+
+        assert_eq!(emulator.start(2), StopReason::InstructionLimit);
+        assert_eq!(emulator.get_pc(), CODE + 8);
+        assert!(emulator.get_code_cache_size() >= cached);
+    }
+
+    #[test]
+    fn register_nodes_round_trip_64_bytes() {
+        // The spec has no ZMM registers, YMM0 and YMM1 are adjacent and stand in for one.
+        let mut emulator = IcicleEmulator::new();
+        let ymm0 = emulator.reg.get_node(registers::X86Register::Ymm0);
+        let node = pcode::VarNode { size: 64, ..ymm0 };
+
+        let pattern: Vec<u8> = (0..64).map(|index| 0xA0 ^ (index * 7) as u8).collect();
+        assert_eq!(emulator.write_node(node, &pattern), 64);
+
+        let mut value = [0u8; 64];
+        assert_eq!(emulator.read_node(node, &mut value), 64);
+        assert_eq!(value.to_vec(), pattern);
+
+        let mut ymm1 = [0u8; 32];
+        emulator.read_register(registers::X86Register::Ymm1, &mut ymm1);
+        assert_eq!(ymm1, pattern[32..]);
+    }
 
-        //let value = icicle_cpu::regs::DynamicValue::new(buffer, reg_node.size.into());
-        //self.vm.cpu.write_trunc(reg_node, value);
-
-        let cpu = &mut self.vm.cpu;
-
-        match reg_node.size {
-            1 => cpu.write_var::<[u8; 1]>(reg_node, buffer[..1].try_into().unwrap()),
-            2 => cpu.write_var::<[u8; 2]>(reg_node, buffer[..2].try_into().unwrap()),
-            3 => cpu.write_var::<[u8; 3]>(reg_node, buffer[..3].try_into().unwrap()),
-            4 => cpu.write_var::<[u8; 4]>(reg_node, buffer[..4].try_into().unwrap()),
-            5 => cpu.write_var::<[u8; 5]>(reg_node, buffer[..5].try_into().unwrap()),
-            6 => cpu.write_var::<[u8; 6]>(reg_node, buffer[..6].try_into().unwrap()),
-            7 => cpu.write_var::<[u8; 7]>(reg_node, buffer[..7].try_into().unwrap()),
-            8 => cpu.write_var::<[u8; 8]>(reg_node, buffer[..8].try_into().unwrap()),
-            9 => cpu.write_var::<[u8; 9]>(reg_node, buffer[..9].try_into().unwrap()),
-            10 => cpu.write_var::<[u8; 10]>(reg_node, buffer[..10].try_into().unwrap()),
-            11 => cpu.write_var::<[u8; 11]>(reg_node, buffer[..11].try_into().unwrap()),
-            12 => cpu.write_var::<[u8; 12]>(reg_node, buffer[..12].try_into().unwrap()),
-            13 => cpu.write_var::<[u8; 13]>(reg_node, buffer[..13].try_into().unwrap()),
-            14 => cpu.write_var::<[u8; 14]>(reg_node, buffer[..14].try_into().unwrap()),
-            15 => cpu.write_var::<[u8; 15]>(reg_node, buffer[..15].try_into().unwrap()),
-            16 => cpu.write_var::<[u8; 16]>(reg_node, buffer[..16].try_into().unwrap()),
-            _ => panic!("invalid dynamic value size"),
-        }
-
-        return reg_node.size.into();
+    #[test]
+    fn memory_batches_reject_short_buffers() {
+        let mut emulator = IcicleEmulator::new();
+        assert!(emulator.map_memory(0x2000, 0x1000, FOREIGN_READ | FOREIGN_WRITE));
+
+        let addresses = [0x2000, 0x2010];
+        let sizes = [4, 8];
+        let mut lengths = [usize::MAX; 2];
+
+        let mut buffer = [0u8; 11];
+        assert_eq!(
+            emulator.read_memory_batch(&addresses, &sizes, &mut buffer, &mut lengths),
+            0
+        );
+        assert_eq!(
+            emulator.write_memory_batch(&addresses, &sizes, &buffer, &mut lengths),
+            0
+        );
+        assert_eq!(lengths, [usize::MAX; 2]);
+
+        let mut buffer = [0u8; 12];
+        let mut short_lengths = [0usize; 1];
+        assert_eq!(
+            emulator.read_memory_batch(&addresses, &sizes, &mut buffer, &mut short_lengths),
+            0
+        );
+        assert_eq!(
+            emulator.write_memory_batch(&addresses, &sizes, &buffer, &mut short_lengths),
+            0
+        );
+
+        assert_eq!(
+            emulator.read_memory_batch(&addresses, &sizes, &mut buffer, &mut lengths),
+            2
+        );
+        assert_eq!(lengths, [4, 8]);
     }
 }
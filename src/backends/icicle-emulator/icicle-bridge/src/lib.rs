@@ -1,8 +1,53 @@
+// Every icicle_* export is an unsafe extern "C" function: `ptr` must come from one of the
+// icicle_create_emulator functions and not be destroyed yet, every other pointer must be
+// valid for the access described next to the export.
+#![allow(clippy::missing_safety_doc)]
+// Returns are written out explicitly throughout the crate.
+#![allow(clippy::needless_return)]
+
+mod api;
+mod coverage;
+mod decode;
+mod delta;
+mod fpu;
+#[cfg(feature = "gdb")]
+mod gdb;
 mod icicle;
+mod mmio_log;
+mod msr;
+mod paging;
+mod port;
+mod regions;
 mod registers;
+mod signal;
+mod stack_tracker;
+mod timer;
+mod trace;
+mod vector;
+mod watchpoint;
+mod xsave;
 
-use icicle::IcicleEmulator;
+use decode::DecodedInsn;
+use decode::FaultInsn;
+use fpu::FpuState;
+use icicle::ArchInfo;
+use icicle::DescriptorTable;
+use icicle::ExitException;
+use icicle::GpRegs;
+use icicle::LimitPolicy;
+use icicle::MemoryStatus;
+use icicle::MmioRange;
+use icicle::PermissionAudit;
+use icicle::PortHandler;
+use icicle::SyscallMode;
+use icicle::WxPolicy;
 use registers::X86Register;
+
+pub use api::{Emulator, Error, HookId, PERM_EXEC, PERM_READ, PERM_WRITE};
+pub use icicle::{IcicleEmulator, StopHandle, StopReason, SyscallAction, VmConfig};
+pub use registers::X86Register as X64Register;
+use std::ffi::CStr;
+use std::os::raw::c_char;
 use std::os::raw::c_void;
 
 fn to_cbool(value: bool) -> i32 {
@@ -14,345 +59,2826 @@ fn to_cbool(value: bool) -> i32 {
 }
 
 #[unsafe(no_mangle)]
-pub fn icicle_create_emulator() -> *mut c_void {
+pub extern "C" fn icicle_create_emulator() -> *mut c_void {
     let emulator = Box::new(IcicleEmulator::new());
     return Box::into_raw(emulator) as *mut c_void;
 }
 
+// `flags` combines the CREATE_* constants in icicle.rs, bit 0 enables the shadow stack.
+#[unsafe(no_mangle)]
+pub extern "C" fn icicle_create_emulator_with_flags(flags: u32) -> *mut c_void {
+    let emulator = Box::new(IcicleEmulator::with_flags(flags));
+    return Box::into_raw(emulator) as *mut c_void;
+}
+
+// Non-zero fields enable the corresponding option.
+#[repr(C)]
+pub struct EmulatorConfig {
+    enable_jit: u8,
+    enable_shadow_stack: u8,
+    enable_recompilation: u8,
+    track_uninitialized: u8,
+    optimize_instructions: u8,
+    optimize_block: u8,
+}
+
+// A null `config` creates the emulator with the defaults of icicle_create_emulator.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_create_emulator_with_config(
+    config: *const EmulatorConfig,
+) -> *mut c_void {
+    let vm_config = if config.is_null() {
+        VmConfig::default()
+    } else {
+        let config = unsafe { &*config };
+        VmConfig {
+            enable_jit: config.enable_jit != 0,
+            enable_shadow_stack: config.enable_shadow_stack != 0,
+            enable_recompilation: config.enable_recompilation != 0,
+            track_uninitialized: config.track_uninitialized != 0,
+            optimize_instructions: config.optimize_instructions != 0,
+            optimize_block: config.optimize_block != 0,
+        }
+    };
+
+    let emulator = Box::new(IcicleEmulator::with_config(&vm_config));
+    return Box::into_raw(emulator) as *mut c_void;
+}
+
+// `count` is an instruction budget, 0 runs until another stop condition. Exhausting it
+// stops with StopReason::InstructionLimit.
 #[unsafe(no_mangle)]
-pub fn icicle_start(ptr: *mut c_void, count: usize) {
+pub unsafe extern "C" fn icicle_start(ptr: *mut c_void, count: usize) -> i32 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        emulator.start(count as u64);
+        return emulator.start(count as u64) as i32;
     }
 }
 
 #[unsafe(no_mangle)]
-pub fn icicle_stop(ptr: *mut c_void) {
+pub unsafe extern "C" fn icicle_get_instruction_count(ptr: *mut c_void) -> u64 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        emulator.stop();
+        return emulator.get_instruction_count();
     }
 }
 
-type RawFunction = extern "C" fn(*mut c_void);
-type PtrFunction = extern "C" fn(*mut c_void, u64);
-type BlockFunction = extern "C" fn(*mut c_void, u64, u64);
-type DataFunction = extern "C" fn(*mut c_void, *const c_void, usize);
-type MmioReadFunction = extern "C" fn(*mut c_void, u64, *mut c_void, usize);
-type MmioWriteFunction = extern "C" fn(*mut c_void, u64, *const c_void, usize);
-type ViolationFunction = extern "C" fn(*mut c_void, u64, u8, i32) -> i32;
-type InterruptFunction = extern "C" fn(*mut c_void, i32);
-type MemoryAccessFunction = MmioWriteFunction;
+// Fills `info` with the pointer size in bytes, the byte order (0 little, 1 big endian) and
+// the architecture name of the emulated target.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_arch_info(ptr: *mut c_void, info: *mut ArchInfo) -> i32 {
+    if info.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        *info = emulator.get_arch_info();
+        return 1;
+    }
+}
+
+// Reads the CPU's PC directly instead of going through the RIP register.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_pc(ptr: *mut c_void) -> u64 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.get_pc();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_set_pc(ptr: *mut c_void, pc: u64) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.set_pc(pc);
+    }
+}
 
+// `flags` combines the STEP_* constants in icicle.rs, bit 0 runs rep-prefixed string
+// instructions to completion instead of one iteration. That run has no instruction limit,
+// so a rep instruction with a huge RCX runs until it is done.
 #[unsafe(no_mangle)]
-pub fn icicle_map_mmio(
+pub unsafe extern "C" fn icicle_step(ptr: *mut c_void, flags: u32) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.step(flags) as i32;
+    }
+}
+
+// `executed` receives the number of instructions run and may be null. The limit policy
+// does not apply, a completed count always reports StopReason::InstructionLimit.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_step_count(
     ptr: *mut c_void,
-    address: u64,
-    length: u64,
-    read_cb: MmioReadFunction,
-    read_data: *mut c_void,
-    write_cb: MmioWriteFunction,
-    write_data: *mut c_void,
+    count: u64,
+    executed: *mut u64,
 ) -> i32 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
 
-        let read_wrapper = Box::new(move |addr: u64, data: &mut [u8]| {
-            let raw_pointer: *mut u8 = data.as_mut_ptr();
-            read_cb(read_data, addr, raw_pointer as *mut c_void, data.len());
-        });
+        let mut steps: u64 = 0;
+        let reason = emulator.step_count(count, &mut steps);
 
-        let write_wrapper = Box::new(move |addr: u64, data: &[u8]| {
-            let raw_pointer: *const u8 = data.as_ptr();
-            write_cb(write_data, addr, raw_pointer as *const c_void, data.len());
-        });
+        if !executed.is_null() {
+            *executed = steps;
+        }
 
-        let res = emulator.map_mmio(address, length, read_wrapper, write_wrapper);
-        return to_cbool(res);
+        return reason as i32;
     }
 }
 
 #[unsafe(no_mangle)]
-pub fn icicle_map_memory(ptr: *mut c_void, address: u64, length: u64, permissions: u8) -> i32 {
+pub unsafe extern "C" fn icicle_run_until_syscall(ptr: *mut c_void, max_instructions: u64) -> i32 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        let res = emulator.map_memory(address, length, permissions);
-        return to_cbool(res);
+        return emulator.run_until_syscall(max_instructions) as i32;
     }
 }
 
+// 0 reports SYSCALL to the syscall hooks, 1 emulates the transition to the LSTAR entry point.
 #[unsafe(no_mangle)]
-pub fn icicle_unmap_memory(ptr: *mut c_void, address: u64, length: u64) -> i32 {
+pub unsafe extern "C" fn icicle_set_syscall_mode(ptr: *mut c_void, mode: i32) -> i32 {
+    let syscall_mode = match SyscallMode::from_i32(mode) {
+        Some(syscall_mode) => syscall_mode,
+        None => return 0,
+    };
+
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        let res = emulator.unmap_memory(address, length);
-        return to_cbool(res);
+        emulator.set_syscall_mode(syscall_mode);
+        return 1;
     }
 }
 
 #[unsafe(no_mangle)]
-pub fn icicle_protect_memory(ptr: *mut c_void, address: u64, length: u64, permissions: u8) -> i32 {
+pub unsafe extern "C" fn icicle_read_msr(ptr: *mut c_void, index: u32) -> u64 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        let res = emulator.protect_memory(address, length, permissions);
-        return to_cbool(res);
+        return emulator.read_msr(index);
     }
 }
 
 #[unsafe(no_mangle)]
-pub fn icicle_write_memory(
-    ptr: *mut c_void,
-    address: u64,
-    data: *const c_void,
-    size: usize,
-) -> i32 {
-    if size == 0 {
-        return 1;
+pub unsafe extern "C" fn icicle_write_msr(ptr: *mut c_void, index: u32, value: u64) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.write_msr(index, value);
     }
+}
 
-    if data.is_null() {
+// `which` is 0 for GDTR, 1 for IDTR and 2 for LDTR.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_descriptor_table(
+    ptr: *mut c_void,
+    which: i32,
+    base: *mut u64,
+    limit: *mut u32,
+) -> i32 {
+    if base.is_null() || limit.is_null() {
         return 0;
     }
 
+    let table = match DescriptorTable::from_i32(which) {
+        Some(table) => table,
+        None => return 0,
+    };
+
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        let u8_slice = std::slice::from_raw_parts(data as *const u8, size);
-        let res = emulator.write_memory(address, u8_slice);
-        return to_cbool(res);
+        let (table_base, table_limit) = emulator.get_descriptor_table(table);
+
+        *base = table_base;
+        *limit = table_limit;
+        return 1;
     }
 }
 
+// Fails for GDTR and IDTR limits above 0xFFFF.
 #[unsafe(no_mangle)]
-pub fn icicle_save_registers(ptr: *mut c_void, accessor: DataFunction, accessor_data: *mut c_void) {
+pub unsafe extern "C" fn icicle_set_descriptor_table(
+    ptr: *mut c_void,
+    which: i32,
+    base: u64,
+    limit: u32,
+) -> i32 {
+    let table = match DescriptorTable::from_i32(which) {
+        Some(table) => table,
+        None => return 0,
+    };
+
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        let registers = emulator.save_registers();
-        accessor(
-            accessor_data,
-            registers.as_ptr() as *const c_void,
-            registers.len(),
-        );
+        return to_cbool(emulator.set_descriptor_table(table, base, limit));
     }
 }
 
+// `executed` receives the number of instructions run and may be null.
 #[unsafe(no_mangle)]
-pub fn icicle_restore_registers(ptr: *mut c_void, data: *const c_void, size: usize) {
-    if size == 0 || data.is_null() {
-        return;
+pub unsafe extern "C" fn icicle_run_with_deadline(
+    ptr: *mut c_void,
+    millis: u64,
+    executed: *mut u64,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+
+        let mut count: u64 = 0;
+        let reason = emulator.run_with_deadline(millis, &mut count);
+
+        if !executed.is_null() {
+            *executed = count;
+        }
+
+        return reason as i32;
     }
+}
 
+// Runs until `millis` of wall-clock time have passed, see icicle_run_with_deadline.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_start_with_timeout(ptr: *mut c_void, millis: u64) -> i32 {
     unsafe {
-        let emulator = &mut *(ptr as *mut IcicleEmulator);
-        let u8_slice = std::slice::from_raw_parts(data as *const u8, size);
-        emulator.restore_registers(u8_slice);
+        return icicle_run_with_deadline(ptr, millis, std::ptr::null_mut());
     }
 }
 
 #[unsafe(no_mangle)]
-pub fn icicle_create_snapshot(ptr: *mut c_void) -> u32 {
+pub unsafe extern "C" fn icicle_get_call_depth(ptr: *mut c_void) -> i64 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        return emulator.create_snapshot();
+        return emulator.get_call_depth();
     }
 }
 
+// `depth` receives the call depth after the run and may be null.
 #[unsafe(no_mangle)]
-pub fn icicle_restore_snapshot(ptr: *mut c_void, id: u32) {
+pub unsafe extern "C" fn icicle_run_until_depth(
+    ptr: *mut c_void,
+    target_depth: i64,
+    max_instructions: u64,
+    depth: *mut i64,
+) -> i32 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        emulator.restore_snapshot(id);
+        let reason = emulator.run_until_depth(target_depth, max_instructions);
+
+        if !depth.is_null() {
+            *depth = emulator.get_call_depth();
+        }
+
+        return reason as i32;
     }
 }
 
 #[unsafe(no_mangle)]
-pub fn icicle_read_memory(ptr: *mut c_void, address: u64, data: *mut c_void, size: usize) -> i32 {
-    if size == 0 {
+pub unsafe extern "C" fn icicle_set_limit_policy(ptr: *mut c_void, policy: i32) -> i32 {
+    let limit_policy = match LimitPolicy::from_i32(policy) {
+        Some(limit_policy) => limit_policy,
+        None => return 0,
+    };
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.set_limit_policy(limit_policy);
         return 1;
     }
+}
 
-    if data.is_null() {
-        return 0;
+// The callback receives the current instruction count and returns how many
+// more instructions to run, 0 stops at the limit.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_set_limit_callback(
+    ptr: *mut c_void,
+    callback: LimitFunction,
+    data: *mut c_void,
+) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.set_limit_callback(Box::new(move |icount: u64| callback(data, icount)));
     }
+}
 
+// `flags` combines the BREAKPOINT_* constants in icicle.rs, bit 0 makes the breakpoint
+// temporary. Returns the breakpoint id, which can also be passed to icicle_remove_hook.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_breakpoint(ptr: *mut c_void, address: u64, flags: u32) -> u32 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        let u8_slice = std::slice::from_raw_parts_mut(data as *mut u8, size);
-        let res = emulator.read_memory(address, u8_slice);
-        return to_cbool(res);
+        return emulator.add_breakpoint(address, flags);
     }
 }
 
 #[unsafe(no_mangle)]
-pub fn icicle_add_interrupt_hook(
-    ptr: *mut c_void,
-    callback: InterruptFunction,
-    data: *mut c_void,
-) -> u32 {
+pub unsafe extern "C" fn icicle_remove_breakpoint(ptr: *mut c_void, id: u32) -> i32 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        return emulator.add_interrupt_hook(Box::new(move |code: i32| callback(data, code)));
+        return to_cbool(emulator.remove_breakpoint(id));
     }
 }
 
+// Fills up to `capacity` entries of the arrays, which may be null, and returns the number
+// of breakpoints.
 #[unsafe(no_mangle)]
-pub fn icicle_add_violation_hook(
+pub unsafe extern "C" fn icicle_list_breakpoints(
     ptr: *mut c_void,
-    callback: ViolationFunction,
-    data: *mut c_void,
-) -> u32 {
+    ids: *mut u32,
+    addresses: *mut u64,
+    capacity: usize,
+) -> usize {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        return emulator.add_violation_hook(Box::new(
-            move |address: u64, permission: u8, unmapped: bool| {
-                let result = callback(data, address, permission, to_cbool(unmapped));
-                if result == 0 {
-                    return false;
-                }
+        let breakpoints = emulator.list_breakpoints();
 
-                return true;
-            },
-        ));
+        for (index, (id, address, _flags)) in breakpoints.iter().take(capacity).enumerate() {
+            if !ids.is_null() {
+                *ids.add(index) = *id;
+            }
+
+            if !addresses.is_null() {
+                *addresses.add(index) = *address;
+            }
+        }
+
+        return breakpoints.len();
     }
 }
 
+// Returns the id of the breakpoint the last run stopped on, 0 if it did not stop on one.
+// `address` receives its address and may be null.
 #[unsafe(no_mangle)]
-pub fn icicle_add_read_hook(
-    ptr: *mut c_void,
-    start: u64,
-    end: u64,
-    callback: MemoryAccessFunction,
-    user: *mut c_void,
-) -> u32 {
+pub unsafe extern "C" fn icicle_get_breakpoint_hit(ptr: *mut c_void, address: *mut u64) -> u32 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        return emulator.add_read_hook(
-            start,
-            end,
-            Box::new(move |address: u64, data: &[u8]| {
-                callback(user, address, data.as_ptr() as *const c_void, data.len());
-            }),
-        );
+        let (id, hit_address) = match emulator.get_breakpoint_hit() {
+            Some(hit) => hit,
+            None => return 0,
+        };
+
+        if !address.is_null() {
+            *address = hit_address;
+        }
+
+        return id;
     }
 }
 
+// Blocks until a gdb connected to 127.0.0.1:`port` detaches or disconnects. The emulator
+// runs on the calling thread whenever gdb continues or steps. Returns 0 if the port could
+// not be listened on or no connection was accepted. Only built with the "gdb" feature.
+#[cfg(feature = "gdb")]
 #[unsafe(no_mangle)]
-pub fn icicle_add_write_hook(
-    ptr: *mut c_void,
-    start: u64,
-    end: u64,
-    callback: MemoryAccessFunction,
-    user: *mut c_void,
-) -> u32 {
+pub unsafe extern "C" fn icicle_start_gdb_server(ptr: *mut c_void, port: u16) -> i32 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        return emulator.add_write_hook(
-            start,
-            end,
-            Box::new(move |address: u64, data: &[u8]| {
-                callback(user, address, data.as_ptr() as *const c_void, data.len());
-            }),
-        );
+        return to_cbool(gdb::serve(emulator, port));
     }
 }
 
 #[unsafe(no_mangle)]
-pub fn icicle_add_syscall_hook(ptr: *mut c_void, callback: RawFunction, data: *mut c_void) -> u32 {
+pub unsafe extern "C" fn icicle_set_exit_address(ptr: *mut c_void, address: u64) {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        return emulator.add_syscall_hook(Box::new(move || callback(data)));
+        emulator.set_exit_address(Some(address));
     }
 }
 
 #[unsafe(no_mangle)]
-pub fn icicle_add_block_hook(ptr: *mut c_void, callback: BlockFunction, data: *mut c_void) -> u32 {
+pub unsafe extern "C" fn icicle_clear_exit_address(ptr: *mut c_void) {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        return emulator.add_block_hook(Box::new(move |address: u64, instructions: u64| {
-            callback(data, address, instructions)
-        }));
+        emulator.set_exit_address(None);
     }
 }
 
+// Returns -1 if no exit address is set, more than six arguments are passed,
+// or the return address cannot be pushed.
 #[unsafe(no_mangle)]
-pub fn icicle_add_generic_execution_hook(
+pub unsafe extern "C" fn icicle_call(
     ptr: *mut c_void,
-    callback: PtrFunction,
-    data: *mut c_void,
-) -> u32 {
+    function: u64,
+    args: *const u64,
+    arg_count: usize,
+    max_instructions: u64,
+    result: *mut u64,
+) -> i32 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        return emulator.add_generic_execution_hook(Box::new(move |ptr: u64| callback(data, ptr)));
+
+        let arg_slice = match arg_count {
+            0 => &[],
+            _ if args.is_null() => return -1,
+            _ => std::slice::from_raw_parts(args, arg_count),
+        };
+
+        let mut return_value: u64 = 0;
+        let reason = emulator.call(function, arg_slice, max_instructions, &mut return_value);
+
+        if !result.is_null() {
+            *result = return_value;
+        }
+
+        return match reason {
+            Some(reason) => reason as i32,
+            None => -1,
+        };
     }
 }
 
+// Returns a RunUntilStatus, 0 if execution reached `address`. `max_instructions` of 0
+// means no limit. The exception of a faulting run is available via
+// icicle_get_exit_exception.
 #[unsafe(no_mangle)]
-pub fn icicle_add_execution_hook(
+pub unsafe extern "C" fn icicle_run_until(
     ptr: *mut c_void,
     address: u64,
-    callback: PtrFunction,
-    data: *mut c_void,
-) -> u32 {
+    max_instructions: u64,
+) -> i32 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        return emulator.add_execution_hook(address, Box::new(move |ptr: u64| callback(data, ptr)));
+        return emulator.run_until(address, max_instructions) as i32;
     }
 }
 
+// Returns -1 without running if `targets` is null while `count` is not 0. A target the PC
+// is already on only stops the run once it is reached again.
 #[unsafe(no_mangle)]
-pub fn icicle_remove_hook(ptr: *mut c_void, id: u32) {
+pub unsafe extern "C" fn icicle_run_until_any(
+    ptr: *mut c_void,
+    targets: *const u64,
+    count: usize,
+    max_instructions: u64,
+    hit: *mut u64,
+) -> i32 {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        emulator.remove_hook(id);
+
+        let target_slice = match count {
+            0 => &[],
+            _ if targets.is_null() => return -1,
+            _ => std::slice::from_raw_parts(targets, count),
+        };
+
+        let mut hit_address: u64 = 0;
+        let reason = emulator.run_until_any(target_slice, max_instructions, &mut hit_address);
+
+        if !hit.is_null() {
+            *hit = hit_address;
+        }
+
+        return reason as i32;
     }
 }
 
+// Returns the ExitException the last run stopped on, 0 if it did not stop on an exception.
+// `value` receives the exception value, e.g. the faulting address, and may be null.
 #[unsafe(no_mangle)]
-pub fn icicle_read_register(
-    ptr: *mut c_void,
-    reg: X86Register,
-    data: *mut c_void,
-    size: usize,
-) -> usize {
-    if size == 0 {
-        return 1;
+pub unsafe extern "C" fn icicle_get_exit_exception(ptr: *mut c_void, value: *mut u64) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let (exception, exception_value) = emulator.get_exit_exception();
+
+        if !value.is_null() {
+            *value = exception_value;
+        }
+
+        return exception as i32;
     }
+}
 
-    if data.is_null() {
-        return 0;
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_has_pending_exception(ptr: *mut c_void) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return to_cbool(emulator.has_pending_exception());
     }
+}
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_clear_exception(ptr: *mut c_void) {
     unsafe {
         let emulator = &mut *(ptr as *mut IcicleEmulator);
-        let u8_slice = std::slice::from_raw_parts_mut(data as *mut u8, size);
-        return emulator.read_register(reg, u8_slice);
+        emulator.clear_exception();
     }
 }
 
 #[unsafe(no_mangle)]
-pub fn icicle_write_register(
-    ptr: *mut c_void,
-    reg: X86Register,
-    data: *const c_void,
-    size: usize,
-) -> usize {
-    if size == 0 {
-        return 1;
+pub unsafe extern "C" fn icicle_stop(ptr: *mut c_void) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.stop();
+    }
+}
+
+// Handle for stopping runs of the emulator from other threads. It only shares the stop
+// flags with the emulator, so it stays valid after icicle_destroy_emulator and must be
+// released with icicle_free_stop_handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_stop_handle(ptr: *mut c_void) -> *mut StopHandle {
+    unsafe {
+        let emulator = &*(ptr as *const IcicleEmulator);
+        return Box::into_raw(Box::new(emulator.stop_handle()));
     }
+}
 
-    if data.is_null() {
-        return 0;
+// Unlike icicle_stop, this may be called from any thread while another thread is inside
+// icicle_start. The run ends with StopReason::StopRequested at the next block boundary. A
+// request made while nothing runs stops the next run immediately.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_request_stop(handle: *mut StopHandle) {
+    if handle.is_null() {
+        return;
     }
 
     unsafe {
-        let emulator = &mut *(ptr as *mut IcicleEmulator);
-        let u8_slice = std::slice::from_raw_parts(data as *const u8, size);
-        return emulator.write_register(reg, u8_slice);
+        (*handle).request_stop();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_free_stop_handle(handle: *mut StopHandle) {
+    if handle.is_null() {
+        return;
+    }
+
+    unsafe {
+        let _ = Box::from_raw(handle);
+    }
+}
+
+type SyscallFunction = extern "C" fn(*mut c_void) -> i32;
+type SyscallContextFunction = extern "C" fn(*mut c_void, *mut c_void) -> i32;
+type PtrFunction = extern "C" fn(*mut c_void, u64);
+type BlockFunction = extern "C" fn(*mut c_void, u64, u64);
+type DataFunction = extern "C" fn(*mut c_void, *const c_void, usize);
+type MmioReadFunction = extern "C" fn(*mut c_void, u64, *mut c_void, usize) -> i32;
+type MmioWriteFunction = extern "C" fn(*mut c_void, u64, *const c_void, usize) -> i32;
+type MmioReadFlagsFunction = extern "C" fn(*mut c_void, u64, *mut c_void, usize, u32) -> i32;
+type MmioWriteFlagsFunction = extern "C" fn(*mut c_void, u64, *const c_void, usize, u32) -> i32;
+type ViolationFunction = extern "C" fn(*mut c_void, u64, u8, i32) -> i32;
+type ExceptionFunction = extern "C" fn(*mut c_void, i32, u64) -> i32;
+type InterruptFunction = extern "C" fn(*mut c_void, i32);
+type DoorbellFunction = extern "C" fn(*mut c_void, u64, u64);
+type WxViolationFunction = extern "C" fn(*mut c_void, u64, u64, u8);
+type TracepointFunction = extern "C" fn(*mut c_void, u64, u64);
+type StackUninitFunction = extern "C" fn(*mut c_void, u64, usize, i64);
+type ReturnCheckFunction = extern "C" fn(*mut c_void, u64, u64, u64);
+type RegionFunction = extern "C" fn(*mut c_void, u64, u64);
+type MappingFunction = extern "C" fn(*mut c_void, u64, u64, u8);
+type ModeTransitionFunction = extern "C" fn(*mut c_void, u8, u8, u64);
+type WatchpointFunction = extern "C" fn(*mut c_void, u64, u64, u64);
+type PageMappingFunction = extern "C" fn(*mut c_void, u64, u64, u64, u8);
+type RegisterDiffFunction = extern "C" fn(*mut c_void, i32, *const c_void, *const c_void, usize);
+type LimitFunction = extern "C" fn(*mut c_void, u64) -> u64;
+type PortReadFunction = extern "C" fn(*mut c_void, u16, u8) -> u32;
+type PortWriteFunction = extern "C" fn(*mut c_void, u16, u8, u32);
+type MemoryAccessFunction = extern "C" fn(*mut c_void, u64, u64, *const c_void, usize);
+
+// The callbacks return 0 to accept the access. Any other value rejects it and the guest
+// access fails like an access to unmapped memory.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_map_mmio(
+    ptr: *mut c_void,
+    address: u64,
+    length: u64,
+    read_cb: MmioReadFunction,
+    read_data: *mut c_void,
+    write_cb: MmioWriteFunction,
+    write_data: *mut c_void,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+
+        let read_wrapper = Box::new(move |addr: u64, data: &mut [u8]| {
+            let raw_pointer: *mut u8 = data.as_mut_ptr();
+            return read_cb(read_data, addr, raw_pointer as *mut c_void, data.len()) == 0;
+        });
+
+        let write_wrapper = Box::new(move |addr: u64, data: &[u8]| {
+            let raw_pointer: *const u8 = data.as_ptr();
+            return write_cb(write_data, addr, raw_pointer as *const c_void, data.len()) == 0;
+        });
+
+        let res = emulator.map_mmio(address, length, read_wrapper, write_wrapper);
+        return to_cbool(res);
+    }
+}
+
+// Returns a MemoryStatus. On overlap the conflicting mapping is written to
+// conflict_start/conflict_end when they are non-null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_map_mmio_ex(
+    ptr: *mut c_void,
+    address: u64,
+    length: u64,
+    read_cb: MmioReadFunction,
+    read_data: *mut c_void,
+    write_cb: MmioWriteFunction,
+    write_data: *mut c_void,
+    conflict_start: *mut u64,
+    conflict_end: *mut u64,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+
+        let read_wrapper = Box::new(move |addr: u64, data: &mut [u8]| {
+            let raw_pointer: *mut u8 = data.as_mut_ptr();
+            return read_cb(read_data, addr, raw_pointer as *mut c_void, data.len()) == 0;
+        });
+
+        let write_wrapper = Box::new(move |addr: u64, data: &[u8]| {
+            let raw_pointer: *const u8 = data.as_ptr();
+            return write_cb(write_data, addr, raw_pointer as *const c_void, data.len()) == 0;
+        });
+
+        let res = emulator.map_mmio_checked(address, length, read_wrapper, write_wrapper);
+        let error = match res {
+            Ok(()) => return MemoryStatus::Success as i32,
+            Err(error) => error,
+        };
+
+        if let Some((start, end)) = error.conflict {
+            if !conflict_start.is_null() {
+                *conflict_start = start;
+            }
+
+            if !conflict_end.is_null() {
+                *conflict_end = end;
+            }
+        }
+
+        return error.status as i32;
+    }
+}
+
+// Like icicle_map_mmio, but the callbacks additionally receive MMIO_ACCESS_* flags telling
+// whether the access is unaligned to its size or extends past the end of the window.
+// Returns a MemoryStatus.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_map_mmio_with_flags(
+    ptr: *mut c_void,
+    address: u64,
+    length: u64,
+    read_cb: MmioReadFlagsFunction,
+    read_data: *mut c_void,
+    write_cb: MmioWriteFlagsFunction,
+    write_data: *mut c_void,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+
+        let read_wrapper = Box::new(move |addr: u64, data: &mut [u8]| {
+            let flags = icicle::mmio_access_flags(address, length, addr, data.len());
+            let raw_pointer: *mut u8 = data.as_mut_ptr();
+            return read_cb(
+                read_data,
+                addr,
+                raw_pointer as *mut c_void,
+                data.len(),
+                flags,
+            ) == 0;
+        });
+
+        let write_wrapper = Box::new(move |addr: u64, data: &[u8]| {
+            let flags = icicle::mmio_access_flags(address, length, addr, data.len());
+            let raw_pointer: *const u8 = data.as_ptr();
+            return write_cb(
+                write_data,
+                addr,
+                raw_pointer as *const c_void,
+                data.len(),
+                flags,
+            ) == 0;
+        });
+
+        return match emulator.map_mmio_checked(address, length, read_wrapper, write_wrapper) {
+            Ok(()) => MemoryStatus::Success as i32,
+            Err(error) => error.status as i32,
+        };
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_attach_timer(
+    ptr: *mut c_void,
+    base: u64,
+    frequency: u64,
+    vector: i32,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let res = emulator.attach_timer(base, frequency, vector);
+        return to_cbool(res);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_detach_timer(ptr: *mut c_void, base: u64) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return to_cbool(emulator.detach_timer(base));
+    }
+}
+
+#[repr(C)]
+pub struct MmioRangeDescriptor {
+    offset: u64,
+    length: u64,
+    read_cb: MmioReadFunction,
+    read_data: *mut c_void,
+    write_cb: MmioWriteFunction,
+    write_data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct RegisterInit {
+    reg: X86Register,
+    data: *const c_void,
+    size: usize,
+}
+
+// With `map` set the region is mapped with `permissions` first, otherwise it must already
+// be mapped. `data` may be null to only map the region, and shorter than `length`.
+#[repr(C)]
+pub struct RegionInit {
+    address: u64,
+    length: u64,
+    permissions: u8,
+    map: u8,
+    data: *const c_void,
+    data_size: usize,
+}
+
+#[repr(C)]
+pub struct StateDescriptor {
+    regions: *const RegionInit,
+    region_count: usize,
+    registers: *const RegisterInit,
+    register_count: usize,
+}
+
+#[repr(i32)]
+enum ApplyStateStatus {
+    Success = 0,
+    InvalidDescriptor,
+    RegionFailed,
+    RegisterFailed,
+}
+
+// Regions are applied before registers, in order, stopping at the first failure.
+// `failed_index` receives the index of the failing element and may be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_apply_state(
+    ptr: *mut c_void,
+    descriptor: *const StateDescriptor,
+    failed_index: *mut usize,
+) -> i32 {
+    if descriptor.is_null() {
+        return ApplyStateStatus::InvalidDescriptor as i32;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let state = &*descriptor;
+
+        let report = |status: ApplyStateStatus, index: usize| {
+            if !failed_index.is_null() {
+                *failed_index = index;
+            }
+
+            return status as i32;
+        };
+
+        if (state.regions.is_null() && state.region_count != 0)
+            || (state.registers.is_null() && state.register_count != 0)
+        {
+            return report(ApplyStateStatus::InvalidDescriptor, 0);
+        }
+
+        let regions = match state.region_count {
+            0 => &[],
+            count => std::slice::from_raw_parts(state.regions, count),
+        };
+
+        for (index, region) in regions.iter().enumerate() {
+            if region.map != 0
+                && !emulator.map_memory(region.address, region.length, region.permissions)
+            {
+                return report(ApplyStateStatus::RegionFailed, index);
+            }
+
+            if region.data.is_null() || region.data_size == 0 {
+                continue;
+            }
+
+            if region.data_size as u64 > region.length {
+                return report(ApplyStateStatus::RegionFailed, index);
+            }
+
+            let data = std::slice::from_raw_parts(region.data as *const u8, region.data_size);
+            if !emulator.write_memory(region.address, data) {
+                return report(ApplyStateStatus::RegionFailed, index);
+            }
+        }
+
+        let registers = match state.register_count {
+            0 => &[],
+            count => std::slice::from_raw_parts(state.registers, count),
+        };
+
+        for (index, register) in registers.iter().enumerate() {
+            if register.data.is_null() || register.size == 0 {
+                return report(ApplyStateStatus::RegisterFailed, index);
+            }
+
+            let data = std::slice::from_raw_parts(register.data as *const u8, register.size);
+            if emulator
+                .write_register_checked(register.reg, data)
+                .is_none()
+            {
+                return report(ApplyStateStatus::RegisterFailed, index);
+            }
+        }
+
+        return ApplyStateStatus::Success as i32;
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_map_mmio_ranges(
+    ptr: *mut c_void,
+    address: u64,
+    length: u64,
+    descriptors: *const MmioRangeDescriptor,
+    count: usize,
+) -> i32 {
+    if descriptors.is_null() || count == 0 {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let descriptor_slice = std::slice::from_raw_parts(descriptors, count);
+
+        let ranges = descriptor_slice
+            .iter()
+            .map(|descriptor| {
+                let read_cb = descriptor.read_cb;
+                let read_data = descriptor.read_data;
+                let write_cb = descriptor.write_cb;
+                let write_data = descriptor.write_data;
+
+                MmioRange {
+                    offset: descriptor.offset,
+                    length: descriptor.length,
+                    read_handler: Box::new(move |addr: u64, data: &mut [u8]| {
+                        let raw_pointer: *mut u8 = data.as_mut_ptr();
+                        return read_cb(read_data, addr, raw_pointer as *mut c_void, data.len())
+                            == 0;
+                    }),
+                    write_handler: Box::new(move |addr: u64, data: &[u8]| {
+                        let raw_pointer: *const u8 = data.as_ptr();
+                        return write_cb(
+                            write_data,
+                            addr,
+                            raw_pointer as *const c_void,
+                            data.len(),
+                        ) == 0;
+                    }),
+                }
+            })
+            .collect();
+
+        let res = emulator.map_mmio_ranges(address, length, ranges);
+        return to_cbool(res.is_ok());
+    }
+}
+
+// Callbacks receive the port number and the access width in bytes (1, 2 or 4).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_set_port_handler(
+    ptr: *mut c_void,
+    read_cb: PortReadFunction,
+    write_cb: PortWriteFunction,
+    data: *mut c_void,
+) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.set_port_handler(PortHandler {
+            read: Box::new(move |port: u16, width: u8| read_cb(data, port, width)),
+            write: Box::new(move |port: u16, width: u8, value: u32| {
+                write_cb(data, port, width, value)
+            }),
+        });
+    }
+}
+
+// Calls `callback` with the address and length of every RWX range of guest RAM and
+// stores the byte counts per permission class in `audit`, which may be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_audit_permissions(
+    ptr: *mut c_void,
+    callback: RegionFunction,
+    data: *mut c_void,
+    audit: *mut PermissionAudit,
+) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let result = emulator
+            .audit_permissions(&|address: u64, length: u64| callback(data, address, length));
+
+        if !audit.is_null() {
+            *audit = result;
+        }
+    }
+}
+
+// Calls `accessor` with the address, length and permissions of every mapped range of guest
+// RAM. Permissions use the same bits as icicle_map_memory. MMIO ranges are not reported.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_mappings(
+    ptr: *mut c_void,
+    accessor: MappingFunction,
+    data: *mut c_void,
+) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.get_mappings(&|address: u64, length: u64, permissions: u8| {
+            accessor(data, address, length, permissions)
+        });
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_mmio_record_start(ptr: *mut c_void) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.start_mmio_recording();
+    }
+}
+
+// Passes the encoded recording to `accessor`, the buffer is only valid during the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_mmio_record_stop(
+    ptr: *mut c_void,
+    accessor: DataFunction,
+    accessor_data: *mut c_void,
+) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let log = emulator.stop_mmio_recording();
+        accessor(accessor_data, log.as_ptr() as *const c_void, log.len());
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_mmio_replay_load(
+    ptr: *mut c_void,
+    data: *const c_void,
+    size: usize,
+) -> i32 {
+    if data.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = std::slice::from_raw_parts(data as *const u8, size);
+        return to_cbool(emulator.start_mmio_replay(u8_slice));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_mmio_replay_stop(ptr: *mut c_void) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.stop_mmio_replay();
+    }
+}
+
+// Returns the index of the first recorded event the replay diverged at, or -1.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_mmio_replay_divergence(ptr: *mut c_void) -> i64 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return match emulator.get_mmio_divergence() {
+            Some(index) => index as i64,
+            None => -1,
+        };
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_doorbell(
+    ptr: *mut c_void,
+    address: u64,
+    exit_on_ring: i32,
+    callback: DoorbellFunction,
+    data: *mut c_void,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let res = emulator.add_doorbell(
+            address,
+            exit_on_ring != 0,
+            Box::new(move |addr: u64, value: u64| callback(data, addr, value)),
+        );
+
+        return to_cbool(res);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_map_memory(
+    ptr: *mut c_void,
+    address: u64,
+    length: u64,
+    permissions: u8,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let res = emulator.map_memory(address, length, permissions);
+        return to_cbool(res);
+    }
+}
+
+// Returns a MemoryStatus instead of a boolean.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_map_memory_ex(
+    ptr: *mut c_void,
+    address: u64,
+    length: u64,
+    permissions: u8,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return match emulator.map_memory_checked(address, length, permissions) {
+            Ok(()) => MemoryStatus::Success as i32,
+            Err(status) => status as i32,
+        };
+    }
+}
+
+// Passing an empty name removes the name registered at `address`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_name_region(
+    ptr: *mut c_void,
+    address: u64,
+    length: u64,
+    name: *const c_void,
+    name_len: usize,
+) -> i32 {
+    if name.is_null() && name_len != 0 {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+
+        let name_bytes = match name_len {
+            0 => &[],
+            _ => std::slice::from_raw_parts(name as *const u8, name_len),
+        };
+
+        let name_str = match std::str::from_utf8(name_bytes) {
+            Ok(name_str) => name_str,
+            Err(_) => return 0,
+        };
+
+        emulator.name_region(address, length, name_str);
+        return 1;
+    }
+}
+
+// `max_entries` bounds the number of recorded instructions, 0 means no limit.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_trace_start(ptr: *mut c_void, max_entries: usize) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.start_trace(max_entries);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_trace_stop(ptr: *mut c_void) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.stop_trace();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_trace_length(ptr: *mut c_void) -> usize {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.get_trace_length();
+    }
+}
+
+// `mode` is 0 to disable, 1 for block and 2 for edge coverage as described in coverage.rs.
+// Executed blocks are counted directly in `map`, e.g. AFL's shared memory bitmap, which is
+// not cleared. `map_size` must be a power of two of at most 16 MiB. `map` has to stay valid
+// until coverage is disabled or the emulator is destroyed. Both are ignored when disabling.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_enable_coverage(
+    ptr: *mut c_void,
+    mode: i32,
+    map: *mut u8,
+    map_size: usize,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return to_cbool(emulator.enable_coverage(mode, map, map_size));
+    }
+}
+
+// Copies at most `len` bytes of the map to `buf` and returns the map size, 0 if disabled.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_coverage(
+    ptr: *mut c_void,
+    buf: *mut c_void,
+    len: usize,
+) -> usize {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+
+        if buf.is_null() {
+            return emulator.get_coverage(&mut []);
+        }
+
+        let buffer = std::slice::from_raw_parts_mut(buf as *mut u8, len);
+        return emulator.get_coverage(buffer);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_reset_coverage(ptr: *mut c_void) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.reset_coverage();
+    }
+}
+
+// `format` is 0 for the text format and 1 for the binary format described in trace.rs.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_export_trace(
+    ptr: *mut c_void,
+    path: *const c_void,
+    path_len: usize,
+    format: i32,
+) -> i32 {
+    if path.is_null() || path_len == 0 {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let path_bytes = std::slice::from_raw_parts(path as *const u8, path_len);
+
+        let path_str = match std::str::from_utf8(path_bytes) {
+            Ok(path_str) => path_str,
+            Err(_) => return 0,
+        };
+
+        return to_cbool(emulator.export_trace(path_str, format));
+    }
+}
+
+// Returns the full name length, copying at most `size` bytes. Returns 0 if unnamed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_region_name(
+    ptr: *mut c_void,
+    address: u64,
+    data: *mut c_void,
+    size: usize,
+) -> usize {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+
+        let name = match emulator.get_region_name(address) {
+            Some(name) => name,
+            None => return 0,
+        };
+
+        if !data.is_null() {
+            let len = std::cmp::min(name.len(), size);
+            let u8_slice = std::slice::from_raw_parts_mut(data as *mut u8, len);
+            u8_slice.copy_from_slice(&name.as_bytes()[..len]);
+        }
+
+        return name.len();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_unmap_memory(ptr: *mut c_void, address: u64, length: u64) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let res = emulator.unmap_memory(address, length);
+        return to_cbool(res);
+    }
+}
+
+// The *_ex memory functions return a MemoryStatus instead of a boolean.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_unmap_memory_ex(
+    ptr: *mut c_void,
+    address: u64,
+    length: u64,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return match emulator.unmap_memory_checked(address, length) {
+            Ok(()) => MemoryStatus::Success as i32,
+            Err(status) => status as i32,
+        };
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_protect_memory(
+    ptr: *mut c_void,
+    address: u64,
+    length: u64,
+    permissions: u8,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let res = emulator.protect_memory(address, length, permissions);
+        return to_cbool(res);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_protect_memory_ex(
+    ptr: *mut c_void,
+    address: u64,
+    length: u64,
+    permissions: u8,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return match emulator.protect_memory_checked(address, length, permissions) {
+            Ok(()) => MemoryStatus::Success as i32,
+            Err(status) => status as i32,
+        };
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_write_memory(
+    ptr: *mut c_void,
+    address: u64,
+    data: *const c_void,
+    size: usize,
+) -> i32 {
+    if size == 0 {
+        return 1;
+    }
+
+    if data.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = std::slice::from_raw_parts(data as *const u8, size);
+        let res = emulator.write_memory(address, u8_slice);
+        return to_cbool(res);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_write_memory_ex(
+    ptr: *mut c_void,
+    address: u64,
+    data: *const c_void,
+    size: usize,
+) -> i32 {
+    if size == 0 {
+        return MemoryStatus::Success as i32;
+    }
+
+    if data.is_null() {
+        return MemoryStatus::InvalidArgument as i32;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = std::slice::from_raw_parts(data as *const u8, size);
+        return match emulator.write_memory_checked(address, u8_slice) {
+            Ok(()) => MemoryStatus::Success as i32,
+            Err(status) => status as i32,
+        };
+    }
+}
+
+// Like icicle_write_memory_ex, but a non-zero `check_perm` makes writes to pages without
+// write permission fail with a WriteViolation status.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_write_memory_perm(
+    ptr: *mut c_void,
+    address: u64,
+    data: *const c_void,
+    size: usize,
+    check_perm: i32,
+) -> i32 {
+    if size == 0 {
+        return MemoryStatus::Success as i32;
+    }
+
+    if data.is_null() {
+        return MemoryStatus::InvalidArgument as i32;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = std::slice::from_raw_parts(data as *const u8, size);
+        return match emulator.write_memory_perm(address, u8_slice, check_perm != 0) {
+            Ok(()) => MemoryStatus::Success as i32,
+            Err(status) => status as i32,
+        };
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_save_registers(
+    ptr: *mut c_void,
+    accessor: DataFunction,
+    accessor_data: *mut c_void,
+) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let registers = emulator.save_registers();
+        accessor(
+            accessor_data,
+            registers.as_ptr() as *const c_void,
+            registers.len(),
+        );
+    }
+}
+
+// Returns 0 without touching the registers if `data` is not a blob of this build's
+// icicle_save_registers, see IcicleEmulator::save_registers for the format.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_restore_registers(
+    ptr: *mut c_void,
+    data: *const c_void,
+    size: usize,
+) -> i32 {
+    if size == 0 || data.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = std::slice::from_raw_parts(data as *const u8, size);
+        return to_cbool(emulator.restore_registers(u8_slice));
+    }
+}
+
+// Stable for identical states within one build of the bridge, intended for quick
+// equality checks rather than as a persistent identifier.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_state_hash(ptr: *mut c_void) -> u64 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.state_hash();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_snapshot_delta(
+    ptr: *mut c_void,
+    base_snapshot: *const c_void,
+    base_len: usize,
+    accessor: DataFunction,
+    accessor_data: *mut c_void,
+) -> i32 {
+    if base_snapshot.is_null() || base_len == 0 {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let base = std::slice::from_raw_parts(base_snapshot as *const u8, base_len);
+
+        let delta = match emulator.create_register_delta(base) {
+            Some(delta) => delta,
+            None => return 0,
+        };
+
+        accessor(accessor_data, delta.as_ptr() as *const c_void, delta.len());
+        return 1;
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_apply_snapshot_delta(
+    ptr: *mut c_void,
+    base_snapshot: *const c_void,
+    base_len: usize,
+    delta: *const c_void,
+    delta_len: usize,
+) -> i32 {
+    if base_snapshot.is_null() || base_len == 0 || delta.is_null() || delta_len == 0 {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let base = std::slice::from_raw_parts(base_snapshot as *const u8, base_len);
+        let delta_slice = std::slice::from_raw_parts(delta as *const u8, delta_len);
+
+        let res = emulator.apply_register_delta(base, delta_slice);
+        return to_cbool(res);
+    }
+}
+
+// The returned handle must be released with icicle_free_register_checkpoint.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_register_checkpoint(ptr: *mut c_void) -> *mut c_void {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let checkpoint = Box::new(emulator.save_registers());
+        return Box::into_raw(checkpoint) as *mut c_void;
+    }
+}
+
+// The callback receives the register id, pointers to the old and new value and their size.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_register_diff(
+    ptr: *mut c_void,
+    checkpoint: *mut c_void,
+    callback: RegisterDiffFunction,
+    data: *mut c_void,
+) -> i32 {
+    if checkpoint.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let base = &*(checkpoint as *const Vec<u8>);
+
+        let res = emulator.diff_registers(base, &mut |reg: X86Register, old: &[u8], new: &[u8]| {
+            callback(
+                data,
+                reg as i32,
+                old.as_ptr() as *const c_void,
+                new.as_ptr() as *const c_void,
+                old.len(),
+            );
+        });
+
+        return to_cbool(res);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_free_register_checkpoint(checkpoint: *mut c_void) {
+    if checkpoint.is_null() {
+        return;
+    }
+
+    unsafe {
+        let _ = Box::from_raw(checkpoint as *mut Vec<u8>);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_create_snapshot(ptr: *mut c_void) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.create_snapshot();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_restore_snapshot(ptr: *mut c_void, id: u32) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return to_cbool(emulator.restore_snapshot(id));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_delete_snapshot(ptr: *mut c_void, id: u32) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return to_cbool(emulator.delete_snapshot(id));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_read_memory(
+    ptr: *mut c_void,
+    address: u64,
+    data: *mut c_void,
+    size: usize,
+) -> i32 {
+    if size == 0 {
+        return 1;
+    }
+
+    if data.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = std::slice::from_raw_parts_mut(data as *mut u8, size);
+        let res = emulator.read_memory(address, u8_slice);
+        return to_cbool(res);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_read_memory_ex(
+    ptr: *mut c_void,
+    address: u64,
+    data: *mut c_void,
+    size: usize,
+) -> i32 {
+    if size == 0 {
+        return MemoryStatus::Success as i32;
+    }
+
+    if data.is_null() {
+        return MemoryStatus::InvalidArgument as i32;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = std::slice::from_raw_parts_mut(data as *mut u8, size);
+        return match emulator.read_memory_checked(address, u8_slice) {
+            Ok(()) => MemoryStatus::Success as i32,
+            Err(status) => status as i32,
+        };
+    }
+}
+
+// Stores the addresses in [start, end) where the `pattern_len` bytes of `pattern` occur in
+// `results`, at most `max_results` of them, and returns their number. `mask` may be null,
+// otherwise it holds `pattern_len` bytes and only the bits set in it are compared, so a 0
+// byte is a wildcard.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_search_memory(
+    ptr: *mut c_void,
+    start: u64,
+    end: u64,
+    pattern: *const c_void,
+    pattern_len: usize,
+    mask: *const c_void,
+    results: *mut c_void,
+    max_results: usize,
+) -> usize {
+    if pattern.is_null() || results.is_null() || pattern_len == 0 {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let pattern = std::slice::from_raw_parts(pattern as *const u8, pattern_len);
+        let mask = if mask.is_null() {
+            None
+        } else {
+            Some(std::slice::from_raw_parts(mask as *const u8, pattern_len))
+        };
+
+        let found = emulator.search_memory(start, end, pattern, mask, max_results);
+        let results = std::slice::from_raw_parts_mut(results as *mut u64, found.len());
+        results.copy_from_slice(&found);
+        return found.len();
+    }
+}
+
+// Performs `count` reads of `sizes[i]` bytes at `addrs[i]`. The values are stored back to
+// back in `out_buf`, which must hold the sum of `sizes`. `out_lens[i]` receives the bytes
+// read, 0 for failed reads, and may be null. Returns the number of successful reads.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_read_memory_multi(
+    ptr: *mut c_void,
+    addrs: *const u64,
+    sizes: *const usize,
+    count: usize,
+    out_buf: *mut c_void,
+    out_lens: *mut usize,
+) -> usize {
+    if count == 0 || addrs.is_null() || sizes.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let addresses = std::slice::from_raw_parts(addrs, count);
+        let sizes = std::slice::from_raw_parts(sizes, count);
+
+        let total = match sizes
+            .iter()
+            .try_fold(0usize, |sum, size| sum.checked_add(*size))
+        {
+            Some(total) => total,
+            None => return 0,
+        };
+
+        if total != 0 && out_buf.is_null() {
+            return 0;
+        }
+
+        let buffer: &mut [u8] = if total == 0 {
+            &mut []
+        } else {
+            std::slice::from_raw_parts_mut(out_buf as *mut u8, total)
+        };
+
+        let mut local_lengths = Vec::new();
+        let lengths = if out_lens.is_null() {
+            local_lengths.resize(count, 0);
+            &mut local_lengths[..]
+        } else {
+            std::slice::from_raw_parts_mut(out_lens, count)
+        };
+
+        return emulator.read_memory_batch(addresses, sizes, buffer, lengths);
+    }
+}
+
+// Counterpart of icicle_read_memory_multi, `data` holds the values back to back.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_write_memory_multi(
+    ptr: *mut c_void,
+    addrs: *const u64,
+    sizes: *const usize,
+    count: usize,
+    data: *const c_void,
+    out_lens: *mut usize,
+) -> usize {
+    if count == 0 || addrs.is_null() || sizes.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let addresses = std::slice::from_raw_parts(addrs, count);
+        let sizes = std::slice::from_raw_parts(sizes, count);
+
+        let total = match sizes
+            .iter()
+            .try_fold(0usize, |sum, size| sum.checked_add(*size))
+        {
+            Some(total) => total,
+            None => return 0,
+        };
+
+        if total != 0 && data.is_null() {
+            return 0;
+        }
+
+        let values: &[u8] = if total == 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(data as *const u8, total)
+        };
+
+        let mut local_lengths = Vec::new();
+        let lengths = if out_lens.is_null() {
+            local_lengths.resize(count, 0);
+            &mut local_lengths[..]
+        } else {
+            std::slice::from_raw_parts_mut(out_lens, count)
+        };
+
+        return emulator.write_memory_batch(addresses, sizes, values, lengths);
+    }
+}
+
+// Accesses `segment:offset`, returns 0 if `segment` is not a segment register.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_read_memory_seg(
+    ptr: *mut c_void,
+    segment: X86Register,
+    offset: u64,
+    data: *mut c_void,
+    size: usize,
+) -> i32 {
+    if data.is_null() && size != 0 {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = match size {
+            0 => &mut [],
+            _ => std::slice::from_raw_parts_mut(data as *mut u8, size),
+        };
+
+        let res = emulator.read_memory_segment(segment, offset, u8_slice);
+        return to_cbool(res);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_write_memory_seg(
+    ptr: *mut c_void,
+    segment: X86Register,
+    offset: u64,
+    data: *const c_void,
+    size: usize,
+) -> i32 {
+    if data.is_null() && size != 0 {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = match size {
+            0 => &[],
+            _ => std::slice::from_raw_parts(data as *const u8, size),
+        };
+
+        let res = emulator.write_memory_segment(segment, offset, u8_slice);
+        return to_cbool(res);
+    }
+}
+
+// On success `*buffer` receives a newly allocated buffer of `*size` bytes owned by the caller.
+// It must be released with icicle_free_buffer(buffer, size) and not with the C allocator.
+// On failure nothing is allocated and both outputs are set to null/0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_read_memory_alloc(
+    ptr: *mut c_void,
+    address: u64,
+    length: usize,
+    buffer: *mut *mut u8,
+    size: *mut usize,
+) -> i32 {
+    if buffer.is_null() || size.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        *buffer = std::ptr::null_mut();
+        *size = 0;
+
+        let mut data = vec![0u8; length].into_boxed_slice();
+        if !emulator.read_memory(address, &mut data) {
+            return 0;
+        }
+
+        *size = data.len();
+        *buffer = Box::into_raw(data) as *mut u8;
+        return 1;
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_free_buffer(buffer: *mut u8, size: usize) {
+    if buffer.is_null() {
+        return;
+    }
+
+    unsafe {
+        let slice = std::ptr::slice_from_raw_parts_mut(buffer, size);
+        let _ = Box::from_raw(slice);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_read_array(
+    ptr: *mut c_void,
+    address: u64,
+    elem_size: usize,
+    count: usize,
+    stride: u64,
+    out: *mut c_void,
+) -> usize {
+    if elem_size == 0 || count == 0 || out.is_null() {
+        return 0;
+    }
+
+    let size = match elem_size.checked_mul(count) {
+        Some(size) => size,
+        None => return 0,
+    };
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = std::slice::from_raw_parts_mut(out as *mut u8, size);
+        return emulator.read_array(address, stride, elem_size, u8_slice);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_interrupt_hook(
+    ptr: *mut c_void,
+    callback: InterruptFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_interrupt_hook(Box::new(move |code: i32| callback(data, code)));
+    }
+}
+
+// Requires an emulator created with track_uninitialized, see icicle_create_emulator_with_config.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_uninit_read_hook(
+    ptr: *mut c_void,
+    callback: PtrFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator
+            .add_uninit_read_hook(Box::new(move |address: u64| callback(data, address)));
+    }
+}
+
+// The callback receives the ExitException code and its value, e.g. the faulting address.
+// Returning non-zero resumes at the faulting instruction, 0 ends the run.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_exception_hook(
+    ptr: *mut c_void,
+    callback: ExceptionFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_exception_hook(Box::new(
+            move |exception: ExitException, value: u64| {
+                return callback(data, exception as i32, value) != 0;
+            },
+        ));
+    }
+}
+
+// The callback receives the faulting address, the attempted FOREIGN_* access and whether the
+// address is unmapped. It may map or reprotect memory through this emulator, e.g. to page in
+// memory on demand. Returning non-zero from every violation hook retries the instruction.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_violation_hook(
+    ptr: *mut c_void,
+    callback: ViolationFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_violation_hook(Box::new(
+            move |address: u64, permission: u8, unmapped: bool| {
+                let result = callback(data, address, permission, to_cbool(unmapped));
+                if result == 0 {
+                    return false;
+                }
+
+                return true;
+            },
+        ));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_wx_violation_hook(
+    ptr: *mut c_void,
+    callback: WxViolationFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_wx_violation_hook(Box::new(
+            move |address: u64, length: u64, permissions: u8| {
+                callback(data, address, length, permissions)
+            },
+        ));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_set_wx_policy(ptr: *mut c_void, policy: i32) -> i32 {
+    let wx_policy = match WxPolicy::from_i32(policy) {
+        Some(wx_policy) => wx_policy,
+        None => return 0,
+    };
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.set_wx_policy(wx_policy);
+        return 1;
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_read_hook(
+    ptr: *mut c_void,
+    start: u64,
+    end: u64,
+    callback: MemoryAccessFunction,
+    user: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_read_hook(
+            start,
+            end,
+            Box::new(move |pc: u64, address: u64, data: &[u8]| {
+                callback(
+                    user,
+                    pc,
+                    address,
+                    data.as_ptr() as *const c_void,
+                    data.len(),
+                );
+            }),
+        );
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_write_hook(
+    ptr: *mut c_void,
+    start: u64,
+    end: u64,
+    callback: MemoryAccessFunction,
+    user: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_write_hook(
+            start,
+            end,
+            Box::new(move |pc: u64, address: u64, data: &[u8]| {
+                callback(
+                    user,
+                    pc,
+                    address,
+                    data.as_ptr() as *const c_void,
+                    data.len(),
+                );
+            }),
+        );
+    }
+}
+
+// `condition` is 0 for equal, 1 for changed and 2 for unsigned greater than `value`.
+// The callback receives the watched address and the value before and after the write.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_watchpoint(
+    ptr: *mut c_void,
+    address: u64,
+    size: usize,
+    condition: i32,
+    value: u64,
+    callback: WatchpointFunction,
+    data: *mut c_void,
+) -> u32 {
+    let watch_condition = match watchpoint::WatchCondition::from_i32(condition) {
+        Some(watch_condition) => watch_condition,
+        None => return 0,
+    };
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_watchpoint(
+            address,
+            size,
+            watch_condition,
+            value,
+            Box::new(move |address: u64, old: u64, new: u64| callback(data, address, old, new)),
+        );
+    }
+}
+
+// The callback returns a SyscallAction, unknown values are treated as Continue.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_syscall_hook(
+    ptr: *mut c_void,
+    callback: SyscallFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_syscall_hook(Box::new(move |_: &mut IcicleEmulator| {
+            SyscallAction::from_i32(callback(data)).unwrap_or(SyscallAction::Continue)
+        }));
+    }
+}
+
+// Like icicle_add_syscall_hook, but the callback receives an emulator handle before `data`.
+// Syscall hooks run between two instructions, outside of icicle's execution loop, and the
+// handle is derived from the emulator's own borrow for the duration of the call. The
+// callback may pass it to any icicle_* function, including ones adding or removing hooks,
+// but must not use a copy of `ptr` saved elsewhere while it runs.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_syscall_hook_ex(
+    ptr: *mut c_void,
+    callback: SyscallContextFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_syscall_hook(Box::new(move |emulator: &mut IcicleEmulator| {
+            let handle = emulator as *mut IcicleEmulator as *mut c_void;
+            SyscallAction::from_i32(callback(handle, data)).unwrap_or(SyscallAction::Continue)
+        }));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_block_hook(
+    ptr: *mut c_void,
+    callback: BlockFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_block_hook(Box::new(move |address: u64, instructions: u64| {
+            callback(data, address, instructions)
+        }));
+    }
+}
+
+// Calls `callback` with the address of every instruction before it executes. The hook
+// point is part of every translated block, JIT compiled or not, so installing the hook
+// does not change how code runs. Its cost is the callback itself, once per instruction,
+// and goes away when the hook is removed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_generic_execution_hook(
+    ptr: *mut c_void,
+    callback: PtrFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_generic_execution_hook(Box::new(move |ptr: u64| callback(data, ptr)));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_execution_hook(
+    ptr: *mut c_void,
+    address: u64,
+    callback: PtrFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_execution_hook(address, Box::new(move |ptr: u64| callback(data, ptr)));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_tracepoint(
+    ptr: *mut c_void,
+    address: u64,
+    callback: TracepointFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_tracepoint(
+            address,
+            Box::new(move |address: u64, icount: u64| callback(data, address, icount)),
+        );
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_watch_stack_uninit(
+    ptr: *mut c_void,
+    base: u64,
+    size: u64,
+    callback: StackUninitFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.watch_stack_uninit(
+            base,
+            size,
+            Box::new(move |address: u64, length: usize, offset: i64| {
+                callback(data, address, length, offset)
+            }),
+        );
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_return_check_hook(
+    ptr: *mut c_void,
+    callback: ReturnCheckFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_return_check_hook(Box::new(
+            move |address: u64, expected: u64, actual: u64| {
+                callback(data, address, expected, actual)
+            },
+        ));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_cpl(ptr: *mut c_void) -> u8 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.get_cpl();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_mode_transition_hook(
+    ptr: *mut c_void,
+    callback: ModeTransitionFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.add_mode_transition_hook(Box::new(
+            move |old_cpl: u8, new_cpl: u8, address: u64| callback(data, old_cpl, new_cpl, address),
+        ));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_add_register_read_hook(
+    ptr: *mut c_void,
+    reg: X86Register,
+    callback: PtrFunction,
+    data: *mut c_void,
+) -> u32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator
+            .add_register_read_hook(reg, Box::new(move |address: u64| callback(data, address)));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_remove_hook(ptr: *mut c_void, id: u32) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.remove_hook(id);
+    }
+}
+
+// Attaches a host defined value to a hook id returned by any icicle_add_* function. The
+// tag is stored with the hook and removed with it, unknown ids return 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_set_hook_tag(ptr: *mut c_void, id: u32, tag: u64) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return to_cbool(emulator.set_hook_tag(id, tag));
+    }
+}
+
+// Returns 0 for hooks without a tag.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_hook_tag(ptr: *mut c_void, id: u32) -> u64 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.get_hook_tag(id);
+    }
+}
+
+// Size in bytes of the register, 0 if the specification does not define it.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_register_size(ptr: *mut c_void, reg: X86Register) -> usize {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.register_size(reg);
+    }
+}
+
+// Returns the register size, 0 for registers the specification does not define.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_read_register(
+    ptr: *mut c_void,
+    reg: X86Register,
+    data: *mut c_void,
+    size: usize,
+) -> usize {
+    if size == 0 {
+        return 1;
+    }
+
+    if data.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = std::slice::from_raw_parts_mut(data as *mut u8, size);
+        return emulator.read_register(reg, u8_slice);
+    }
+}
+
+// For registers of at most 8 bytes. Returns 0 for wider or unavailable registers.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_read_register_u64(ptr: *mut c_void, reg: X86Register) -> u64 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.read_register_u64(reg).unwrap_or(0);
+    }
+}
+
+// Returns 0 for registers wider than 8 bytes and values that do not fit the register.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_write_register_u64(
+    ptr: *mut c_void,
+    reg: X86Register,
+    value: u64,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return to_cbool(emulator.write_register_u64(reg, value));
+    }
+}
+
+// Returns the number of input bytes consumed, input past the register size is ignored.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_write_register(
+    ptr: *mut c_void,
+    reg: X86Register,
+    data: *const c_void,
+    size: usize,
+) -> usize {
+    if size == 0 {
+        return 1;
+    }
+
+    if data.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = std::slice::from_raw_parts(data as *const u8, size);
+        return emulator.write_register(reg, u8_slice);
+    }
+}
+
+// Reads a register by its sleigh name, e.g. "RAX", "XMM0_Qa" or "FPUControlWord", falling
+// back to a case-insensitive match. Returns the register size, 0 if the name is unknown.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_read_register_by_name(
+    ptr: *mut c_void,
+    name: *const c_void,
+    name_len: usize,
+    data: *mut c_void,
+    size: usize,
+) -> usize {
+    if name.is_null() || (data.is_null() && size != 0) {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let name_bytes = std::slice::from_raw_parts(name as *const u8, name_len);
+
+        let name_str = match std::str::from_utf8(name_bytes) {
+            Ok(name_str) => name_str,
+            Err(_) => return 0,
+        };
+
+        let u8_slice = match size {
+            0 => &mut [],
+            _ => std::slice::from_raw_parts_mut(data as *mut u8, size),
+        };
+
+        return emulator.read_register_by_name(name_str, u8_slice);
+    }
+}
+
+// Returns the number of input bytes consumed, 0 if the name is unknown.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_write_register_by_name(
+    ptr: *mut c_void,
+    name: *const c_void,
+    name_len: usize,
+    data: *const c_void,
+    size: usize,
+) -> usize {
+    if name.is_null() || data.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let name_bytes = std::slice::from_raw_parts(name as *const u8, name_len);
+
+        let name_str = match std::str::from_utf8(name_bytes) {
+            Ok(name_str) => name_str,
+            Err(_) => return 0,
+        };
+
+        let u8_slice = std::slice::from_raw_parts(data as *const u8, size);
+        return emulator.write_register_by_name(name_str, u8_slice);
+    }
+}
+
+// Like icicle_read_register_by_name with a NUL-terminated name.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_read_register_by_cname(
+    ptr: *mut c_void,
+    name: *const c_char,
+    data: *mut c_void,
+    size: usize,
+) -> usize {
+    if name.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let name = CStr::from_ptr(name);
+        return icicle_read_register_by_name(
+            ptr,
+            name.as_ptr() as *const c_void,
+            name.count_bytes(),
+            data,
+            size,
+        );
+    }
+}
+
+// Like icicle_write_register_by_name with a NUL-terminated name.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_write_register_by_cname(
+    ptr: *mut c_void,
+    name: *const c_char,
+    data: *const c_void,
+    size: usize,
+) -> usize {
+    if name.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let name = CStr::from_ptr(name);
+        return icicle_write_register_by_name(
+            ptr,
+            name.as_ptr() as *const c_void,
+            name.count_bytes(),
+            data,
+            size,
+        );
+    }
+}
+
+// Like icicle_write_register, but writes nothing and returns 0 if `size` exceeds the register size.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_write_register_checked(
+    ptr: *mut c_void,
+    reg: X86Register,
+    data: *const c_void,
+    size: usize,
+) -> usize {
+    if size == 0 || data.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let u8_slice = std::slice::from_raw_parts(data as *const u8, size);
+        return emulator.write_register_checked(reg, u8_slice).unwrap_or(0);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_setup_segments(
+    ptr: *mut c_void,
+    cs: u16,
+    ds: u16,
+    es: u16,
+    fs: u16,
+    gs: u16,
+    ss: u16,
+    fs_base: u64,
+    gs_base: u64,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let res = emulator.setup_segments(&[cs, ds, es, fs, gs, ss], fs_base, gs_base);
+        return to_cbool(res);
+    }
+}
+
+// See IcicleEmulator::init_flat_long_mode for the resulting state. `stack_base` receives
+// the lowest address of the mapped stack.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_init_flat_long_mode(
+    ptr: *mut c_void,
+    stack_size: u64,
+    stack_base: *mut u64,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+
+        let base = match emulator.init_flat_long_mode(stack_size) {
+            Some(base) => base,
+            None => return 0,
+        };
+
+        if !stack_base.is_null() {
+            *stack_base = base;
+        }
+
+        return 1;
+    }
+}
+
+// Fills `regs` with RAX to R15, RIP and RFLAGS in the layout of icicle::GpRegs.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_dump_gpregs(ptr: *mut c_void, regs: *mut GpRegs) -> i32 {
+    if regs.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        *regs = emulator.get_gpregs();
+        return 1;
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_load_gpregs(ptr: *mut c_void, regs: *const GpRegs) -> i32 {
+    if regs.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let value = &*regs;
+        emulator.set_gpregs(value);
+        return 1;
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_fpu_state(ptr: *mut c_void, state: *mut FpuState) -> i32 {
+    if state.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let out = &mut *state;
+        *out = emulator.get_fpu_state();
+        return 1;
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_set_fpu_state(ptr: *mut c_void, state: *const FpuState) -> i32 {
+    if state.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let value = &*state;
+        emulator.set_fpu_state(value);
+        return 1;
+    }
+}
+
+// The callback receives the virtual address, physical address, size and PAGE_* permissions
+// of each mapping. Returns 0 if paging is disabled or the paging mode is unsupported.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_walk_page_tables(
+    ptr: *mut c_void,
+    callback: PageMappingFunction,
+    data: *mut c_void,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let res = emulator.walk_page_tables(&mut |mapping: &paging::Mapping| {
+            callback(
+                data,
+                mapping.virtual_address,
+                mapping.physical_address,
+                mapping.size,
+                mapping.permissions,
+            );
+        });
+
+        return to_cbool(res);
+    }
+}
+
+// Stores the physical address `vaddr` maps to in `paddr` and returns 1, or returns 0 if it
+// is not mapped by the guest page tables or the paging mode is unsupported. Addresses map
+// to themselves while paging is disabled.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_translate(ptr: *mut c_void, vaddr: u64, paddr: *mut u64) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+
+        let address = match emulator.translate_address(vaddr) {
+            Some(address) => address,
+            None => return 0,
+        };
+
+        if !paddr.is_null() {
+            *paddr = address;
+        }
+
+        return 1;
+    }
+}
+
+// Returns the mode icicle_translate uses: 0 if addresses map to themselves, 1 for 4-level
+// paging, 2 for PAE paging and 3 for paging modes the page table functions do not support
+// (32-bit non-PAE and 5-level paging). The emulator itself always accesses memory flat, so
+// paging enabled with no present entry in the table at CR3 also returns 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_addressing_mode(ptr: *mut c_void) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.get_addressing_mode() as i32;
+    }
+}
+
+// Same values as icicle_get_addressing_mode, but only derived from CR0, CR4 and EFER.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_guest_paging_mode(ptr: *mut c_void) -> i32 {
+    unsafe {
+        let emulator = &*(ptr as *mut IcicleEmulator);
+        return emulator.get_guest_paging_mode() as i32;
+    }
+}
+
+// The FXSAVE/XSAVE functions use the architectural 64-bit layouts. They return 0 if the
+// area is misaligned or unmapped, the XSAVE variants also for compacted areas on restore.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_fxsave(ptr: *mut c_void, address: u64) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return to_cbool(emulator.fxsave(address));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_fxrstor(ptr: *mut c_void, address: u64) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return to_cbool(emulator.fxrstor(address));
+    }
+}
+
+// Only the x87 (bit 0), SSE (bit 1) and AVX (bit 2) components of `mask` are handled.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_xsave(ptr: *mut c_void, address: u64, mask: u64) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return to_cbool(emulator.xsave(address, mask));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_xrstor(ptr: *mut c_void, address: u64, mask: u64) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return to_cbool(emulator.xrstor(address, mask));
+    }
+}
+
+// Resets the registers to their power-on values, memory and mappings are kept.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_cpu_reset(ptr: *mut c_void) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.cpu_reset();
+    }
+}
+
+// Unmaps all memory, resets the registers and the instruction count and, if `remove_hooks`
+// is non-zero, removes all hooks and breakpoints. Cheaper than creating a new emulator.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_reset(ptr: *mut c_void, remove_hooks: i32) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.reset(remove_hooks != 0);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_fpu_top(ptr: *mut c_void) -> u8 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.get_fpu_top();
+    }
+}
+
+// Returns 0 if `top` is not in 0..=7.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_set_fpu_top(ptr: *mut c_void, top: u8) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return to_cbool(emulator.set_fpu_top(top));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_deliver_signal(
+    ptr: *mut c_void,
+    signo: i32,
+    handler: u64,
+    restorer: u64,
+    sigmask: u64,
+) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let res = emulator.deliver_signal(signo, handler, restorer, sigmask);
+        return to_cbool(res);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_sigreturn(ptr: *mut c_void, sigmask: *mut u64) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+
+        let mut mask: u64 = 0;
+        let res = emulator.sigreturn(&mut mask);
+
+        if res && !sigmask.is_null() {
+            *sigmask = mask;
+        }
+
+        return to_cbool(res);
+    }
+}
+
+// Copies up to `max` return addresses, most recent first, and returns the number copied.
+// With a null `out` the total number of entries is returned instead. Always 0 unless the
+// emulator was created with CREATE_SHADOW_STACK.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_shadow_stack(
+    ptr: *mut c_void,
+    out: *mut u64,
+    max: usize,
+) -> usize {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let entries = emulator.get_shadow_stack();
+
+        if out.is_null() {
+            return entries.len();
+        }
+
+        let count = std::cmp::min(entries.len(), max);
+        let out_slice = std::slice::from_raw_parts_mut(out, count);
+        out_slice.copy_from_slice(&entries[..count]);
+
+        return count;
+    }
+}
+
+// `max_ops` is a budget of p-code ops in translated blocks, as counted by
+// icicle_get_code_cache_size, not a size in bytes of the code cache or JIT memory. Going
+// over it flushes the whole cache at the next block boundary, single blocks are never
+// evicted. A limit of 0 removes the limit.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_set_code_cache_limit(ptr: *mut c_void, max_ops: u64) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let limit = match max_ops {
+            0 => None,
+            _ => Some(max_ops),
+        };
+
+        emulator.set_code_cache_limit(limit);
+    }
+}
+
+// Drops all translated code. Execution ignores guest paging, CR3 and INVLPG, so hosts that
+// switch address spaces by remapping memory call this before running the new mappings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_flush_tlb(ptr: *mut c_void) {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        emulator.flush_tlb();
+    }
+}
+
+// Returns the number of p-code ops translated since the cache was last flushed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_code_cache_size(ptr: *mut c_void) -> u64 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.get_code_cache_size();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_is_jitted(ptr: *mut c_void, address: u64) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        return emulator.get_translation_state(address) as i32;
+    }
+}
+
+// Returns a DecodeStatus, `insn` is only written on success.
+// `flags` is a combination of the FLAG_* constants in decode.rs.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_decode(
+    ptr: *mut c_void,
+    address: u64,
+    insn: *mut DecodedInsn,
+) -> i32 {
+    if insn.is_null() {
+        return decode::DecodeStatus::Invalid as i32;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        match emulator.decode_instruction(address) {
+            Ok(instruction) => {
+                *insn = instruction.to_decoded();
+                return decode::DecodeStatus::Success as i32;
+            }
+            Err(status) => return status as i32,
+        }
+    }
+}
+
+// Returns the FLAG_* bits of the instruction at `address`, or the negated DecodeStatus
+// if it cannot be decoded.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_classify_instruction(ptr: *mut c_void, address: u64) -> i32 {
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        match emulator.decode_instruction(address) {
+            Ok(instruction) => return instruction.flags as i32,
+            Err(status) => return -(status as i32),
+        }
+    }
+}
+
+// Returns 0 if the last run did not stop on an exception or `out_len` does not match
+// the size of FaultInsn.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_fault_instruction(
+    ptr: *mut c_void,
+    out: *mut FaultInsn,
+    out_len: usize,
+) -> i32 {
+    if out.is_null() || out_len != std::mem::size_of::<FaultInsn>() {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        match emulator.get_fault_instruction() {
+            Some(fault) => {
+                *out = *fault;
+                return 1;
+            }
+            None => return 0,
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_get_vector_state(
+    ptr: *mut c_void,
+    data: *mut c_void,
+    size: usize,
+) -> usize {
+    if data.is_null() || size < vector::VECTOR_STATE_SIZE {
+        return vector::VECTOR_STATE_SIZE;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let buffer = &mut *(data as *mut [u8; vector::VECTOR_STATE_SIZE]);
+        emulator.get_vector_state(buffer);
+        return vector::VECTOR_STATE_SIZE;
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn icicle_set_vector_state(
+    ptr: *mut c_void,
+    data: *const c_void,
+    size: usize,
+) -> i32 {
+    if data.is_null() || size < vector::VECTOR_STATE_SIZE {
+        return 0;
+    }
+
+    unsafe {
+        let emulator = &mut *(ptr as *mut IcicleEmulator);
+        let buffer = &*(data as *const [u8; vector::VECTOR_STATE_SIZE]);
+        emulator.set_vector_state(buffer);
+        return 1;
     }
 }
 
 #[unsafe(no_mangle)]
-pub fn icicle_destroy_emulator(ptr: *mut c_void) {
+pub unsafe extern "C" fn icicle_destroy_emulator(ptr: *mut c_void) {
     if ptr.is_null() {
         return;
     }
@@ -0,0 +1,200 @@
+// Recording of MMIO accesses for deterministic replay.
+//
+// Format (all integers little-endian):
+//
+//   magic      4 bytes  "ICMM"
+//   version    u32      LOG_VERSION
+//   events     repeated until the end of the buffer:
+//                kind     u8   EVENT_READ or EVENT_WRITE
+//                address  u64
+//                length   u32
+//                bytes    `length` bytes, the value read or written
+//
+// During replay reads return the recorded value and writes are checked against the
+// recording, the devices are not called. The first access that does not match the
+// next recorded event marks the replay as diverged, after which accesses go to the
+// live devices again.
+
+const LOG_MAGIC: &[u8; 4] = b"ICMM";
+const LOG_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 8;
+const EVENT_HEADER_SIZE: usize = 13;
+
+const EVENT_READ: u8 = 0;
+const EVENT_WRITE: u8 = 1;
+
+struct MmioEvent {
+    kind: u8,
+    address: u64,
+    data: Vec<u8>,
+}
+
+#[derive(PartialEq)]
+enum Mode {
+    Off,
+    Record,
+    Replay,
+}
+
+pub struct MmioLog {
+    mode: Mode,
+    events: Vec<MmioEvent>,
+    position: usize,
+    divergence: Option<usize>,
+}
+
+impl MmioLog {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Off,
+            events: Vec::new(),
+            position: 0,
+            divergence: None,
+        }
+    }
+
+    pub fn start_recording(&mut self) {
+        self.mode = Mode::Record;
+        self.events.clear();
+        self.position = 0;
+        self.divergence = None;
+    }
+
+    // Stops recording and returns the encoded log.
+    pub fn stop_recording(&mut self) -> Vec<u8> {
+        let mut log = Vec::new();
+        log.extend_from_slice(LOG_MAGIC);
+        log.extend_from_slice(&LOG_VERSION.to_le_bytes());
+
+        for event in self.events.iter() {
+            log.push(event.kind);
+            log.extend_from_slice(&event.address.to_le_bytes());
+            log.extend_from_slice(&(event.data.len() as u32).to_le_bytes());
+            log.extend_from_slice(&event.data);
+        }
+
+        self.mode = Mode::Off;
+        self.events.clear();
+        return log;
+    }
+
+    pub fn start_replay(&mut self, log: &[u8]) -> bool {
+        if log.len() < HEADER_SIZE || &log[..4] != LOG_MAGIC {
+            return false;
+        }
+
+        if u32::from_le_bytes(log[4..8].try_into().unwrap()) != LOG_VERSION {
+            return false;
+        }
+
+        let mut events = Vec::new();
+        let mut position = HEADER_SIZE;
+
+        while position < log.len() {
+            if log.len() - position < EVENT_HEADER_SIZE {
+                return false;
+            }
+
+            let kind = log[position];
+            let address = u64::from_le_bytes(log[position + 1..position + 9].try_into().unwrap());
+            let length =
+                u32::from_le_bytes(log[position + 9..position + 13].try_into().unwrap()) as usize;
+            position += EVENT_HEADER_SIZE;
+
+            if (kind != EVENT_READ && kind != EVENT_WRITE) || log.len() - position < length {
+                return false;
+            }
+
+            events.push(MmioEvent {
+                kind,
+                address,
+                data: log[position..position + length].to_vec(),
+            });
+            position += length;
+        }
+
+        self.mode = Mode::Replay;
+        self.events = events;
+        self.position = 0;
+        self.divergence = None;
+        return true;
+    }
+
+    pub fn stop_replay(&mut self) {
+        self.mode = Mode::Off;
+        self.events.clear();
+    }
+
+    // Index of the first event that did not match, if the replay diverged.
+    pub fn divergence(&self) -> Option<usize> {
+        return self.divergence;
+    }
+
+    fn is_replaying(&self) -> bool {
+        return self.mode == Mode::Replay && self.divergence.is_none();
+    }
+
+    // Returns the next recorded event if it matches the access, marking the replay as
+    // diverged otherwise.
+    fn next_event(&mut self, kind: u8, address: u64, length: usize) -> Option<&MmioEvent> {
+        let index = self.position;
+        let matches = match self.events.get(index) {
+            Some(event) => {
+                event.kind == kind && event.address == address && event.data.len() == length
+            }
+            None => false,
+        };
+
+        if !matches {
+            self.divergence = Some(index);
+            return None;
+        }
+
+        self.position += 1;
+        return self.events.get(index);
+    }
+
+    // Fills `data` from the recording, returns false if the live device must be read.
+    pub fn replay_read(&mut self, address: u64, data: &mut [u8]) -> bool {
+        if !self.is_replaying() {
+            return false;
+        }
+
+        match self.next_event(EVENT_READ, address, data.len()) {
+            Some(event) => {
+                data.copy_from_slice(&event.data);
+                return true;
+            }
+            None => return false,
+        }
+    }
+
+    // Returns false if the write must be forwarded to the live device.
+    pub fn replay_write(&mut self, address: u64, data: &[u8]) -> bool {
+        if !self.is_replaying() {
+            return false;
+        }
+
+        let index = self.position;
+        match self.next_event(EVENT_WRITE, address, data.len()) {
+            Some(event) if event.data == data => return true,
+            Some(_) => {
+                self.divergence = Some(index);
+                return false;
+            }
+            None => return false,
+        }
+    }
+
+    pub fn record(&mut self, write: bool, address: u64, data: &[u8]) {
+        if self.mode != Mode::Record {
+            return;
+        }
+
+        self.events.push(MmioEvent {
+            kind: if write { EVENT_WRITE } else { EVENT_READ },
+            address,
+            data: data.to_vec(),
+        });
+    }
+}
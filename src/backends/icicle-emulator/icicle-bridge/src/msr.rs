@@ -0,0 +1,46 @@
+// Model specific registers. The sleigh register file does not contain them, so everything
+// except the FS/GS bases is kept in a side table. Registers that were never written read 0.
+
+pub const IA32_EFER: u32 = 0xC000_0080;
+pub const IA32_STAR: u32 = 0xC000_0081;
+pub const IA32_LSTAR: u32 = 0xC000_0082;
+pub const IA32_FMASK: u32 = 0xC000_0084;
+pub const IA32_FS_BASE: u32 = 0xC000_0100;
+pub const IA32_GS_BASE: u32 = 0xC000_0101;
+
+pub const EFER_SCE: u64 = 1 << 0;
+pub const EFER_LME: u64 = 1 << 8;
+pub const EFER_LMA: u64 = 1 << 10;
+pub const EFER_NXE: u64 = 1 << 11;
+
+// RF is always cleared on SYSCALL, SYSRET restores only these bits from R11.
+pub const RFLAGS_RF: u64 = 1 << 16;
+pub const SYSRET_FLAGS_MASK: u64 = 0x3C7FD7;
+pub const RFLAGS_FIXED: u64 = 1 << 1;
+
+pub fn syscall_selectors(star: u64) -> (u16, u16) {
+    let base = (star >> 32) as u16;
+    return (base & !3, (base & !3).wrapping_add(8));
+}
+
+// A 64-bit SYSRET uses STAR[63:48] + 16 for CS, a 32-bit one STAR[63:48]. SS is always
+// STAR[63:48] + 8.
+pub fn sysret_selectors(star: u64, long_mode: bool) -> (u16, u16) {
+    let base = (star >> 48) as u16;
+    let cs = if long_mode {
+        base.wrapping_add(16)
+    } else {
+        base
+    };
+
+    return (cs | 3, base.wrapping_add(8) | 3);
+}
+
+// Matches SYSRET and returns whether it goes back to 64-bit code, which needs REX.W.
+pub fn parse_sysret(bytes: &[u8]) -> Option<bool> {
+    return match bytes {
+        [0x0F, 0x07, ..] => Some(false),
+        [rex @ 0x40..=0x4F, 0x0F, 0x07, ..] => Some((rex & 0x08) != 0),
+        _ => None,
+    };
+}
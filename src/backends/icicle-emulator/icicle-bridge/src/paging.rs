@@ -0,0 +1,336 @@
+// Walker for the guest page tables rooted at CR3. Guest physical addresses are the
+// addresses mapped in the emulator, page table entries are read from there directly.
+//
+// 4-level paging (EFER.LME) and 32-bit PAE paging are supported, legacy 2-level paging
+// and 5-level paging (CR4.LA57) are not. Every present leaf entry is reported once,
+// large pages with their full size.
+
+pub const PAGE_READ: u8 = 1 << 0;
+pub const PAGE_WRITE: u8 = 1 << 1;
+pub const PAGE_EXEC: u8 = 1 << 2;
+pub const PAGE_USER: u8 = 1 << 3;
+
+const CR0_PG: u64 = 1 << 31;
+const CR4_PAE: u64 = 1 << 5;
+const CR4_LA57: u64 = 1 << 12;
+
+const ENTRY_PRESENT: u64 = 1 << 0;
+const ENTRY_WRITE: u64 = 1 << 1;
+const ENTRY_USER: u64 = 1 << 2;
+const ENTRY_LARGE: u64 = 1 << 7;
+const ENTRY_NX: u64 = 1 << 63;
+
+const ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+const ENTRY_SIZE: u64 = 8;
+const TABLE_ENTRIES: u64 = 512;
+const PAE_PDPT_ENTRIES: u64 = 4;
+
+pub fn is_enabled(cr0: u64) -> bool {
+    return (cr0 & CR0_PG) != 0;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PagingMode {
+    FourLevel,
+    Pae,
+}
+
+impl PagingMode {
+    pub fn detect(cr0: u64, cr4: u64, long_mode: bool) -> Option<Self> {
+        if (cr0 & CR0_PG) == 0 || (cr4 & CR4_PAE) == 0 || (cr4 & CR4_LA57) != 0 {
+            return None;
+        }
+
+        if long_mode {
+            return Some(PagingMode::FourLevel);
+        }
+
+        return Some(PagingMode::Pae);
+    }
+
+    fn shifts(&self) -> &'static [u32] {
+        return match self {
+            PagingMode::FourLevel => &[39, 30, 21, 12],
+            PagingMode::Pae => &[30, 21, 12],
+        };
+    }
+
+    pub fn root(&self, cr3: u64) -> u64 {
+        return match self {
+            PagingMode::FourLevel => cr3 & ADDRESS_MASK,
+            PagingMode::Pae => cr3 & 0xFFFF_FFE0,
+        };
+    }
+
+    fn is_pdpt(&self, level: usize) -> bool {
+        return *self == PagingMode::Pae && level == 0;
+    }
+
+    fn entries(&self, level: usize) -> u64 {
+        if self.is_pdpt(level) {
+            return PAE_PDPT_ENTRIES;
+        }
+
+        return TABLE_ENTRIES;
+    }
+
+    // Interprets an entry of a table at `level`. `permissions` are the ones granted by the
+    // entries above it.
+    fn decode_entry(&self, level: usize, entry: u64, permissions: u8, nx_enabled: bool) -> Entry {
+        if (entry & ENTRY_PRESENT) == 0 {
+            return Entry::NotPresent;
+        }
+
+        let shifts = self.shifts();
+        let is_pdpt = self.is_pdpt(level);
+
+        // PAE PDPTEs carry no access rights.
+        let mut permissions = permissions;
+        if !is_pdpt {
+            if (entry & ENTRY_WRITE) == 0 {
+                permissions &= !PAGE_WRITE;
+            }
+
+            if (entry & ENTRY_USER) == 0 {
+                permissions &= !PAGE_USER;
+            }
+        }
+
+        if nx_enabled && (entry & ENTRY_NX) != 0 {
+            permissions &= !PAGE_EXEC;
+        }
+
+        let is_leaf = level == shifts.len() - 1;
+        let is_large = !is_leaf && !is_pdpt && level > 0 && (entry & ENTRY_LARGE) != 0;
+
+        if is_leaf || is_large {
+            let size = 1u64 << shifts[level];
+            return Entry::Page {
+                address: entry & ADDRESS_MASK & !(size - 1),
+                size,
+                permissions,
+            };
+        }
+
+        return Entry::Table {
+            address: entry & ADDRESS_MASK,
+            permissions,
+        };
+    }
+}
+
+enum Entry {
+    NotPresent,
+    Table {
+        address: u64,
+        permissions: u8,
+    },
+    Page {
+        address: u64,
+        size: u64,
+        permissions: u8,
+    },
+}
+
+pub struct Mapping {
+    pub virtual_address: u64,
+    pub physical_address: u64,
+    pub size: u64,
+    pub permissions: u8,
+}
+
+pub struct Walker<'a> {
+    pub mode: PagingMode,
+    pub nx_enabled: bool,
+    pub read_entry: &'a mut dyn FnMut(u64) -> Option<u64>,
+    pub callback: &'a mut dyn FnMut(&Mapping),
+}
+
+fn sign_extend(address: u64) -> u64 {
+    return ((address as i64) << 16 >> 16) as u64;
+}
+
+impl Walker<'_> {
+    pub fn walk(&mut self, cr3: u64) {
+        let root = self.mode.root(cr3);
+        let permissions = PAGE_READ | PAGE_WRITE | PAGE_EXEC | PAGE_USER;
+        self.walk_table(root, 0, 0, permissions);
+    }
+
+    fn walk_table(&mut self, table: u64, level: usize, base: u64, permissions: u8) {
+        let shift = self.mode.shifts()[level];
+
+        for index in 0..self.mode.entries(level) {
+            let entry = match (self.read_entry)(table + index * ENTRY_SIZE) {
+                Some(entry) => entry,
+                None => return,
+            };
+
+            let virtual_address = base | (index << shift);
+
+            match self
+                .mode
+                .decode_entry(level, entry, permissions, self.nx_enabled)
+            {
+                Entry::NotPresent => {}
+                Entry::Page {
+                    address,
+                    size,
+                    permissions,
+                } => {
+                    let virtual_address = match self.mode {
+                        PagingMode::FourLevel => sign_extend(virtual_address),
+                        PagingMode::Pae => virtual_address,
+                    };
+
+                    (self.callback)(&Mapping {
+                        virtual_address,
+                        physical_address: address,
+                        size,
+                        permissions,
+                    });
+                }
+                Entry::Table {
+                    address,
+                    permissions,
+                } => self.walk_table(address, level + 1, virtual_address, permissions),
+            }
+        }
+    }
+}
+
+// Translates `address` through the tables rooted at CR3 without walking the whole tree.
+// Returns None for non-canonical addresses and addresses without a present mapping.
+pub fn translate(
+    mode: PagingMode,
+    cr3: u64,
+    address: u64,
+    read_entry: &mut dyn FnMut(u64) -> Option<u64>,
+) -> Option<u64> {
+    let valid = match mode {
+        PagingMode::FourLevel => sign_extend(address) == address,
+        PagingMode::Pae => address <= u32::MAX as u64,
+    };
+
+    if !valid {
+        return None;
+    }
+
+    let mut table = mode.root(cr3);
+
+    for (level, shift) in mode.shifts().iter().enumerate() {
+        let index = (address >> shift) & (mode.entries(level) - 1);
+        let entry = read_entry(table + index * ENTRY_SIZE)?;
+
+        match mode.decode_entry(level, entry, 0, false) {
+            Entry::NotPresent => return None,
+            Entry::Page {
+                address: page,
+                size,
+                ..
+            } => return Some(page | (address & (size - 1))),
+            Entry::Table { address, .. } => table = address,
+        }
+    }
+
+    return None;
+}
+
+// True if the top level table at CR3 has at least one present entry.
+pub fn has_present_entry(
+    mode: PagingMode,
+    cr3: u64,
+    read_entry: &mut dyn FnMut(u64) -> Option<u64>,
+) -> bool {
+    let table = mode.root(cr3);
+
+    return (0..mode.entries(0)).any(|index| match read_entry(table + index * ENTRY_SIZE) {
+        Some(entry) => (entry & ENTRY_PRESENT) != 0,
+        None => false,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const PRESENT_WRITE: u64 = ENTRY_PRESENT | ENTRY_WRITE;
+
+    fn translate_with(
+        mode: PagingMode,
+        entries: &HashMap<u64, u64>,
+        cr3: u64,
+        address: u64,
+    ) -> Option<u64> {
+        let mut read_entry = |entry_address: u64| entries.get(&entry_address).copied();
+        return translate(mode, cr3, address, &mut read_entry);
+    }
+
+    #[test]
+    fn translates_four_level_pages() {
+        // PML4 at 0x1000, PDPT at 0x2000, PD at 0x3000 and PT at 0x4000.
+        let entries = HashMap::from([
+            (0x1000, 0x2000 | PRESENT_WRITE),
+            (0x2000, 0x3000 | PRESENT_WRITE),
+            (0x3000, 0x4000 | PRESENT_WRITE),
+            (0x3008, 0x4020_0000 | PRESENT_WRITE | ENTRY_LARGE),
+            (0x4008, 0x9000 | PRESENT_WRITE),
+        ]);
+
+        let mode = PagingMode::FourLevel;
+        assert_eq!(translate_with(mode, &entries, 0x1000, 0x1234), Some(0x9234));
+        assert_eq!(
+            translate_with(mode, &entries, 0x1000, 0x20_1234),
+            Some(0x4020_1234)
+        );
+        assert_eq!(translate_with(mode, &entries, 0x1000, 0x2000), None);
+        assert_eq!(
+            translate_with(mode, &entries, 0x1000, 0x8000_0000_0000),
+            None
+        );
+    }
+
+    #[test]
+    fn translates_pae_pages() {
+        // The PDPT at 0x1020 has 4 entries, the PD is at 0x2000 and the PT at 0x3000.
+        let entries = HashMap::from([
+            (0x1028, 0x2000 | ENTRY_PRESENT),
+            (0x2000, 0x3000 | PRESENT_WRITE),
+            (0x3010, 0x7000 | PRESENT_WRITE),
+        ]);
+
+        let mode = PagingMode::Pae;
+        assert_eq!(
+            translate_with(mode, &entries, 0x1020, 0x4000_2345),
+            Some(0x7345)
+        );
+        assert_eq!(translate_with(mode, &entries, 0x1020, 0x2345), None);
+        assert_eq!(translate_with(mode, &entries, 0x1020, 0x1_0000_0000), None);
+    }
+
+    #[test]
+    fn empty_roots_have_no_present_entry() {
+        let entries = HashMap::from([
+            (0x1000, 0x2000 | ENTRY_WRITE),
+            (0x2ff8, 0x3000 | ENTRY_PRESENT),
+        ]);
+        let mut read_entry = |entry_address: u64| entries.get(&entry_address).copied();
+
+        assert!(!has_present_entry(
+            PagingMode::FourLevel,
+            0x1000,
+            &mut read_entry
+        ));
+        assert!(has_present_entry(
+            PagingMode::FourLevel,
+            0x2000,
+            &mut read_entry
+        ));
+        assert!(!has_present_entry(
+            PagingMode::FourLevel,
+            0x5000,
+            &mut read_entry
+        ));
+    }
+}
@@ -0,0 +1,59 @@
+// Decoding of the IN/OUT forms that icicle cannot execute natively:
+//
+//   E4 ib / E5 ib   IN  AL/eAX, imm8
+//   EC    / ED      IN  AL/eAX, DX
+//   E6 ib / E7 ib   OUT imm8, AL/eAX
+//   EE    / EF      OUT DX, AL/eAX
+//
+// An operand size prefix (0x66) turns the eAX forms into 16-bit accesses.
+// String forms (INS/OUTS) are not handled.
+
+const OPERAND_SIZE_PREFIX: u8 = 0x66;
+
+pub struct PortAccess {
+    // None when the port is taken from DX.
+    pub immediate: Option<u16>,
+    pub width: u8,
+    pub write: bool,
+    pub length: u64,
+}
+
+pub fn parse(bytes: &[u8]) -> Option<PortAccess> {
+    let mut position = 0;
+    let mut operand_size_override = false;
+
+    while position < bytes.len() && bytes[position] == OPERAND_SIZE_PREFIX {
+        operand_size_override = true;
+        position += 1;
+    }
+
+    let opcode = *bytes.get(position)?;
+    position += 1;
+
+    let wide_width = if operand_size_override { 2 } else { 4 };
+
+    let (width, write, has_immediate) = match opcode {
+        0xE4 => (1, false, true),
+        0xE5 => (wide_width, false, true),
+        0xE6 => (1, true, true),
+        0xE7 => (wide_width, true, true),
+        0xEC => (1, false, false),
+        0xED => (wide_width, false, false),
+        0xEE => (1, true, false),
+        0xEF => (wide_width, true, false),
+        _ => return None,
+    };
+
+    let mut immediate = None;
+    if has_immediate {
+        immediate = Some(*bytes.get(position)? as u16);
+        position += 1;
+    }
+
+    return Some(PortAccess {
+        immediate,
+        width,
+        write,
+        length: position as u64,
+    });
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+
+// Address ranges currently backed by guest RAM, keyed by start address with
+// exclusive end addresses. Adjacent and overlapping ranges are merged.
+#[derive(Clone)]
+pub struct RegionMap {
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl RegionMap {
+    pub fn new() -> Self {
+        Self {
+            ranges: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, address: u64, length: u64) {
+        let mut start = address;
+        let mut end = address.saturating_add(length);
+
+        let touching: Vec<(u64, u64)> = self
+            .ranges
+            .range(..=end)
+            .filter(|(_, range_end)| **range_end >= start)
+            .map(|(range_start, range_end)| (*range_start, *range_end))
+            .collect();
+
+        for (range_start, range_end) in touching {
+            self.ranges.remove(&range_start);
+            start = std::cmp::min(start, range_start);
+            end = std::cmp::max(end, range_end);
+        }
+
+        self.ranges.insert(start, end);
+    }
+
+    pub fn remove(&mut self, address: u64, length: u64) {
+        let end = address.saturating_add(length);
+
+        let overlapping: Vec<(u64, u64)> = self
+            .ranges
+            .range(..end)
+            .filter(|(_, range_end)| **range_end > address)
+            .map(|(range_start, range_end)| (*range_start, *range_end))
+            .collect();
+
+        for (range_start, range_end) in overlapping {
+            self.ranges.remove(&range_start);
+
+            if range_start < address {
+                self.ranges.insert(range_start, address);
+            }
+
+            if range_end > end {
+                self.ranges.insert(end, range_end);
+            }
+        }
+    }
+
+    // Returns the first stored range intersecting [address, address + length).
+    pub fn find_overlap(&self, address: u64, length: u64) -> Option<(u64, u64)> {
+        let end = address.saturating_add(length);
+
+        return self
+            .ranges
+            .range(..end)
+            .find(|(_, range_end)| **range_end > address)
+            .map(|(range_start, range_end)| (*range_start, *range_end));
+    }
+
+    // Number of bytes from `address` to the end of the range containing it, 0 if none does.
+    pub fn remaining(&self, address: u64) -> u64 {
+        return match self.ranges.range(..=address).next_back() {
+            Some((_, end)) if *end > address => *end - address,
+            _ => 0,
+        };
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        return self.ranges.iter().map(|(start, end)| (*start, *end));
+    }
+}
@@ -1,5 +1,5 @@
 #[repr(i32)]
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum X86Register {
     Invalid = 0,
     Ah,
@@ -316,14 +316,14 @@ pub(crate) struct X86RegisterNodes {
     fp5: pcode::VarNode,
     fp6: pcode::VarNode,
     fp7: pcode::VarNode,
-    /*k0: pcode::VarNode,
-    k1: pcode::VarNode,
-    k2: pcode::VarNode,
-    k3: pcode::VarNode,
-    k4: pcode::VarNode,
-    k5: pcode::VarNode,
-    k6: pcode::VarNode,
-    k7: pcode::VarNode,*/
+    k0: Option<pcode::VarNode>,
+    k1: Option<pcode::VarNode>,
+    k2: Option<pcode::VarNode>,
+    k3: Option<pcode::VarNode>,
+    k4: Option<pcode::VarNode>,
+    k5: Option<pcode::VarNode>,
+    k6: Option<pcode::VarNode>,
+    k7: Option<pcode::VarNode>,
     mm0: pcode::VarNode,
     mm1: pcode::VarNode,
     mm2: pcode::VarNode,
@@ -356,22 +356,22 @@ pub(crate) struct X86RegisterNodes {
     xmm13: pcode::VarNode,
     xmm14: pcode::VarNode,
     xmm15: pcode::VarNode,
-    /*xmm16: pcode::VarNode,
-    xmm17: pcode::VarNode,
-    xmm18: pcode::VarNode,
-    xmm19: pcode::VarNode,
-    xmm20: pcode::VarNode,
-    xmm21: pcode::VarNode,
-    xmm22: pcode::VarNode,
-    xmm23: pcode::VarNode,
-    xmm24: pcode::VarNode,
-    xmm25: pcode::VarNode,
-    xmm26: pcode::VarNode,
-    xmm27: pcode::VarNode,
-    xmm28: pcode::VarNode,
-    xmm29: pcode::VarNode,
-    xmm30: pcode::VarNode,
-    xmm31: pcode::VarNode,*/
+    xmm16: Option<pcode::VarNode>,
+    xmm17: Option<pcode::VarNode>,
+    xmm18: Option<pcode::VarNode>,
+    xmm19: Option<pcode::VarNode>,
+    xmm20: Option<pcode::VarNode>,
+    xmm21: Option<pcode::VarNode>,
+    xmm22: Option<pcode::VarNode>,
+    xmm23: Option<pcode::VarNode>,
+    xmm24: Option<pcode::VarNode>,
+    xmm25: Option<pcode::VarNode>,
+    xmm26: Option<pcode::VarNode>,
+    xmm27: Option<pcode::VarNode>,
+    xmm28: Option<pcode::VarNode>,
+    xmm29: Option<pcode::VarNode>,
+    xmm30: Option<pcode::VarNode>,
+    xmm31: Option<pcode::VarNode>,
     ymm0: pcode::VarNode,
     ymm1: pcode::VarNode,
     ymm2: pcode::VarNode,
@@ -388,54 +388,54 @@ pub(crate) struct X86RegisterNodes {
     ymm13: pcode::VarNode,
     ymm14: pcode::VarNode,
     ymm15: pcode::VarNode,
-    /*ymm16: pcode::VarNode,
-    ymm17: pcode::VarNode,
-    ymm18: pcode::VarNode,
-    ymm19: pcode::VarNode,
-    ymm20: pcode::VarNode,
-    ymm21: pcode::VarNode,
-    ymm22: pcode::VarNode,
-    ymm23: pcode::VarNode,
-    ymm24: pcode::VarNode,
-    ymm25: pcode::VarNode,
-    ymm26: pcode::VarNode,
-    ymm27: pcode::VarNode,
-    ymm28: pcode::VarNode,
-    ymm29: pcode::VarNode,
-    ymm30: pcode::VarNode,
-    ymm31: pcode::VarNode,*/
-    /*zmm0: pcode::VarNode,
-    zmm1: pcode::VarNode,
-    zmm2: pcode::VarNode,
-    zmm3: pcode::VarNode,
-    zmm4: pcode::VarNode,
-    zmm5: pcode::VarNode,
-    zmm6: pcode::VarNode,
-    zmm7: pcode::VarNode,
-    zmm8: pcode::VarNode,
-    zmm9: pcode::VarNode,
-    zmm10: pcode::VarNode,
-    zmm11: pcode::VarNode,
-    zmm12: pcode::VarNode,
-    zmm13: pcode::VarNode,
-    zmm14: pcode::VarNode,
-    zmm15: pcode::VarNode,
-    zmm16: pcode::VarNode,
-    zmm17: pcode::VarNode,
-    zmm18: pcode::VarNode,
-    zmm19: pcode::VarNode,
-    zmm20: pcode::VarNode,
-    zmm21: pcode::VarNode,
-    zmm22: pcode::VarNode,
-    zmm23: pcode::VarNode,
-    zmm24: pcode::VarNode,
-    zmm25: pcode::VarNode,
-    zmm26: pcode::VarNode,
-    zmm27: pcode::VarNode,
-    zmm28: pcode::VarNode,
-    zmm29: pcode::VarNode,
-    zmm30: pcode::VarNode,
-    zmm31: pcode::VarNode,*/
+    ymm16: Option<pcode::VarNode>,
+    ymm17: Option<pcode::VarNode>,
+    ymm18: Option<pcode::VarNode>,
+    ymm19: Option<pcode::VarNode>,
+    ymm20: Option<pcode::VarNode>,
+    ymm21: Option<pcode::VarNode>,
+    ymm22: Option<pcode::VarNode>,
+    ymm23: Option<pcode::VarNode>,
+    ymm24: Option<pcode::VarNode>,
+    ymm25: Option<pcode::VarNode>,
+    ymm26: Option<pcode::VarNode>,
+    ymm27: Option<pcode::VarNode>,
+    ymm28: Option<pcode::VarNode>,
+    ymm29: Option<pcode::VarNode>,
+    ymm30: Option<pcode::VarNode>,
+    ymm31: Option<pcode::VarNode>,
+    zmm0: Option<pcode::VarNode>,
+    zmm1: Option<pcode::VarNode>,
+    zmm2: Option<pcode::VarNode>,
+    zmm3: Option<pcode::VarNode>,
+    zmm4: Option<pcode::VarNode>,
+    zmm5: Option<pcode::VarNode>,
+    zmm6: Option<pcode::VarNode>,
+    zmm7: Option<pcode::VarNode>,
+    zmm8: Option<pcode::VarNode>,
+    zmm9: Option<pcode::VarNode>,
+    zmm10: Option<pcode::VarNode>,
+    zmm11: Option<pcode::VarNode>,
+    zmm12: Option<pcode::VarNode>,
+    zmm13: Option<pcode::VarNode>,
+    zmm14: Option<pcode::VarNode>,
+    zmm15: Option<pcode::VarNode>,
+    zmm16: Option<pcode::VarNode>,
+    zmm17: Option<pcode::VarNode>,
+    zmm18: Option<pcode::VarNode>,
+    zmm19: Option<pcode::VarNode>,
+    zmm20: Option<pcode::VarNode>,
+    zmm21: Option<pcode::VarNode>,
+    zmm22: Option<pcode::VarNode>,
+    zmm23: Option<pcode::VarNode>,
+    zmm24: Option<pcode::VarNode>,
+    zmm25: Option<pcode::VarNode>,
+    zmm26: Option<pcode::VarNode>,
+    zmm27: Option<pcode::VarNode>,
+    zmm28: Option<pcode::VarNode>,
+    zmm29: Option<pcode::VarNode>,
+    zmm30: Option<pcode::VarNode>,
+    zmm31: Option<pcode::VarNode>,
     r8b: pcode::VarNode,
     r9b: pcode::VarNode,
     r10b: pcode::VarNode,
@@ -476,7 +476,12 @@ pub(crate) struct X86RegisterNodes {
 
 impl X86RegisterNodes {
     pub fn new(arch: &icicle_cpu::Arch) -> Self {
-        let r = |name: &str| arch.sleigh.get_reg(name).unwrap().var;
+        let r = |name: &str| match arch.sleigh.get_reg(name) {
+            Some(reg) => reg.var,
+            None => panic!("Register {name} missing from the specification"),
+        };
+        // AVX-512 registers are only present in specifications that support it.
+        let o = |name: &str| arch.sleigh.get_reg(name).map(|reg| reg.var);
         let nodes = [
             "CF", "F1", "PF", "F3", "AF", "F5", "ZF", "SF", "TF", "IF", "DF", "OF", "IOPL", "NT",
             "F15", "RF", "VM", "AC", "VIF", "VIP", "ID",
@@ -554,14 +559,14 @@ impl X86RegisterNodes {
             fp5: r("ST5"),
             fp6: r("ST6"),
             fp7: r("ST7"),
-            /*k0: r("K0"),
-            k1: r("K1"),
-            k2: r("K2"),
-            k3: r("K3"),
-            k4: r("K4"),
-            k5: r("K5"),
-            k6: r("K6"),
-            k7: r("K7"),*/
+            k0: o("K0"),
+            k1: o("K1"),
+            k2: o("K2"),
+            k3: o("K3"),
+            k4: o("K4"),
+            k5: o("K5"),
+            k6: o("K6"),
+            k7: o("K7"),
             mm0: r("MM0"),
             mm1: r("MM1"),
             mm2: r("MM2"),
@@ -594,22 +599,22 @@ impl X86RegisterNodes {
             xmm13: r("XMM13"),
             xmm14: r("XMM14"),
             xmm15: r("XMM15"),
-            /*xmm16: r("XMM16"),
-            xmm17: r("XMM17"),
-            xmm18: r("XMM18"),
-            xmm19: r("XMM19"),
-            xmm20: r("XMM20"),
-            xmm21: r("XMM21"),
-            xmm22: r("XMM22"),
-            xmm23: r("XMM23"),
-            xmm24: r("XMM24"),
-            xmm25: r("XMM25"),
-            xmm26: r("XMM26"),
-            xmm27: r("XMM27"),
-            xmm28: r("XMM28"),
-            xmm29: r("XMM29"),
-            xmm30: r("XMM30"),
-            xmm31: r("XMM31"),*/
+            xmm16: o("XMM16"),
+            xmm17: o("XMM17"),
+            xmm18: o("XMM18"),
+            xmm19: o("XMM19"),
+            xmm20: o("XMM20"),
+            xmm21: o("XMM21"),
+            xmm22: o("XMM22"),
+            xmm23: o("XMM23"),
+            xmm24: o("XMM24"),
+            xmm25: o("XMM25"),
+            xmm26: o("XMM26"),
+            xmm27: o("XMM27"),
+            xmm28: o("XMM28"),
+            xmm29: o("XMM29"),
+            xmm30: o("XMM30"),
+            xmm31: o("XMM31"),
             ymm0: r("YMM0"),
             ymm1: r("YMM1"),
             ymm2: r("YMM2"),
@@ -626,54 +631,54 @@ impl X86RegisterNodes {
             ymm13: r("YMM13"),
             ymm14: r("YMM14"),
             ymm15: r("YMM15"),
-            /*ymm16: r("YMM16"),
-            ymm17: r("YMM17"),
-            ymm18: r("YMM18"),
-            ymm19: r("YMM19"),
-            ymm20: r("YMM20"),
-            ymm21: r("YMM21"),
-            ymm22: r("YMM22"),
-            ymm23: r("YMM23"),
-            ymm24: r("YMM24"),
-            ymm25: r("YMM25"),
-            ymm26: r("YMM26"),
-            ymm27: r("YMM27"),
-            ymm28: r("YMM28"),
-            ymm29: r("YMM29"),
-            ymm30: r("YMM30"),
-            ymm31: r("YMM31"),*/
-            /*zmm0: r("ZMM0"),
-            zmm1: r("ZMM1"),
-            zmm2: r("ZMM2"),
-            zmm3: r("ZMM3"),
-            zmm4: r("ZMM4"),
-            zmm5: r("ZMM5"),
-            zmm6: r("ZMM6"),
-            zmm7: r("ZMM7"),
-            zmm8: r("ZMM8"),
-            zmm9: r("ZMM9"),
-            zmm10: r("ZMM10"),
-            zmm11: r("ZMM11"),
-            zmm12: r("ZMM12"),
-            zmm13: r("ZMM13"),
-            zmm14: r("ZMM14"),
-            zmm15: r("ZMM15"),
-            zmm16: r("ZMM16"),
-            zmm17: r("ZMM17"),
-            zmm18: r("ZMM18"),
-            zmm19: r("ZMM19"),
-            zmm20: r("ZMM20"),
-            zmm21: r("ZMM21"),
-            zmm22: r("ZMM22"),
-            zmm23: r("ZMM23"),
-            zmm24: r("ZMM24"),
-            zmm25: r("ZMM25"),
-            zmm26: r("ZMM26"),
-            zmm27: r("ZMM27"),
-            zmm28: r("ZMM28"),
-            zmm29: r("ZMM29"),
-            zmm30: r("ZMM30"),
-            zmm31: r("ZMM31"),*/
+            ymm16: o("YMM16"),
+            ymm17: o("YMM17"),
+            ymm18: o("YMM18"),
+            ymm19: o("YMM19"),
+            ymm20: o("YMM20"),
+            ymm21: o("YMM21"),
+            ymm22: o("YMM22"),
+            ymm23: o("YMM23"),
+            ymm24: o("YMM24"),
+            ymm25: o("YMM25"),
+            ymm26: o("YMM26"),
+            ymm27: o("YMM27"),
+            ymm28: o("YMM28"),
+            ymm29: o("YMM29"),
+            ymm30: o("YMM30"),
+            ymm31: o("YMM31"),
+            zmm0: o("ZMM0"),
+            zmm1: o("ZMM1"),
+            zmm2: o("ZMM2"),
+            zmm3: o("ZMM3"),
+            zmm4: o("ZMM4"),
+            zmm5: o("ZMM5"),
+            zmm6: o("ZMM6"),
+            zmm7: o("ZMM7"),
+            zmm8: o("ZMM8"),
+            zmm9: o("ZMM9"),
+            zmm10: o("ZMM10"),
+            zmm11: o("ZMM11"),
+            zmm12: o("ZMM12"),
+            zmm13: o("ZMM13"),
+            zmm14: o("ZMM14"),
+            zmm15: o("ZMM15"),
+            zmm16: o("ZMM16"),
+            zmm17: o("ZMM17"),
+            zmm18: o("ZMM18"),
+            zmm19: o("ZMM19"),
+            zmm20: o("ZMM20"),
+            zmm21: o("ZMM21"),
+            zmm22: o("ZMM22"),
+            zmm23: o("ZMM23"),
+            zmm24: o("ZMM24"),
+            zmm25: o("ZMM25"),
+            zmm26: o("ZMM26"),
+            zmm27: o("ZMM27"),
+            zmm28: o("ZMM28"),
+            zmm29: o("ZMM29"),
+            zmm30: o("ZMM30"),
+            zmm31: o("ZMM31"),
             r8b: r("R8B"),
             r9b: r("R9B"),
             r10b: r("R10B"),
@@ -707,6 +712,7 @@ impl X86RegisterNodes {
             fds: r("DS"), // ?
             fcs: r("CS"), // ?
             //msr: r("MSR"),
+            // 8-byte registers the SLEIGH spec adds to addresses with fs:/gs: overrides.
             fs_base: r("FS_OFFSET"),
             gs_base: r("GS_OFFSET"),
             flags: nodes.map(|name: &str| r(name)).to_vec(),
@@ -731,7 +737,102 @@ impl X86RegisterNodes {
         }
     }
 
+    fn avx512_node(&self, reg: X86Register) -> Option<pcode::VarNode> {
+        match reg {
+            X86Register::K0 => self.k0,
+            X86Register::K1 => self.k1,
+            X86Register::K2 => self.k2,
+            X86Register::K3 => self.k3,
+            X86Register::K4 => self.k4,
+            X86Register::K5 => self.k5,
+            X86Register::K6 => self.k6,
+            X86Register::K7 => self.k7,
+            X86Register::Xmm16 => self.xmm16,
+            X86Register::Xmm17 => self.xmm17,
+            X86Register::Xmm18 => self.xmm18,
+            X86Register::Xmm19 => self.xmm19,
+            X86Register::Xmm20 => self.xmm20,
+            X86Register::Xmm21 => self.xmm21,
+            X86Register::Xmm22 => self.xmm22,
+            X86Register::Xmm23 => self.xmm23,
+            X86Register::Xmm24 => self.xmm24,
+            X86Register::Xmm25 => self.xmm25,
+            X86Register::Xmm26 => self.xmm26,
+            X86Register::Xmm27 => self.xmm27,
+            X86Register::Xmm28 => self.xmm28,
+            X86Register::Xmm29 => self.xmm29,
+            X86Register::Xmm30 => self.xmm30,
+            X86Register::Xmm31 => self.xmm31,
+            X86Register::Ymm16 => self.ymm16,
+            X86Register::Ymm17 => self.ymm17,
+            X86Register::Ymm18 => self.ymm18,
+            X86Register::Ymm19 => self.ymm19,
+            X86Register::Ymm20 => self.ymm20,
+            X86Register::Ymm21 => self.ymm21,
+            X86Register::Ymm22 => self.ymm22,
+            X86Register::Ymm23 => self.ymm23,
+            X86Register::Ymm24 => self.ymm24,
+            X86Register::Ymm25 => self.ymm25,
+            X86Register::Ymm26 => self.ymm26,
+            X86Register::Ymm27 => self.ymm27,
+            X86Register::Ymm28 => self.ymm28,
+            X86Register::Ymm29 => self.ymm29,
+            X86Register::Ymm30 => self.ymm30,
+            X86Register::Ymm31 => self.ymm31,
+            X86Register::Zmm0 => self.zmm0,
+            X86Register::Zmm1 => self.zmm1,
+            X86Register::Zmm2 => self.zmm2,
+            X86Register::Zmm3 => self.zmm3,
+            X86Register::Zmm4 => self.zmm4,
+            X86Register::Zmm5 => self.zmm5,
+            X86Register::Zmm6 => self.zmm6,
+            X86Register::Zmm7 => self.zmm7,
+            X86Register::Zmm8 => self.zmm8,
+            X86Register::Zmm9 => self.zmm9,
+            X86Register::Zmm10 => self.zmm10,
+            X86Register::Zmm11 => self.zmm11,
+            X86Register::Zmm12 => self.zmm12,
+            X86Register::Zmm13 => self.zmm13,
+            X86Register::Zmm14 => self.zmm14,
+            X86Register::Zmm15 => self.zmm15,
+            X86Register::Zmm16 => self.zmm16,
+            X86Register::Zmm17 => self.zmm17,
+            X86Register::Zmm18 => self.zmm18,
+            X86Register::Zmm19 => self.zmm19,
+            X86Register::Zmm20 => self.zmm20,
+            X86Register::Zmm21 => self.zmm21,
+            X86Register::Zmm22 => self.zmm22,
+            X86Register::Zmm23 => self.zmm23,
+            X86Register::Zmm24 => self.zmm24,
+            X86Register::Zmm25 => self.zmm25,
+            X86Register::Zmm26 => self.zmm26,
+            X86Register::Zmm27 => self.zmm27,
+            X86Register::Zmm28 => self.zmm28,
+            X86Register::Zmm29 => self.zmm29,
+            X86Register::Zmm30 => self.zmm30,
+            X86Register::Zmm31 => self.zmm31,
+            _ => None,
+        }
+    }
+
+    fn is_avx512(reg: X86Register) -> bool {
+        let value = reg as i32;
+        return (X86Register::Xmm16 as i32..=X86Register::Xmm31 as i32).contains(&value)
+            || (X86Register::Ymm16 as i32..=X86Register::Ymm31 as i32).contains(&value)
+            || (X86Register::Zmm0 as i32..=X86Register::Zmm31 as i32).contains(&value)
+            || (X86Register::K0 as i32..=X86Register::K7 as i32).contains(&value);
+    }
+
+    // False for AVX-512 registers the loaded specification does not define.
+    pub fn is_available(&self, reg: X86Register) -> bool {
+        return !Self::is_avx512(reg) || self.avx512_node(reg).is_some();
+    }
+
     pub fn get_node(&self, reg: X86Register) -> pcode::VarNode {
+        if let Some(node) = self.avx512_node(reg) {
+            return node;
+        }
+
         match reg {
             X86Register::Rax => self.rax,
             X86Register::Rbx => self.rbx,
@@ -804,14 +905,6 @@ impl X86RegisterNodes {
             X86Register::Fp5 => self.fp5,
             X86Register::Fp6 => self.fp6,
             X86Register::Fp7 => self.fp7,
-            /*X86Register::K0 => self.k0,
-            X86Register::K1 => self.k1,
-            X86Register::K2 => self.k2,
-            X86Register::K3 => self.k3,
-            X86Register::K4 => self.k4,
-            X86Register::K5 => self.k5,
-            X86Register::K6 => self.k6,
-            X86Register::K7 => self.k7,*/
             X86Register::Mm0 => self.mm0,
             X86Register::Mm1 => self.mm1,
             X86Register::Mm2 => self.mm2,
@@ -844,22 +937,6 @@ impl X86RegisterNodes {
             X86Register::Xmm13 => self.xmm13,
             X86Register::Xmm14 => self.xmm14,
             X86Register::Xmm15 => self.xmm15,
-            /*X86Register::Xmm16 => self.xmm16,
-            X86Register::Xmm17 => self.xmm17,
-            X86Register::Xmm18 => self.xmm18,
-            X86Register::Xmm19 => self.xmm19,
-            X86Register::Xmm20 => self.xmm20,
-            X86Register::Xmm21 => self.xmm21,
-            X86Register::Xmm22 => self.xmm22,
-            X86Register::Xmm23 => self.xmm23,
-            X86Register::Xmm24 => self.xmm24,
-            X86Register::Xmm25 => self.xmm25,
-            X86Register::Xmm26 => self.xmm26,
-            X86Register::Xmm27 => self.xmm27,
-            X86Register::Xmm28 => self.xmm28,
-            X86Register::Xmm29 => self.xmm29,
-            X86Register::Xmm30 => self.xmm30,
-            X86Register::Xmm31 => self.xmm31,*/
             X86Register::Ymm0 => self.ymm0,
             X86Register::Ymm1 => self.ymm1,
             X86Register::Ymm2 => self.ymm2,
@@ -876,54 +953,6 @@ impl X86RegisterNodes {
             X86Register::Ymm13 => self.ymm13,
             X86Register::Ymm14 => self.ymm14,
             X86Register::Ymm15 => self.ymm15,
-            /*X86Register::Ymm16 => self.ymm16,
-            X86Register::Ymm17 => self.ymm17,
-            X86Register::Ymm18 => self.ymm18,
-            X86Register::Ymm19 => self.ymm19,
-            X86Register::Ymm20 => self.ymm20,
-            X86Register::Ymm21 => self.ymm21,
-            X86Register::Ymm22 => self.ymm22,
-            X86Register::Ymm23 => self.ymm23,
-            X86Register::Ymm24 => self.ymm24,
-            X86Register::Ymm25 => self.ymm25,
-            X86Register::Ymm26 => self.ymm26,
-            X86Register::Ymm27 => self.ymm27,
-            X86Register::Ymm28 => self.ymm28,
-            X86Register::Ymm29 => self.ymm29,
-            X86Register::Ymm30 => self.ymm30,
-            X86Register::Ymm31 => self.ymm31,*/
-            /*X86Register::Zmm0 => self.zmm0,
-            X86Register::Zmm1 => self.zmm1,
-            X86Register::Zmm2 => self.zmm2,
-            X86Register::Zmm3 => self.zmm3,
-            X86Register::Zmm4 => self.zmm4,
-            X86Register::Zmm5 => self.zmm5,
-            X86Register::Zmm6 => self.zmm6,
-            X86Register::Zmm7 => self.zmm7,
-            X86Register::Zmm8 => self.zmm8,
-            X86Register::Zmm9 => self.zmm9,
-            X86Register::Zmm10 => self.zmm10,
-            X86Register::Zmm11 => self.zmm11,
-            X86Register::Zmm12 => self.zmm12,
-            X86Register::Zmm13 => self.zmm13,
-            X86Register::Zmm14 => self.zmm14,
-            X86Register::Zmm15 => self.zmm15,
-            X86Register::Zmm16 => self.zmm16,
-            X86Register::Zmm17 => self.zmm17,
-            X86Register::Zmm18 => self.zmm18,
-            X86Register::Zmm19 => self.zmm19,
-            X86Register::Zmm20 => self.zmm20,
-            X86Register::Zmm21 => self.zmm21,
-            X86Register::Zmm22 => self.zmm22,
-            X86Register::Zmm23 => self.zmm23,
-            X86Register::Zmm24 => self.zmm24,
-            X86Register::Zmm25 => self.zmm25,
-            X86Register::Zmm26 => self.zmm26,
-            X86Register::Zmm27 => self.zmm27,
-            X86Register::Zmm28 => self.zmm28,
-            X86Register::Zmm29 => self.zmm29,
-            X86Register::Zmm30 => self.zmm30,
-            X86Register::Zmm31 => self.zmm31,*/
             X86Register::R8b => self.r8b,
             X86Register::R9b => self.r9b,
             X86Register::R10b => self.r10b,
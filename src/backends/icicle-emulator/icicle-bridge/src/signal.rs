@@ -0,0 +1,112 @@
+use crate::registers::X86Register;
+
+// Linux x86_64 rt_sigframe as laid out by the kernel:
+//
+//   0x000  pretcode     (return address of the handler, i.e. sa_restorer)
+//   0x008  ucontext     uc_flags, uc_link, uc_stack, uc_mcontext (sigcontext), uc_sigmask
+//   0x138  siginfo      128 bytes, only si_signo/si_errno/si_code are filled
+//
+// The handler is entered with RSP pointing at pretcode, so RSP + 8 is 16-byte aligned.
+
+pub const RED_ZONE_SIZE: u64 = 128;
+
+pub const UCONTEXT_OFFSET: usize = 0x8;
+pub const SIGINFO_OFFSET: usize = 0x138;
+pub const FRAME_SIZE: usize = SIGINFO_OFFSET + 128;
+
+const MCONTEXT_OFFSET: usize = UCONTEXT_OFFSET + 0x28;
+const EFLAGS_OFFSET: usize = MCONTEXT_OFFSET + 0x88;
+const SEGMENTS_OFFSET: usize = MCONTEXT_OFFSET + 0x90;
+const SIGMASK_OFFSET: usize = UCONTEXT_OFFSET + 0x128;
+
+// Register order of struct sigcontext, up to and including RIP.
+pub const SIGCONTEXT_REGISTERS: [X86Register; 17] = [
+    X86Register::R8,
+    X86Register::R9,
+    X86Register::R10,
+    X86Register::R11,
+    X86Register::R12,
+    X86Register::R13,
+    X86Register::R14,
+    X86Register::R15,
+    X86Register::Rdi,
+    X86Register::Rsi,
+    X86Register::Rbp,
+    X86Register::Rbx,
+    X86Register::Rdx,
+    X86Register::Rax,
+    X86Register::Rcx,
+    X86Register::Rsp,
+    X86Register::Rip,
+];
+
+// Segment order of struct sigcontext.
+pub const SIGCONTEXT_SEGMENTS: [X86Register; 4] = [
+    X86Register::Cs,
+    X86Register::Gs,
+    X86Register::Fs,
+    X86Register::Ss,
+];
+
+#[derive(Default)]
+pub struct SignalContext {
+    pub registers: [u64; 17],
+    pub eflags: u64,
+    pub segments: [u16; 4],
+    pub sigmask: u64,
+}
+
+fn write_u64(frame: &mut [u8], offset: usize, value: u64) {
+    frame[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+fn read_u64(frame: &[u8], offset: usize) -> u64 {
+    return u64::from_le_bytes(frame[offset..offset + 8].try_into().unwrap());
+}
+
+pub fn frame_address(stack_pointer: u64) -> u64 {
+    let sp = stack_pointer.wrapping_sub(RED_ZONE_SIZE + FRAME_SIZE as u64);
+    return (sp & !15).wrapping_sub(8);
+}
+
+pub fn build_frame(context: &SignalContext, signo: i32, restorer: u64) -> [u8; FRAME_SIZE] {
+    let mut frame = [0u8; FRAME_SIZE];
+
+    write_u64(&mut frame, 0, restorer);
+
+    for (index, value) in context.registers.iter().enumerate() {
+        write_u64(&mut frame, MCONTEXT_OFFSET + index * 8, *value);
+    }
+
+    write_u64(&mut frame, EFLAGS_OFFSET, context.eflags);
+
+    for (index, value) in context.segments.iter().enumerate() {
+        let offset = SEGMENTS_OFFSET + index * 2;
+        frame[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    write_u64(&mut frame, SIGMASK_OFFSET, context.sigmask);
+
+    frame[SIGINFO_OFFSET..SIGINFO_OFFSET + 4].copy_from_slice(&signo.to_le_bytes());
+
+    return frame;
+}
+
+pub fn parse_frame(frame: &[u8; FRAME_SIZE]) -> SignalContext {
+    let mut context = SignalContext::default();
+
+    for (index, value) in context.registers.iter_mut().enumerate() {
+        *value = read_u64(frame, MCONTEXT_OFFSET + index * 8);
+    }
+
+    context.eflags = read_u64(frame, EFLAGS_OFFSET);
+
+    for (index, value) in context.segments.iter_mut().enumerate() {
+        let offset = SEGMENTS_OFFSET + index * 2;
+        *value = u16::from_le_bytes([frame[offset], frame[offset + 1]]);
+    }
+
+    context.sigmask = read_u64(frame, SIGMASK_OFFSET);
+
+    return context;
+}
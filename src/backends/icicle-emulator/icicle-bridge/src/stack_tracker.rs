@@ -0,0 +1,44 @@
+// Tracks which bytes of a stack region have been written since the watch was installed.
+// Everything starts out uninitialized, one bit per byte.
+
+pub struct StackTracker {
+    base: u64,
+    size: u64,
+    written: Vec<u64>,
+}
+
+impl StackTracker {
+    pub fn new(base: u64, size: u64) -> Self {
+        Self {
+            base,
+            size,
+            written: vec![0; size.div_ceil(64) as usize],
+        }
+    }
+
+    // Clips [address, address + length) to the tracked region, as offsets into it.
+    fn clip(&self, address: u64, length: usize) -> std::ops::Range<u64> {
+        let start = address.max(self.base);
+        let end = address
+            .saturating_add(length as u64)
+            .min(self.base + self.size);
+
+        if start >= end {
+            return 0..0;
+        }
+
+        return (start - self.base)..(end - self.base);
+    }
+
+    pub fn mark_written(&mut self, address: u64, length: usize) {
+        for offset in self.clip(address, length) {
+            self.written[(offset / 64) as usize] |= 1 << (offset % 64);
+        }
+    }
+
+    pub fn is_uninitialized(&self, address: u64, length: usize) -> bool {
+        return self
+            .clip(address, length)
+            .any(|offset| (self.written[(offset / 64) as usize] & (1 << (offset % 64))) == 0);
+    }
+}
@@ -0,0 +1,115 @@
+use std::time::Instant;
+
+// Register layout of the timer page, all registers are 64-bit little-endian:
+//
+//   0x00  COUNTER   ticks at the configured frequency since the device was attached (read-only)
+//   0x08  INTERVAL  ticks between interrupts, 0 disables the periodic interrupt
+//   0x10  CONTROL   bit 0 enables interrupt delivery
+//   0x18  VECTOR    interrupt number passed to the interrupt hooks
+
+pub const TIMER_SIZE: u64 = 0x1000;
+
+const COUNTER_OFFSET: u64 = 0x00;
+const INTERVAL_OFFSET: u64 = 0x08;
+const CONTROL_OFFSET: u64 = 0x10;
+const VECTOR_OFFSET: u64 = 0x18;
+
+const CONTROL_ENABLE: u64 = 1 << 0;
+
+pub struct TimerDevice {
+    start: Instant,
+    frequency: u64,
+    interval: u64,
+    control: u64,
+    vector: i32,
+    deadline: u64,
+    pending: bool,
+}
+
+impl TimerDevice {
+    pub fn new(frequency: u64, vector: i32) -> Self {
+        Self {
+            start: Instant::now(),
+            frequency,
+            interval: 0,
+            control: 0,
+            vector,
+            deadline: 0,
+            pending: false,
+        }
+    }
+
+    fn ticks(&self) -> u64 {
+        let nanos = self.start.elapsed().as_nanos();
+        return (nanos * self.frequency as u128 / 1_000_000_000) as u64;
+    }
+
+    fn read_register(&self, offset: u64) -> u64 {
+        match offset {
+            COUNTER_OFFSET => self.ticks(),
+            INTERVAL_OFFSET => self.interval,
+            CONTROL_OFFSET => self.control,
+            VECTOR_OFFSET => self.vector as u64,
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, offset: u64, value: u64) {
+        match offset {
+            INTERVAL_OFFSET => {
+                self.interval = value;
+                self.deadline = self.ticks().saturating_add(value);
+            }
+            CONTROL_OFFSET => self.control = value,
+            VECTOR_OFFSET => self.vector = value as i32,
+            _ => {}
+        }
+    }
+
+    pub fn read(&mut self, offset: u64, data: &mut [u8]) {
+        let register = offset & !7;
+        let shift = (offset & 7) as usize;
+
+        let bytes = self.read_register(register).to_le_bytes();
+        let len = std::cmp::min(data.len(), bytes.len() - shift);
+
+        data.fill(0);
+        data[..len].copy_from_slice(&bytes[shift..shift + len]);
+    }
+
+    pub fn write(&mut self, offset: u64, data: &[u8]) {
+        let register = offset & !7;
+        let shift = (offset & 7) as usize;
+
+        let mut bytes = self.read_register(register).to_le_bytes();
+        let len = std::cmp::min(data.len(), bytes.len() - shift);
+        bytes[shift..shift + len].copy_from_slice(&data[..len]);
+
+        self.write_register(register, u64::from_le_bytes(bytes));
+    }
+
+    // Returns true if an interrupt became pending.
+    pub fn poll(&mut self) -> bool {
+        if (self.control & CONTROL_ENABLE) == 0 || self.interval == 0 || self.pending {
+            return false;
+        }
+
+        let now = self.ticks();
+        if now < self.deadline {
+            return false;
+        }
+
+        self.deadline = now.saturating_add(self.interval);
+        self.pending = true;
+        return true;
+    }
+
+    pub fn take_pending(&mut self) -> Option<i32> {
+        if !self.pending {
+            return None;
+        }
+
+        self.pending = false;
+        return Some(self.vector);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::registers::X86Register;
+
+// Execution trace of the instructions executed while tracing is enabled. Each entry holds
+// the address of an instruction and the general purpose registers that changed since the
+// previous entry, i.e. the effects of the previous instruction. The first entry lists all
+// registers.
+//
+// Text format (TRACE_FORMAT_TEXT), one line per instruction:
+//
+//   <icount> <pc as 0x%016x>[ <register>=<value as 0x%x>]*
+//
+// e.g. "42 0x0000000000401000 rax=0x1 rsp=0x7ffffffde000". Register names are lowercase.
+//
+// Binary format (TRACE_FORMAT_BINARY, all integers little-endian):
+//
+//   magic      4 bytes  "ICTR"
+//   version    u32      TRACE_VERSION
+//   registers  u32      number of entries in TRACE_REGISTERS
+//   entries    repeated until the end of the file:
+//                icount   u64
+//                pc       u64
+//                changes  u8
+//                repeated `changes` times:
+//                  index  u8   index into TRACE_REGISTERS
+//                  value  u64
+
+pub const TRACE_FORMAT_TEXT: i32 = 0;
+pub const TRACE_FORMAT_BINARY: i32 = 1;
+
+const TRACE_MAGIC: &[u8; 4] = b"ICTR";
+const TRACE_VERSION: u32 = 1;
+
+pub const TRACE_REGISTERS: [(X86Register, &str); 16] = [
+    (X86Register::Rax, "rax"),
+    (X86Register::Rbx, "rbx"),
+    (X86Register::Rcx, "rcx"),
+    (X86Register::Rdx, "rdx"),
+    (X86Register::Rsi, "rsi"),
+    (X86Register::Rdi, "rdi"),
+    (X86Register::Rbp, "rbp"),
+    (X86Register::Rsp, "rsp"),
+    (X86Register::R8, "r8"),
+    (X86Register::R9, "r9"),
+    (X86Register::R10, "r10"),
+    (X86Register::R11, "r11"),
+    (X86Register::R12, "r12"),
+    (X86Register::R13, "r13"),
+    (X86Register::R14, "r14"),
+    (X86Register::R15, "r15"),
+];
+
+struct TraceEntry {
+    icount: u64,
+    pc: u64,
+    changes: Vec<(u8, u64)>,
+}
+
+pub struct TraceRecorder {
+    entries: Vec<TraceEntry>,
+    max_entries: usize,
+    last_values: Option<[u64; TRACE_REGISTERS.len()]>,
+}
+
+impl TraceRecorder {
+    // Recording stops silently once `max_entries` entries were captured, 0 means no limit.
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            max_entries,
+            last_values: None,
+        }
+    }
+
+    pub fn record(&mut self, icount: u64, pc: u64, values: [u64; TRACE_REGISTERS.len()]) {
+        if self.max_entries != 0 && self.entries.len() >= self.max_entries {
+            return;
+        }
+
+        let changes = values
+            .iter()
+            .enumerate()
+            .filter(|(index, value)| match &self.last_values {
+                Some(last_values) => last_values[*index] != **value,
+                None => true,
+            })
+            .map(|(index, value)| (index as u8, *value))
+            .collect();
+
+        self.last_values = Some(values);
+        self.entries.push(TraceEntry {
+            icount,
+            pc,
+            changes,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    pub fn export(&self, format: i32) -> Option<Vec<u8>> {
+        return match format {
+            TRACE_FORMAT_TEXT => Some(self.export_text()),
+            TRACE_FORMAT_BINARY => Some(self.export_binary()),
+            _ => None,
+        };
+    }
+
+    fn export_text(&self) -> Vec<u8> {
+        use std::fmt::Write;
+
+        let mut output = String::new();
+
+        for entry in &self.entries {
+            let _ = write!(output, "{} {:#018x}", entry.icount, entry.pc);
+
+            for (index, value) in &entry.changes {
+                let _ = write!(
+                    output,
+                    " {}={:#x}",
+                    TRACE_REGISTERS[*index as usize].1, value
+                );
+            }
+
+            output.push('\n');
+        }
+
+        return output.into_bytes();
+    }
+
+    fn export_binary(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        output.extend_from_slice(TRACE_MAGIC);
+        output.extend_from_slice(&TRACE_VERSION.to_le_bytes());
+        output.extend_from_slice(&(TRACE_REGISTERS.len() as u32).to_le_bytes());
+
+        for entry in &self.entries {
+            output.extend_from_slice(&entry.icount.to_le_bytes());
+            output.extend_from_slice(&entry.pc.to_le_bytes());
+            output.push(entry.changes.len() as u8);
+
+            for (index, value) in &entry.changes {
+                output.push(*index);
+                output.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        return output;
+    }
+}
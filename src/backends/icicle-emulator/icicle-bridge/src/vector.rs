@@ -0,0 +1,84 @@
+use crate::registers::X86Register;
+
+// Layout of the buffer exchanged by icicle_get_vector_state/icicle_set_vector_state:
+//
+//   0x000  ZMM0..ZMM31  64 bytes each, XMMn/YMMn are the low 16/32 bytes of ZMMn
+//   0x800  K0..K7       8 bytes each
+//
+// Registers the target does not provide read back as zero and are ignored on write.
+
+pub const ZMM_SIZE: usize = 64;
+pub const ZMM_COUNT: usize = 32;
+
+pub const K_OFFSET: usize = ZMM_SIZE * ZMM_COUNT;
+pub const K_SIZE: usize = 8;
+pub const K_COUNT: usize = 8;
+
+pub const VECTOR_STATE_SIZE: usize = K_OFFSET + K_SIZE * K_COUNT;
+
+pub const YMM_SIZE: usize = 32;
+
+pub const YMM_REGISTERS: [X86Register; 16] = [
+    X86Register::Ymm0,
+    X86Register::Ymm1,
+    X86Register::Ymm2,
+    X86Register::Ymm3,
+    X86Register::Ymm4,
+    X86Register::Ymm5,
+    X86Register::Ymm6,
+    X86Register::Ymm7,
+    X86Register::Ymm8,
+    X86Register::Ymm9,
+    X86Register::Ymm10,
+    X86Register::Ymm11,
+    X86Register::Ymm12,
+    X86Register::Ymm13,
+    X86Register::Ymm14,
+    X86Register::Ymm15,
+];
+
+pub const ZMM_REGISTERS: [X86Register; ZMM_COUNT] = [
+    X86Register::Zmm0,
+    X86Register::Zmm1,
+    X86Register::Zmm2,
+    X86Register::Zmm3,
+    X86Register::Zmm4,
+    X86Register::Zmm5,
+    X86Register::Zmm6,
+    X86Register::Zmm7,
+    X86Register::Zmm8,
+    X86Register::Zmm9,
+    X86Register::Zmm10,
+    X86Register::Zmm11,
+    X86Register::Zmm12,
+    X86Register::Zmm13,
+    X86Register::Zmm14,
+    X86Register::Zmm15,
+    X86Register::Zmm16,
+    X86Register::Zmm17,
+    X86Register::Zmm18,
+    X86Register::Zmm19,
+    X86Register::Zmm20,
+    X86Register::Zmm21,
+    X86Register::Zmm22,
+    X86Register::Zmm23,
+    X86Register::Zmm24,
+    X86Register::Zmm25,
+    X86Register::Zmm26,
+    X86Register::Zmm27,
+    X86Register::Zmm28,
+    X86Register::Zmm29,
+    X86Register::Zmm30,
+    X86Register::Zmm31,
+];
+
+pub const K_REGISTERS: [X86Register; K_COUNT] = [
+    X86Register::K0,
+    X86Register::K1,
+    X86Register::K2,
+    X86Register::K3,
+    X86Register::K4,
+    X86Register::K5,
+    X86Register::K6,
+    X86Register::K7,
+];
@@ -0,0 +1,78 @@
+// Value conditions for write watchpoints. The watched value is tracked from guest writes,
+// so host writes to the location are only taken into account by the next guest write.
+
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum WatchCondition {
+    Equal = 0,
+    Changed,
+    Greater,
+}
+
+impl WatchCondition {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(WatchCondition::Equal),
+            1 => Some(WatchCondition::Changed),
+            2 => Some(WatchCondition::Greater),
+            _ => None,
+        }
+    }
+}
+
+pub struct Watchpoint {
+    address: u64,
+    size: usize,
+    condition: WatchCondition,
+    value: u64,
+    current: [u8; 8],
+}
+
+impl Watchpoint {
+    pub fn new(
+        address: u64,
+        size: usize,
+        condition: WatchCondition,
+        value: u64,
+        current: [u8; 8],
+    ) -> Self {
+        Self {
+            address,
+            size,
+            condition,
+            value,
+            current,
+        }
+    }
+
+    pub fn end(&self) -> u64 {
+        return self.address + self.size as u64;
+    }
+
+    // Merges a write that overlaps the watched location and returns the old and new value
+    // if the result satisfies the condition. Greater compares unsigned.
+    pub fn on_write(&mut self, address: u64, data: &[u8]) -> Option<(u64, u64)> {
+        let old = u64::from_le_bytes(self.current);
+
+        for (index, byte) in data.iter().enumerate() {
+            let offset = (address + index as u64).wrapping_sub(self.address);
+            if offset < self.size as u64 {
+                self.current[offset as usize] = *byte;
+            }
+        }
+
+        let new = u64::from_le_bytes(self.current);
+
+        let matched = match self.condition {
+            WatchCondition::Equal => new == self.value,
+            WatchCondition::Changed => new != old,
+            WatchCondition::Greater => new > self.value,
+        };
+
+        if !matched {
+            return None;
+        }
+
+        return Some((old, new));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::registers::X86Register;
+
+// FXSAVE (64-bit format) and standard format XSAVE areas, Intel SDM Vol. 1, 10.5 and 13.4:
+//
+//   0x000  FCW u16, FSW u16, abridged FTW u8, reserved u8, FOP u16
+//   0x008  FIP u64
+//   0x010  FDP u64
+//   0x018  MXCSR u32, MXCSR_MASK u32
+//   0x020  ST0..ST7     16 bytes each, 10 used
+//   0x0A0  XMM0..XMM15  16 bytes each
+//   0x200  XSAVE header XSTATE_BV u64, XCOMP_BV u64, 48 reserved bytes
+//   0x240  AVX          upper 128 bits of YMM0..YMM15
+//
+// Only the x87, SSE and AVX components are supported. The compacted format is rejected.
+
+pub const FXSAVE_SIZE: usize = 512;
+pub const FXSAVE_ALIGNMENT: u64 = 16;
+pub const XSAVE_ALIGNMENT: u64 = 64;
+
+pub const HEADER_OFFSET: usize = 0x200;
+pub const AVX_OFFSET: usize = 0x240;
+pub const XSAVE_SIZE: usize = AVX_OFFSET + 16 * 16;
+
+pub const COMPONENT_X87: u64 = 1 << 0;
+pub const COMPONENT_SSE: u64 = 1 << 1;
+pub const COMPONENT_AVX: u64 = 1 << 2;
+pub const SUPPORTED_COMPONENTS: u64 = COMPONENT_X87 | COMPONENT_SSE | COMPONENT_AVX;
+
+pub const FCW_OFFSET: usize = 0x00;
+pub const FSW_OFFSET: usize = 0x02;
+pub const FTW_OFFSET: usize = 0x04;
+pub const FOP_OFFSET: usize = 0x06;
+pub const FIP_OFFSET: usize = 0x08;
+pub const FDP_OFFSET: usize = 0x10;
+pub const MXCSR_OFFSET: usize = 0x18;
+pub const MXCSR_MASK_OFFSET: usize = 0x1C;
+pub const ST_OFFSET: usize = 0x20;
+pub const XMM_OFFSET: usize = 0xA0;
+
+pub const ST_SIZE: usize = 10;
+pub const SLOT_SIZE: usize = 16;
+pub const MXCSR_MASK: u32 = 0xFFFF;
+
+// Initial values loaded by XRSTOR for components whose XSTATE_BV bit is clear.
+pub const INIT_FCW: u16 = 0x037F;
+pub const INIT_FTW: u16 = 0xFFFF;
+
+pub const ST_REGISTERS: [X86Register; 8] = [
+    X86Register::St0,
+    X86Register::St1,
+    X86Register::St2,
+    X86Register::St3,
+    X86Register::St4,
+    X86Register::St5,
+    X86Register::St6,
+    X86Register::St7,
+];
+
+pub const XMM_REGISTERS: [X86Register; 16] = [
+    X86Register::Xmm0,
+    X86Register::Xmm1,
+    X86Register::Xmm2,
+    X86Register::Xmm3,
+    X86Register::Xmm4,
+    X86Register::Xmm5,
+    X86Register::Xmm6,
+    X86Register::Xmm7,
+    X86Register::Xmm8,
+    X86Register::Xmm9,
+    X86Register::Xmm10,
+    X86Register::Xmm11,
+    X86Register::Xmm12,
+    X86Register::Xmm13,
+    X86Register::Xmm14,
+    X86Register::Xmm15,
+];
+
+const TAG_VALID: u16 = 0;
+const TAG_ZERO: u16 = 1;
+const TAG_SPECIAL: u16 = 2;
+const TAG_EMPTY: u16 = 3;
+
+// One bit per physical register, set when the register is not empty.
+pub fn abridge_tag(tag: u16) -> u8 {
+    let mut abridged = 0u8;
+
+    for index in 0..8 {
+        if ((tag >> (index * 2)) & 3) != TAG_EMPTY {
+            abridged |= 1 << index;
+        }
+    }
+
+    return abridged;
+}
+
+fn classify_register(value: &[u8]) -> u16 {
+    let mantissa = u64::from_le_bytes(value[..8].try_into().unwrap());
+    let exponent = u16::from_le_bytes(value[8..10].try_into().unwrap()) & 0x7FFF;
+
+    if exponent == 0 && mantissa == 0 {
+        return TAG_ZERO;
+    }
+
+    // NaNs, infinities, denormals and unnormals (integer bit clear).
+    if exponent == 0x7FFF || exponent == 0 || (mantissa >> 63) == 0 {
+        return TAG_SPECIAL;
+    }
+
+    return TAG_VALID;
+}
+
+// Rebuilds the full tag word. `stack` holds ST0..ST7, physical register i is ST(i - top).
+pub fn expand_tag(abridged: u8, top: u8, stack: &[[u8; ST_SIZE]; 8]) -> u16 {
+    let mut tag = 0u16;
+
+    for index in 0..8u8 {
+        let value = if (abridged & (1 << index)) == 0 {
+            TAG_EMPTY
+        } else {
+            classify_register(&stack[(index.wrapping_sub(top) & 7) as usize])
+        };
+
+        tag |= value << (index * 2);
+    }
+
+    return tag;
+}
+
+pub fn read_u16(area: &[u8], offset: usize) -> u16 {
+    return u16::from_le_bytes(area[offset..offset + 2].try_into().unwrap());
+}
+
+pub fn read_u32(area: &[u8], offset: usize) -> u32 {
+    return u32::from_le_bytes(area[offset..offset + 4].try_into().unwrap());
+}
+
+pub fn read_u64(area: &[u8], offset: usize) -> u64 {
+    return u64::from_le_bytes(area[offset..offset + 8].try_into().unwrap());
+}
@@ -7,16 +7,16 @@ using icicle_emulator = struct icicle_emulator_;
 
 extern "C"
 {
-    using icicle_mmio_read_func = void(void* user, uint64_t address, void* data, size_t length);
-    using icicle_mmio_write_func = void(void* user, uint64_t address, const void* data, size_t length);
+    using icicle_mmio_read_func = int32_t(void* user, uint64_t address, void* data, size_t length);
+    using icicle_mmio_write_func = int32_t(void* user, uint64_t address, const void* data, size_t length);
 
-    using raw_func = void(void*);
+    using syscall_func = int32_t(void*);
     using ptr_func = void(void*, uint64_t);
     using block_func = void(void*, uint64_t, uint64_t);
     using interrupt_func = void(void*, int32_t);
     using violation_func = int32_t(void*, uint64_t address, uint8_t operation, int32_t unmapped);
     using data_accessor_func = void(void* user, const void* data, size_t length);
-    using memory_access_func = icicle_mmio_write_func;
+    using memory_access_func = void(void* user, uint64_t pc, uint64_t address, const void* data, size_t length);
 
     icicle_emulator* icicle_create_emulator();
     int32_t icicle_protect_memory(icicle_emulator*, uint64_t address, uint64_t length, uint8_t permissions);
@@ -27,10 +27,10 @@ extern "C"
     int32_t icicle_read_memory(icicle_emulator*, uint64_t address, void* data, size_t length);
     int32_t icicle_write_memory(icicle_emulator*, uint64_t address, const void* data, size_t length);
     void icicle_save_registers(icicle_emulator*, data_accessor_func* accessor, void* accessor_data);
-    void icicle_restore_registers(icicle_emulator*, const void* data, size_t length);
+    int32_t icicle_restore_registers(icicle_emulator*, const void* data, size_t length);
     uint32_t icicle_create_snapshot(icicle_emulator*);
-    void icicle_restore_snapshot(icicle_emulator*, uint32_t id);
-    uint32_t icicle_add_syscall_hook(icicle_emulator*, raw_func* callback, void* data);
+    int32_t icicle_restore_snapshot(icicle_emulator*, uint32_t id);
+    uint32_t icicle_add_syscall_hook(icicle_emulator*, syscall_func* callback, void* data);
     uint32_t icicle_add_interrupt_hook(icicle_emulator*, interrupt_func* callback, void* data);
     uint32_t icicle_add_block_hook(icicle_emulator*, block_func* callback, void* data);
     uint32_t icicle_add_execution_hook(icicle_emulator*, uint64_t address, ptr_func* callback, void* data);
@@ -41,7 +41,7 @@ extern "C"
     void icicle_remove_hook(icicle_emulator*, uint32_t id);
     size_t icicle_read_register(icicle_emulator*, int reg, void* data, size_t length);
     size_t icicle_write_register(icicle_emulator*, int reg, const void* data, size_t length);
-    void icicle_start(icicle_emulator*, size_t count);
+    int32_t icicle_start(icicle_emulator*, size_t count);
     void icicle_stop(icicle_emulator*);
     void icicle_destroy_emulator(icicle_emulator*);
 }
@@ -183,11 +183,13 @@ namespace icicle
             auto* read_wrapper = +[](void* user, const uint64_t addr, void* data, const size_t length) {
                 const auto* w = static_cast<mmio_wrapper*>(user);
                 w->read_cb(addr - w->base, data, length);
+                return 0;
             };
 
             auto* write_wrapper = +[](void* user, const uint64_t addr, const void* data, const size_t length) {
                 const auto* w = static_cast<mmio_wrapper*>(user);
                 w->write_cb(addr + w->base, data, length);
+                return 0;
             };
 
             icicle_map_mmio(this->emu_, address, size, read_wrapper, ptr, write_wrapper, ptr);
@@ -239,9 +241,10 @@ namespace icicle
             auto obj = make_function_object(std::move(callback));
             auto* ptr = obj.get();
 
-            const auto invoker = +[](void* cb) {
+            const auto invoker = +[](void* cb) -> int32_t {
                 const auto& func = *static_cast<decltype(ptr)>(cb);
                 (void)func(); //
+                return 0;
             };
 
             const auto id = icicle_add_syscall_hook(this->emu_, invoker, ptr);
@@ -340,7 +343,7 @@ namespace icicle
         {
             auto obj = make_function_object(std::move(callback));
             auto* ptr = obj.get();
-            auto* wrapper = +[](void* user, const uint64_t address, const void* data, size_t length) {
+            auto* wrapper = +[](void* user, uint64_t /*pc*/, const uint64_t address, const void* data, size_t length) {
                 const auto& func = *static_cast<decltype(ptr)>(user);
                 func(address, data, length);
             };
@@ -356,7 +359,7 @@ namespace icicle
         {
             auto obj = make_function_object(std::move(callback));
             auto* ptr = obj.get();
-            auto* wrapper = +[](void* user, const uint64_t address, const void* data, size_t length) {
+            auto* wrapper = +[](void* user, uint64_t /*pc*/, const uint64_t address, const void* data, size_t length) {
                 const auto& func = *static_cast<decltype(ptr)>(user);
                 func(address, data, length);
             };
@@ -398,7 +401,7 @@ namespace icicle
             if (is_snapshot)
             {
                 const auto snapshot = buffer.read<uint32_t>();
-                icicle_restore_snapshot(this->emu_, snapshot);
+                ice(icicle_restore_snapshot(this->emu_, snapshot), "Failed to restore snapshot");
             }
             else
             {
@@ -423,7 +426,8 @@ namespace icicle
 
         void restore_registers(const std::vector<std::byte>& register_data) override
         {
-            icicle_restore_registers(this->emu_, register_data.data(), register_data.size());
+            ice(icicle_restore_registers(this->emu_, register_data.data(), register_data.size()),
+                "Failed to restore registers");
         }
 
         bool has_violation() const override